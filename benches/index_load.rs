@@ -0,0 +1,62 @@
+//! Compares loading a large index whole with streaming its notes.
+//!
+//! Run with `cargo bench --bench index_load`.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use scrivener::config::Config;
+use scrivener::notes::Index;
+
+/// The number of notes in the benchmarked index
+const NOTES: usize = 50_000;
+
+/// The number of times each way of reading is timed
+const RUNS: u32 = 5;
+
+fn main() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("note.txt");
+    fs::write(&file, "words").unwrap();
+
+    let mut index = Index::default();
+    for i in 0..NOTES {
+        let tags = Some(vec![format!("tag{}", i % 10)]);
+        index.add(&format!("Note {}", i), &file, &tags).unwrap();
+    }
+
+    for extension in &["toml", "json"] {
+        let path = dir.path().join(format!("scrivener.{}", extension));
+        index.store_path(&path, &Config::default()).unwrap();
+
+        let loaded = time(|| Index::load_path(&path).unwrap().names().count());
+        let streamed = time(|| count_streamed(&path));
+
+        println!(
+            "{}: load_path {:?}, stream_path {:?} ({} notes)",
+            extension, loaded, streamed, NOTES
+        );
+    }
+}
+
+/// Counts the notes at `path` without loading them into an `Index`.
+fn count_streamed(path: &Path) -> usize {
+    let mut count = 0;
+    Index::stream_path(path, |_| {
+        count += 1;
+        Ok(())
+    })
+    .unwrap();
+    count
+}
+
+/// Returns the average time `read` takes over `RUNS` runs, checking
+/// that it sees every note.
+fn time<F: FnMut() -> usize>(mut read: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert_eq!(read(), NOTES);
+    }
+    start.elapsed() / RUNS
+}
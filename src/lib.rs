@@ -0,0 +1,7 @@
+//! A Linux command line utility for taking notes.
+//!
+//! The `notes` module exposes the `Note` and `Index` types so that
+//! other programs can query and manipulate a scrivener index.
+
+mod scrivener;
+pub use crate::scrivener::{args, config, notes};
@@ -1,7 +1,6 @@
 use exitfailure::ExitFailure;
 use structopt::StructOpt;
 
-mod scrivener;
 use scrivener::args::Args;
 
 fn main() -> Result<(), ExitFailure> {
@@ -9,7 +8,10 @@ fn main() -> Result<(), ExitFailure> {
 
     let args = Args::from_args();
 
-    args.execute(PROGRAM_NAME)?;
+    let code = args.execute(PROGRAM_NAME)?;
+    if code != 0 {
+        std::process::exit(code);
+    }
 
     Ok(())
 }
@@ -0,0 +1,130 @@
+//! Safety copies of note files, taken before they are edited.
+
+use failure::{Error, ResultExt};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::scrivener::config::Config;
+
+/// Copies the file at `path` into the `backup_dir` setting, or next to
+/// the file if it is empty, and returns the path of the copy.
+///
+/// Inside `backup_dir`, copies go under the file's own directory path,
+/// so `/home/me/notes/todo.txt` is copied to
+/// `<backup_dir>/home/me/notes/todo.txt.bak` and files with the same
+/// name in different directories never share copies.
+///
+/// The newest copy is named after the file with `.bak` added. If the
+/// `backup_count` setting keeps more than one, older copies are moved
+/// along to `.1.bak`, `.2.bak`, and so on, and the oldest copy past the
+/// count is replaced.
+///
+/// # Errors
+///
+/// - The backup directory cannot be created.
+/// - The file cannot be copied, or older copies cannot be moved.
+pub(super) fn backup(path: &Path, config: &Config) -> Result<PathBuf, Error> {
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let dir = if config.backup_dir.as_os_str().is_empty() {
+        parent.to_path_buf()
+    } else {
+        let dir = config.backup_dir.join(mirrored(parent));
+        fs::create_dir_all(&dir)
+            .with_context(|_| format!("Could not create {}.", dir.display()))?;
+        dir
+    };
+
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => failure::bail!("{} is not a file.", path.display()),
+    };
+    let slot = |number: usize| match number {
+        0 => dir.join(format!("{}.bak", file_name)),
+        number => dir.join(format!("{}.{}.bak", file_name, number)),
+    };
+
+    for number in (1..config.backup_count.max(1)).rev() {
+        let older = slot(number - 1);
+        if older.exists() {
+            fs::rename(&older, slot(number))
+                .with_context(|_| format!("Could not move backup {}.", older.display()))?;
+        }
+    }
+
+    let newest = slot(0);
+    fs::copy(path, &newest).with_context(|_| {
+        format!(
+            "Could not back up {} to {}.",
+            path.display(),
+            newest.display()
+        )
+    })?;
+
+    Ok(newest)
+}
+
+/// Returns the absolute path of `dir` without its root, so that it can
+/// be joined onto the backup directory.
+fn mirrored(dir: &Path) -> PathBuf {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn rotates_backups() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        let config = Config {
+            backup_dir: dir.path().join("backups"),
+            backup_count: 2,
+            ..Config::default()
+        };
+
+        for content in &["one", "two", "three"] {
+            fs::write(&path, content).unwrap();
+            backup(&path, &config).unwrap();
+        }
+
+        let backups = dir.path().join("backups").join(mirrored(dir.path()));
+        assert_eq!(
+            fs::read_to_string(backups.join("note.txt.bak")).unwrap(),
+            "three"
+        );
+        assert_eq!(
+            fs::read_to_string(backups.join("note.txt.1.bak")).unwrap(),
+            "two"
+        );
+        assert!(!backups.join("note.txt.2.bak").exists());
+    }
+
+    #[test]
+    fn same_names_do_not_share_backups() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            backup_dir: dir.path().join("backups"),
+            backup_count: 1,
+            ..Config::default()
+        };
+
+        let mut copies = Vec::new();
+        for folder in &["work", "home"] {
+            let path = dir.path().join(folder).join("todo.txt");
+            fs::create_dir(path.parent().unwrap()).unwrap();
+            fs::write(&path, folder).unwrap();
+            copies.push(backup(&path, &config).unwrap());
+        }
+
+        assert_ne!(copies[0], copies[1]);
+        assert_eq!(fs::read_to_string(&copies[0]).unwrap(), "work");
+        assert_eq!(fs::read_to_string(&copies[1]).unwrap(), "home");
+        assert!(copies[0].starts_with(&config.backup_dir));
+    }
+}
@@ -0,0 +1,128 @@
+//! Shell completion scripts.
+
+use failure::{Error, ResultExt};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use structopt::clap::Shell;
+use structopt::StructOpt;
+
+use crate::scrivener::args::Args;
+
+/// The name of the program that completions are written for
+const BIN_NAME: &str = "scrv";
+
+/// Writes the completion script for `shell` to `out`.
+pub(super) fn write(shell: Shell, out: &mut impl Write) {
+    Args::clap().gen_completions_to(BIN_NAME, shell, out);
+}
+
+/// Returns where `shell` looks for completion scripts by default,
+/// given the user's data and configuration directories, such as
+/// ~/.local/share and ~/.config on Linux.
+///
+/// zsh only looks in directories on its `fpath`, so scripts for it go
+/// where other programs commonly install them.
+///
+/// # Errors
+///
+/// - `shell` has no such place.
+pub(super) fn install_path(
+    shell: Shell,
+    data_dir: &Path,
+    config_dir: &Path,
+) -> Result<PathBuf, Error> {
+    match shell {
+        Shell::Bash => Ok(data_dir.join("bash-completion/completions").join(BIN_NAME)),
+        Shell::Fish => Ok(config_dir
+            .join("fish/completions")
+            .join(format!("{}.fish", BIN_NAME))),
+        Shell::Zsh => Ok(data_dir
+            .join("zsh/site-functions")
+            .join(format!("_{}", BIN_NAME))),
+        shell => {
+            let name = shell.to_string().to_lowercase();
+            failure::bail!(
+                "Installing completions for {} is not supported. Run `{} completions {}` and \
+                 save the output where {} loads completions from.",
+                name,
+                BIN_NAME,
+                name,
+                name
+            )
+        }
+    }
+}
+
+/// Writes the completion script for `shell` to `install_path`,
+/// creating its directory if needed, and returns where it was written.
+///
+/// # Errors
+///
+/// - `shell` is not supported. See `install_path`.
+/// - The directory or the file cannot be written.
+pub(super) fn install(shell: Shell, data_dir: &Path, config_dir: &Path) -> Result<PathBuf, Error> {
+    let path = install_path(shell, data_dir, config_dir)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|_| format!("Could not create {}.", dir.display()))?;
+    }
+
+    let mut script = Vec::new();
+    write(shell, &mut script);
+    fs::write(&path, script).with_context(|_| format!("Could not write to {}.", path.display()))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn scripts_for_every_shell() {
+        for shell in &Shell::variants() {
+            let shell: Shell = shell.parse().unwrap();
+            let mut script = Vec::new();
+            write(shell, &mut script);
+
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains(BIN_NAME), "{}", shell);
+            assert!(script.contains("prune"), "{}", shell);
+        }
+    }
+
+    #[test]
+    fn shell_names_are_checked() {
+        let parse = |shell| Args::from_iter_safe(&["scrv", "completions", shell]);
+
+        assert!(parse("ZSH").is_ok());
+        assert!(parse("powershell").is_ok());
+        assert!(parse("cmd").is_err());
+    }
+
+    #[test]
+    fn installs_under_home() {
+        let home = TempDir::new().unwrap();
+        let data_dir = home.path().join(".local/share");
+        let config_dir = home.path().join(".config");
+
+        let path = install(Shell::Fish, &data_dir, &config_dir).unwrap();
+        assert_eq!(path, config_dir.join("fish/completions/scrv.fish"));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("complete -c scrv"));
+
+        let path = install(Shell::Bash, &data_dir, &config_dir).unwrap();
+        assert_eq!(path, data_dir.join("bash-completion/completions/scrv"));
+        assert!(path.is_file());
+
+        let path = install(Shell::Zsh, &data_dir, &config_dir).unwrap();
+        assert_eq!(path, data_dir.join("zsh/site-functions/_scrv"));
+
+        let error = install(Shell::PowerShell, &data_dir, &config_dir).unwrap_err();
+        assert!(error.to_string().contains("not supported"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Opening files in the user's text editor.
+
+use failure::{Error, ResultExt};
+use std::env;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::errors;
+use crate::scrivener::config::Config;
+
+/// Returns the command for the user's default text editor.
+///
+/// For Linux, this is the value of $VISUAL, or $EDITOR if $VISUAL is
+/// not set. If neither is set, vi is used instead.
+pub(super) fn default_editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"))
+}
+
+/// Returns `editor` if it was given, or else the `editor` setting if it
+/// is set, or else the default editor.
+pub(super) fn choose(editor: &Option<String>, config: &Config) -> String {
+    editor
+        .clone()
+        .or_else(|| config.editor().map(String::from))
+        .unwrap_or_else(default_editor)
+}
+
+/// Opens a temporary file containing `contents` in `editor` and
+/// returns what the user wrote once the editor exits.
+pub(super) fn capture(editor: &str, contents: &str) -> Result<String, Error> {
+    let text = scrawl::editor::new()
+        .editor(editor)
+        .contents(contents)
+        .open()
+        .with_context(|_| could_not_launch(editor))?;
+    Ok(text)
+}
+
+/// Opens the file at `path` in `editor`.
+///
+/// If `wait` is true, this returns once the editor exits. Otherwise,
+/// the editor is started in the background and this returns right
+/// away, which suits graphical editors. `editor` may include arguments,
+/// such as `code --new-window`, separated by whitespace.
+pub(super) fn open(editor: &str, path: &Path, wait: bool) -> Result<(), Error> {
+    if wait {
+        scrawl::editor::new()
+            .editor(editor)
+            .file(path)
+            .edit()
+            .open()
+            .with_context(|_| could_not_launch(editor))?;
+    } else {
+        let mut words = editor.split_whitespace();
+        let program = match words.next() {
+            Some(program) => program,
+            None => failure::bail!(could_not_launch(editor)),
+        };
+
+        Command::new(program)
+            .args(words)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|_| could_not_launch(editor))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the message for when `editor` cannot be launched.
+fn could_not_launch(editor: &str) -> String {
+    errors::could_not(&format!("launch editor `{}`", editor))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::{
+        fs,
+        os::unix::fs::PermissionsExt,
+        time::{Duration, Instant},
+    };
+    #[cfg(unix)]
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn open_without_waiting() {
+        let dir = TempDir::new().unwrap();
+        let editor = dir.path().join("slow-editor");
+        let path = dir.path().join("note.txt");
+        fs::write(&editor, "#!/bin/sh\nsleep 5\n").unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let start = Instant::now();
+        open(editor.to_str().unwrap(), &path, false).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_without_waiting_passes_arguments() {
+        let dir = TempDir::new().unwrap();
+        let editor = dir.path().join("editor");
+        let path = dir.path().join("note.txt");
+        fs::write(&editor, "#!/bin/sh\necho \"$@\" > \"$2.args\"\n").unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let command = format!("{} --new-window", editor.display());
+        open(&command, &path, false).unwrap();
+
+        // The editor runs in the background, so wait for what it writes.
+        let args = dir.path().join("note.txt.args");
+        let start = Instant::now();
+        let mut written = String::new();
+        while !written.ends_with('\n') && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            written = fs::read_to_string(&args).unwrap_or_default();
+        }
+        assert_eq!(written, format!("--new-window {}\n", path.display()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn capture_keeps_contents() {
+        let dir = TempDir::new().unwrap();
+        let editor = dir.path().join("editor");
+        fs::write(&editor, "#!/bin/sh\necho more >> \"$1\"\n").unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let text = capture(editor.to_str().unwrap(), "# Title\n").unwrap();
+        assert_eq!(text, "# Title\nmore\n");
+    }
+
+    #[test]
+    fn given_editor_comes_first() {
+        let mut config = Config::default();
+        let gedit = Some("gedit".to_string());
+        assert_eq!(choose(&gedit, &config), "gedit");
+        assert_eq!(choose(&None, &config), default_editor());
+
+        config.editor = "nvim".to_string();
+        assert_eq!(choose(&gedit, &config), "gedit");
+        assert_eq!(choose(&None, &config), "nvim");
+    }
+
+    #[test]
+    fn open_missing_editor() {
+        let path = Path::new("note.txt");
+
+        assert!(open("definitely-not-an-editor", path, false).is_err());
+        assert!(open("definitely-not-an-editor", path, true).is_err());
+        assert!(open(" ", path, false).is_err());
+
+        let error = capture("definitely-not-an-editor", "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            could_not_launch("definitely-not-an-editor")
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Writing note files with the configured line endings and byte order
+//! mark.
+
+use failure::{Error, ResultExt};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::scrivener::config::{Bom, Config, LineEndings};
+
+/// The UTF-8 byte order mark.
+const BOM: &str = "\u{feff}";
+
+/// Returns `text` as bytes with the line endings and byte order mark
+/// set in `config`, whatever it had before. Settings left at
+/// `preserve` keep what `text` has.
+pub(super) fn encode(text: &str, config: &Config) -> Vec<u8> {
+    let (had_bom, text) = match text.strip_prefix(BOM) {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let text = match config.line_endings {
+        LineEndings::Preserve => text.to_string(),
+        LineEndings::Lf => text.replace("\r\n", "\n"),
+        LineEndings::Crlf => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + BOM.len());
+    let bom = match config.bom {
+        Bom::Preserve => had_bom,
+        Bom::Always => true,
+        Bom::Never => false,
+    };
+    if bom {
+        bytes.extend(BOM.as_bytes());
+    }
+    bytes.extend(text.as_bytes());
+    bytes
+}
+
+/// Writes `text` to `out` as `encode` returns it.
+///
+/// # Errors
+///
+/// - `out` cannot be written to.
+pub(super) fn write(out: &mut impl Write, text: &str, config: &Config) -> Result<(), Error> {
+    out.write_all(&encode(text, config))
+        .with_context(|_| "Could not write to file.")?;
+    Ok(())
+}
+
+/// Rewrites the file at `path` with the line endings and byte order
+/// mark set in `config`, and returns `true` if it had to be changed.
+///
+/// Files that are not valid UTF-8 are left alone.
+///
+/// # Errors
+///
+/// - The file cannot be read or written.
+pub(super) fn rewrite(path: &Path, config: &Config) -> Result<bool, Error> {
+    let bytes = fs::read(path).with_context(|_| format!("Could not read {}.", path.display()))?;
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(text) => text,
+        Err(_) => return Ok(false),
+    };
+
+    let encoded = encode(text, config);
+    if encoded == bytes {
+        return Ok(false);
+    }
+
+    fs::write(path, encoded).with_context(|_| format!("Could not write {}.", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn encoded_bytes() {
+        let preserve = Config::default();
+        let lf = Config {
+            line_endings: LineEndings::Lf,
+            bom: Bom::Never,
+            ..Config::default()
+        };
+        let crlf = Config {
+            line_endings: LineEndings::Crlf,
+            ..Config::default()
+        };
+        let crlf_bom = Config {
+            bom: Bom::Always,
+            ..crlf.clone()
+        };
+
+        assert_eq!(
+            encode("\u{feff}a\nb\r\n", &preserve),
+            b"\xef\xbb\xbfa\nb\r\n"
+        );
+        assert_eq!(encode("a\nb\r\n", &lf), b"a\nb\n");
+        assert_eq!(encode("a\nb\r\n", &crlf), b"a\r\nb\r\n");
+        assert_eq!(encode("a\n", &crlf_bom), b"\xef\xbb\xbfa\r\n");
+        assert_eq!(encode("\u{feff}a\r\n", &lf), b"a\n");
+    }
+
+    #[test]
+    fn rewritten_files() {
+        let file = NamedTempFile::new().unwrap();
+        let crlf = Config {
+            line_endings: LineEndings::Crlf,
+            ..Config::default()
+        };
+
+        fs::write(file.path(), "a\nb\n").unwrap();
+        assert!(rewrite(file.path(), &crlf).unwrap());
+        assert_eq!(fs::read(file.path()).unwrap(), b"a\r\nb\r\n");
+        assert!(!rewrite(file.path(), &crlf).unwrap());
+
+        fs::write(file.path(), [0xff, b'\n']).unwrap();
+        assert!(!rewrite(file.path(), &crlf).unwrap());
+        assert_eq!(fs::read(file.path()).unwrap(), [0xff, b'\n']);
+
+        fs::write(file.path(), "\u{feff}a\r\n").unwrap();
+        assert!(!rewrite(file.path(), &Config::default()).unwrap());
+    }
+}
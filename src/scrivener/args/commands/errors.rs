@@ -1,10 +1,72 @@
 //! Formatting for consistent error messages.
 
-use std::path::PathBuf;
+use std::path::Path;
 
-/// Used in the event of a successfull operation.
-pub(super) fn successful(name: &str, action: &str) {
-    println!("Note `{}` has been {} successfully", name, action)
+use crate::scrivener::config::Config;
+
+/// Prints `message` after a command succeeds. Every success message
+/// goes through here.
+///
+/// The message goes to stderr so that it stays out of piped output,
+/// and nothing is printed if the `quiet` setting is on.
+pub(super) fn success(message: &str, config: &Config) {
+    if !config.quiet {
+        eprintln!("{}", message)
+    }
+}
+
+/// Used in the event of a successfull operation. See `success`.
+pub(super) fn successful(name: &str, action: &str, config: &Config) {
+    success(
+        &format!("Note `{}` has been {} successfully", name, action),
+        config,
+    )
+}
+
+/// Fills in the placeholders of a `success_message` template for the
+/// `Note` called `name`, whose file is at `path`, or `rel_path` from
+/// the current directory.
+///
+/// The template is read once from start to end, so placeholders that
+/// appear in the filled-in values are left as they are.
+pub(super) fn render_success(
+    template: &str,
+    name: &str,
+    action: &str,
+    path: &Path,
+    rel_path: &str,
+) -> String {
+    let path = path.display().to_string();
+    let placeholders = [
+        ("{name}", name),
+        ("{action}", action),
+        ("{rel_path}", rel_path),
+        ("{path}", path.as_str()),
+    ];
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match placeholders
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, value)) => {
+                rendered.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+
+    rendered
 }
 
 /// Used when an instance of `Note` with a given `name` already exists.
@@ -17,13 +79,22 @@ pub(super) fn does_not_exist(name: &str) -> String {
     format!("Note `{}` does not exist.", name)
 }
 
+/// Used when a `Note` is run but its file is not executable.
+pub(super) fn not_executable(name: &str, path: &Path) -> String {
+    format!(
+        "Note `{}` at {} is not executable. Mark it as executable to run it.",
+        name,
+        path.display()
+    )
+}
+
 /// Used for general cases when an action cannot be completed.
 pub(super) fn could_not(action: &str) -> String {
     format!("Could not {}.", action)
 }
 
 /// Used when a `Note`'s file cannot be operated on.
-pub(super) fn could_not_note(action: &str, name: &str, path: &PathBuf) -> String {
+pub(super) fn could_not_note(action: &str, name: &str, path: &Path) -> String {
     format!(
         "Could not {} note `{}` at {}.",
         action,
@@ -31,3 +102,48 @@ pub(super) fn could_not_note(action: &str, name: &str, path: &PathBuf) -> String
         path.display()
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_success_message() {
+        let path = Path::new("/notes/work/plan.md");
+
+        assert_eq!(
+            render_success(
+                "{action} {name}: {rel_path} ({path})",
+                "Plan",
+                "created",
+                path,
+                "./work/plan.md"
+            ),
+            "created Plan: ./work/plan.md (/notes/work/plan.md)"
+        );
+        assert_eq!(
+            render_success(
+                crate::scrivener::config::DEFAULT_SUCCESS_MESSAGE,
+                "Plan",
+                "added",
+                path,
+                "plan.md"
+            ),
+            "Note `Plan` at /notes/work/plan.md added successfully."
+        );
+    }
+
+    #[test]
+    fn placeholders_in_values_are_kept() {
+        assert_eq!(
+            render_success(
+                "{name} {{action}} at {path}",
+                "{path} {rel_path}",
+                "added",
+                Path::new("/notes/{name}.md"),
+                "{name}.md"
+            ),
+            "{path} {rel_path} {added} at /notes/{name}.md"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Exporting an `Index` to other formats.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::scrivener::notes::{Index, Note};
+
+/// Renders a Markdown page that links to every `Note` in `index`,
+/// with one section per tag and a final section for untagged notes.
+///
+/// Links are relative to `base`, the directory the page is written to.
+/// The output only depends on the contents of `index`, so rendering
+/// the same `Index` twice gives the same page.
+pub(super) fn index_page(index: &Index, base: &Path) -> String {
+    let mut page = String::from("# Notes\n");
+
+    for (tag, notes) in index.by_tag() {
+        push_section(&mut page, tag, &notes, base);
+    }
+
+    let untagged = index.find_by(|note| note.tags().is_none());
+    if !untagged.is_empty() {
+        push_section(&mut page, "Untagged", &untagged, base);
+    }
+
+    page
+}
+
+/// Appends a heading followed by a list of links to `notes`.
+fn push_section(page: &mut String, heading: &str, notes: &[&Note], base: &Path) {
+    page.push_str(&format!("\n## {}\n\n", heading));
+
+    for note in notes {
+        let link = relative_path(note.path(), base);
+        page.push_str(&format!("- [{}](<{}>)\n", note.name(), link.display()));
+    }
+}
+
+/// Returns `path` relative to the directory `base`.
+///
+/// Both paths are expected to be absolute. If they share no common
+/// ancestor, `path` is returned unchanged.
+pub(super) fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path_parts: Vec<Component> = path.components().collect();
+    let base_parts: Vec<Component> = base.components().collect();
+
+    let common = path_parts
+        .iter()
+        .zip(&base_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    if common == 0 {
+        return path.to_path_buf();
+    }
+
+    let mut rel = PathBuf::new();
+    for _ in common..base_parts.len() {
+        rel.push("..");
+    }
+    for part in &path_parts[common..] {
+        rel.push(part);
+    }
+    rel
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let base = Path::new("/home/user/notes");
+
+        let rel = |path| relative_path(Path::new(path), base);
+
+        assert_eq!(rel("/home/user/notes/a.txt"), Path::new("a.txt"));
+        assert_eq!(rel("/home/user/notes/sub/a.txt"), Path::new("sub/a.txt"));
+        assert_eq!(rel("/home/user/a.txt"), Path::new("../a.txt"));
+        assert_eq!(rel("/etc/a.txt"), Path::new("../../../etc/a.txt"));
+    }
+
+    #[test]
+    fn markdown_index_page() {
+        let dir = TempDir::new().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let file = |name: &str| {
+            let path = base.join(name);
+            fs::write(&path, "").unwrap();
+            path
+        };
+        let tags = |tags: &[&str]| Some(tags.iter().map(|t| t.to_string()).collect());
+
+        let mut index = Index::default();
+        index
+            .add("recipe", &file("recipe.txt"), &tags(&["food"]))
+            .unwrap();
+        index
+            .add("plan", &file("my plan.txt"), &tags(&["food", "work"]))
+            .unwrap();
+        index.add("diary", &file("diary.txt"), &None).unwrap();
+
+        let page = index_page(&index, &base);
+
+        assert_eq!(
+            page,
+            "# Notes\n\
+             \n## food\n\n- [plan](<my plan.txt>)\n- [recipe](<recipe.txt>)\n\
+             \n## work\n\n- [plan](<my plan.txt>)\n\
+             \n## Untagged\n\n- [diary](<diary.txt>)\n"
+        );
+        assert_eq!(index_page(&index, &base), page);
+    }
+}
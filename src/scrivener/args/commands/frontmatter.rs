@@ -0,0 +1,60 @@
+//! Separating frontmatter from the body of a note.
+
+/// The lines that can open and close a frontmatter block: `---` for
+/// YAML and `+++` for TOML.
+const DELIMITERS: [&str; 2] = ["---", "+++"];
+
+/// Splits `text` into its leading frontmatter block and the rest, or
+/// returns `None` if it does not start with one.
+///
+/// The block includes both delimiter lines, and must be closed with the
+/// same delimiter it was opened with.
+pub(super) fn split(text: &str) -> Option<(&str, &str)> {
+    let mut lines = text.split_inclusive('\n');
+    let first = lines.next()?;
+    let delimiter = trim_newline(first);
+    if !DELIMITERS.contains(&delimiter) {
+        return None;
+    }
+
+    let mut end = first.len();
+    for line in lines {
+        end += line.len();
+        if trim_newline(line) == delimiter {
+            return Some(text.split_at(end));
+        }
+    }
+
+    None
+}
+
+/// Returns `line` without its trailing `\n` or `\r\n`.
+fn trim_newline(line: &str) -> &str {
+    line.trim_end_matches('\n').trim_end_matches('\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitting() {
+        assert_eq!(
+            split("---\ntitle: A\n---\nBody\n"),
+            Some(("---\ntitle: A\n---\n", "Body\n"))
+        );
+        assert_eq!(
+            split("+++\ntitle = 'A'\n+++"),
+            Some(("+++\ntitle = 'A'\n+++", ""))
+        );
+        assert_eq!(
+            split("---\r\na: 1\r\n---\r\nBody"),
+            Some(("---\r\na: 1\r\n---\r\n", "Body"))
+        );
+
+        assert_eq!(split("Body\n---\n"), None);
+        assert_eq!(split("---\nnever closed\n"), None);
+        assert_eq!(split("---\na\n+++\n"), None);
+        assert_eq!(split(""), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Keeping exported notes in a git repository.
+
+use failure::{Error, ResultExt};
+use std::path::Path;
+use std::process::{Command, Output};
+
+use super::errors;
+
+/// The name and email used for commits if git has none set.
+const FALLBACK_IDENTITY: [&str; 4] = [
+    "-c",
+    "user.name=scrivener",
+    "-c",
+    "user.email=scrivener@localhost",
+];
+
+/// Creates a git repository in `dir` unless it already is one, and
+/// returns `true` if a repository was created.
+///
+/// # Errors
+///
+/// - git cannot be run or fails.
+pub(super) fn init(dir: &Path) -> Result<bool, Error> {
+    if dir.join(".git").exists() {
+        return Ok(false);
+    }
+
+    run(dir, &["init", "--quiet"])?;
+    Ok(true)
+}
+
+/// Stages every file in the repository at `dir` and commits it with
+/// `message`, and returns `false` if there was nothing to commit.
+///
+/// # Errors
+///
+/// - git cannot be run or fails.
+pub(super) fn commit_all(dir: &Path, message: &str) -> Result<bool, Error> {
+    run(dir, &["add", "--all"])?;
+
+    if run(dir, &["status", "--porcelain"])?.stdout.is_empty() {
+        return Ok(false);
+    }
+
+    let has_identity = run(dir, &["config", "user.email"]).is_ok();
+    let mut args = if has_identity {
+        Vec::new()
+    } else {
+        FALLBACK_IDENTITY.to_vec()
+    };
+    args.extend(&["commit", "--quiet", "--message", message]);
+
+    run(dir, &args)?;
+    Ok(true)
+}
+
+/// Runs git with `args` in `dir` and returns its output.
+///
+/// # Errors
+///
+/// - git cannot be run or exits unsuccessfully.
+fn run(dir: &Path, args: &[&str]) -> Result<Output, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|_| errors::could_not("run git"))?;
+
+    failure::ensure!(
+        output.status.success(),
+        "`git {}` failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn init_and_commit() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(init(dir.path()).unwrap());
+        assert!(!init(dir.path()).unwrap());
+        assert!(!commit_all(dir.path(), "Nothing").unwrap());
+
+        fs::write(dir.path().join("note.txt"), "words").unwrap();
+        assert!(commit_all(dir.path(), "First").unwrap());
+        assert!(!commit_all(dir.path(), "Again").unwrap());
+
+        let log = run(dir.path(), &["log", "--format=%s"]).unwrap();
+        assert_eq!(String::from_utf8(log.stdout).unwrap(), "First\n");
+    }
+}
@@ -0,0 +1,101 @@
+//! Finding and highlighting matches in text.
+
+use std::ops::Range;
+
+/// Starts bold yellow text in a terminal.
+const START: &str = "\u{1b}[1;33m";
+
+/// Resets text to the default style in a terminal.
+const END: &str = "\u{1b}[0m";
+
+/// Returns the byte range of the first place `query` appears in `text`,
+/// ignoring case, or `None` if it does not appear or is empty.
+pub(super) fn match_span(text: &str, query: &str) -> Option<Range<usize>> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return None;
+    }
+
+    text.char_indices().find_map(|(start, _)| {
+        let mut lowered = text[start..].char_indices().flat_map(|(offset, c)| {
+            c.to_lowercase()
+                .map(move |lower| (offset + c.len_utf8(), lower))
+        });
+
+        let mut end = start;
+        for &wanted in &query {
+            match lowered.next() {
+                Some((offset, lower)) if lower == wanted => end = start + offset,
+                _ => return None,
+            }
+        }
+
+        Some(start..end)
+    })
+}
+
+/// Scores how well `span` matches `text`: 3 if it is all of `text`, 2
+/// if it is at the start, and 1 otherwise.
+pub(super) fn relevance(text: &str, span: &Range<usize>) -> u32 {
+    if span.start == 0 && span.end == text.len() {
+        3
+    } else if span.start == 0 {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns `text` with the part in `span` highlighted, or `text`
+/// unchanged if `span` is `None` or `color` is false.
+pub(super) fn highlight(text: &str, span: &Option<Range<usize>>, color: bool) -> String {
+    match span {
+        Some(span) if color => format!(
+            "{}{}{}{}{}",
+            &text[..span.start],
+            START,
+            &text[span.clone()],
+            END,
+            &text[span.end..]
+        ),
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans() {
+        assert_eq!(match_span("Meeting notes", "NOTES"), Some(8..13));
+        assert_eq!(match_span("Meeting notes", "eet"), Some(1..4));
+        assert_eq!(match_span("Meeting notes", "x"), None);
+        assert_eq!(match_span("Meeting notes", ""), None);
+
+        // Spans are byte ranges into the original text.
+        assert_eq!(match_span("Ünïcödé", "CÖD"), Some(5..9));
+    }
+
+    #[test]
+    fn relevance_scores() {
+        let score = |text, query| relevance(text, &match_span(text, query).unwrap());
+
+        assert_eq!(score("Plan", "plan"), 3);
+        assert_eq!(score("Plans", "plan"), 2);
+        assert_eq!(score("A plan", "plan"), 1);
+        assert_eq!(score("ünï", "ÜNÏ"), 3);
+    }
+
+    #[test]
+    fn highlighting() {
+        let span = match_span("Meeting", "eet");
+
+        assert_eq!(highlight("Meeting", &span, false), "Meeting");
+        assert_eq!(
+            highlight("Meeting", &span, true),
+            "M\u{1b}[1;33meet\u{1b}[0ming"
+        );
+        assert_eq!(highlight("Meeting", &None, true), "Meeting");
+    }
+}
@@ -0,0 +1,108 @@
+//! Selecting ranges of lines from a note.
+
+use failure::Error;
+use std::str::FromStr;
+
+/// An inclusive range of 1-based line numbers, written as `START:END`,
+/// `START:`, or `:END`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    /// The first line to include, or the first line of the file if
+    /// `None`
+    start: Option<usize>,
+
+    /// The last line to include, or the last line of the file if `None`
+    end: Option<usize>,
+}
+
+impl FromStr for LineRange {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<LineRange, Error> {
+        let (start, end) = match text.find(':') {
+            Some(colon) => (&text[..colon], &text[colon + 1..]),
+            None => failure::bail!("`{}` is not a range. Use START:END, START:, or :END.", text),
+        };
+
+        let parse = |number: &str| -> Result<Option<usize>, Error> {
+            if number.is_empty() {
+                return Ok(None);
+            }
+
+            match number.parse() {
+                Ok(0) => failure::bail!("Line numbers start at 1."),
+                Ok(line) => Ok(Some(line)),
+                Err(_) => failure::bail!("`{}` is not a line number.", number),
+            }
+        };
+
+        let range = LineRange {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+
+        match (range.start, range.end) {
+            (None, None) => failure::bail!("`{}` needs a start, an end, or both.", text),
+            (Some(start), Some(end)) if start > end => {
+                failure::bail!("The range `{}` ends before it starts.", text)
+            }
+            _ => Ok(range),
+        }
+    }
+}
+
+impl LineRange {
+    /// Returns the lines of `text` that are in the range, each followed
+    /// by a newline.
+    ///
+    /// # Errors
+    ///
+    /// - The range starts or ends past the last line of `text`.
+    pub(super) fn select(&self, text: &str) -> Result<String, Error> {
+        let lines: Vec<&str> = text.lines().collect();
+        let start = self.start.unwrap_or(1);
+        let end = self.end.unwrap_or(lines.len());
+
+        failure::ensure!(
+            start <= lines.len() && end <= lines.len(),
+            "The range is past the end of the note, which has {} lines.",
+            lines.len()
+        );
+
+        Ok(lines[start - 1..end]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "one\ntwo\nthree\nfour\n";
+
+    fn select(range: &str) -> String {
+        range.parse::<LineRange>().unwrap().select(TEXT).unwrap()
+    }
+
+    #[test]
+    fn range_forms() {
+        assert_eq!(select("2:3"), "two\nthree\n");
+        assert_eq!(select("3:"), "three\nfour\n");
+        assert_eq!(select(":2"), "one\ntwo\n");
+        assert_eq!(select("4:4"), "four\n");
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        for range in &["3", ":", "0:2", "a:2", "3:2"] {
+            assert!(range.parse::<LineRange>().is_err(), "{}", range);
+        }
+
+        for range in &["5:", ":5", "2:9"] {
+            let range: LineRange = range.parse().unwrap();
+            assert!(range.select(TEXT).is_err());
+        }
+    }
+}
@@ -1,15 +1,50 @@
 //! Subcommands and related logic.
 
+use chrono::{DateTime, NaiveDate};
+use directories::BaseDirs;
 use failure::{Error, ResultExt};
+use filetime::FileTime;
 use prettytable::{format, Attr, Cell, Row, Table};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use structopt::clap::Shell;
 use structopt::StructOpt;
 
-use crate::scrivener::notes::Index;
+use crate::scrivener::config::{Config, CountMode, IndexFormat, PathStyle};
+use crate::scrivener::gzip;
+use crate::scrivener::notes::{self, normalize_name, Index, Note, ScanIgnore};
+use lines::LineRange;
+use progress::Progress;
+use query::Query;
+use std::ops::Range;
+use transaction::Transaction;
 
+mod backup;
+mod completions;
+mod editor;
+mod encoding;
 mod errors;
+mod export;
+mod frontmatter;
+mod git;
+mod highlight;
+mod lines;
+mod pager;
+mod progress;
+mod prompt;
+mod query;
+mod run;
+mod time;
+mod transaction;
+mod view;
 
 #[derive(Debug, StructOpt)]
 /// Command line note application
@@ -29,13 +64,57 @@ pub enum Command {
 
         /// The note file's intended location
         ///
-        /// Defaults to the current directory if not specified
+        /// Defaults to a file named after the note in $SCRIVENER_NOTES_DIR,
+        /// or the default_dir setting, or the current directory
         #[structopt(parse(from_os_str))]
         path: Option<PathBuf>,
 
         /// An optional list of tags to attach to the note
         #[structopt(short, long)]
         tags: Option<Vec<String>>,
+
+        /// A file whose contents the new note starts with
+        ///
+        /// Defaults to the template set for the first tag that has one
+        /// in the `tag_templates` setting
+        #[structopt(long, parse(from_os_str))]
+        template: Option<PathBuf>,
+
+        /// A command to run on the note's file once it has been added
+        ///
+        /// The file's path is appended to the command
+        #[structopt(long)]
+        after_hook: Option<String>,
+
+        /// Open the editor without waiting for it to close
+        ///
+        /// The note's file is created with only its template, so anything typed in a
+        /// terminal editor is not captured. Meant for graphical editors
+        #[structopt(long)]
+        no_wait: bool,
+
+        /// Read the note's text from standard input instead of opening
+        /// the editor
+        ///
+        /// Turned on automatically when standard input is not a terminal,
+        /// unless --editor is given
+        #[structopt(long, conflicts_with_all = &["template", "no-wait"])]
+        stdin: bool,
+
+        /// The editor to write the note in
+        ///
+        /// Defaults to the editor setting, then $VISUAL, then $EDITOR, then vi
+        #[structopt(long, conflicts_with = "stdin")]
+        editor: Option<String>,
+
+        /// Refuse to create the note outside the notes_root setting
+        ///
+        /// Also turned on by the strict_root setting
+        #[structopt(long)]
+        strict_root: bool,
+
+        #[structopt(flatten)]
+        infer: InferOptions,
     },
 
     /// Adds an existing plaintext file to the notes index
@@ -50,6 +129,25 @@ pub enum Command {
         /// An optional list of tags to attach to the note
         #[structopt(short, long)]
         tags: Option<Vec<String>>,
+
+        #[structopt(flatten)]
+        infer: InferOptions,
+
+        /// A command to run on the note's file once it has been added
+        ///
+        /// The file's path is appended to the command
+        #[structopt(long)]
+        after_hook: Option<String>,
+
+        /// Refuse to add a file outside the notes_root setting
+        ///
+        /// Also turned on by the strict_root setting
+        #[structopt(long)]
+        strict_root: bool,
+
+        /// Move the file into the notes_root setting before adding it
+        #[structopt(long)]
+        move_into_root: bool,
     },
 
     /// Edits an existing note
@@ -57,396 +155,6255 @@ pub enum Command {
     /// Defaults to the current directory if not specified
     Edit {
         /// The name of the note to edit
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Open the editor without waiting for it to close
+        #[structopt(long)]
+        no_wait: bool,
+
+        /// The editor to open the note in
+        ///
+        /// Defaults to the editor setting, then $VISUAL, then $EDITOR, then vi
+        #[structopt(long)]
+        editor: Option<String>,
+
+        /// Copy the note's file to a `.bak` file first
+        ///
+        /// Where copies go and how many are kept is set by the
+        /// backup_dir and backup_count settings
+        #[structopt(long)]
+        backup: bool,
+    },
+
+    /// Prints the contents of a note
+    #[structopt(visible_alias = "cat")]
+    Show {
+        /// The name of the note to print
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Only print these lines, given as START:END, START:, or :END
+        ///
+        /// Line numbers start at 1 and both ends are included
+        #[structopt(long)]
+        lines: Option<LineRange>,
+
+        /// Leave out the `---` or `+++` frontmatter block at the start of
+        /// the note
+        #[structopt(long, conflicts_with = "frontmatter-only")]
+        no_frontmatter: bool,
+
+        /// Only print the `---` or `+++` frontmatter block at the start of
+        /// the note
+        #[structopt(long)]
+        frontmatter_only: bool,
+
+        /// Show the note through $PAGER, or less if it is not set
+        ///
+        /// Ignored when the output is not a terminal
+        #[structopt(long)]
+        paginate: bool,
+    },
+
+    /// Opens a random note
+    Random {
+        /// Only choose from notes with this tag
+        #[structopt(long)]
+        tag: Option<String>,
+
+        /// Print the note instead of opening it
+        #[structopt(long)]
+        show: bool,
+
+        /// Choose the same note every time for a given number
+        #[structopt(long)]
+        seed: Option<u64>,
+    },
+
+    /// Marks a note as modified now without editing it
+    Touch {
+        /// The name of the note to touch
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Also update the modification time of the note's file
+        #[structopt(short, long)]
+        file: bool,
+    },
+
+    /// Removes a note from the notes index without deleting the file
+    Remove {
+        /// The name of the note to remove
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Delete the note's file as well, like `delete`
+        #[structopt(long)]
+        also_delete: bool,
+    },
+    /// Removes a note from the notes index and deletes its file
+    Delete {
+        /// The name of the note to delete
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Keep the note's file, like `remove`
+        #[structopt(long)]
+        keep_file: bool,
+
+        /// Also remove the directories that held the file if they are
+        /// left empty, up to the notes_root setting
+        #[structopt(long, conflicts_with = "keep-file")]
+        prune_dirs: bool,
+    },
+
+    /// Lists all notes
+    List {
+        #[structopt(flatten)]
+        options: ListOptions,
+    },
+
+    /// Searches all notes for notes with a given name or tag
+    Search {
+        /// The text to look for in each note's name and tags
+        ///
+        /// Can also be a query of name:TEXT, tag:TAG, and path:TEXT
+        /// terms joined by AND, OR, NOT, and parentheses, such as
+        /// 'tag:work AND (name:report OR NOT tag:done)'. Terms without a
+        /// field match names
+        query: String,
+
+        /// Open the matching note in the default text editor
+        ///
+        /// If several notes match, choose one from a list
+        #[structopt(short, long)]
+        open: bool,
+
+        /// Do not highlight the matched text
+        ///
+        /// Highlighting is also left out when the output is not a
+        /// terminal
+        #[structopt(long)]
+        no_color: bool,
+
+        /// Print the matches as a JSON array, with the relevance score
+        /// of each
+        ///
+        /// Each object has the note's name, path, and tags, the tags
+        /// that matched as matched_tags, and the score
+        #[structopt(long, conflicts_with = "open")]
+        json: bool,
+
+        /// Only show this many of the most relevant notes, followed by
+        /// how many were left out
+        #[structopt(long)]
+        max_results: Option<usize>,
+
+        /// Only look for the query in note names
+        ///
+        /// Giving both --name and --tags is the same as giving neither
+        #[structopt(long = "name")]
+        name_only: bool,
+
+        /// Only look for the query in note tags
+        #[structopt(long = "tags")]
+        tags_only: bool,
+
+        /// Treat the query as a regular expression, ignoring case,
+        /// instead of plain text or a boolean query
+        #[structopt(long)]
+        regex: bool,
+    },
+
+    /// Adds the plaintext files in a directory to the notes index
+    ///
+    /// Each note is named after its file. Files that are already in
+    /// the index are skipped
+    Scan {
+        /// The directory to scan
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Scan subdirectories as well
+        #[structopt(short, long)]
+        recursive: bool,
+
+        /// Also leave out files matched by the `.gitignore`-style
+        /// patterns in this file
+        ///
+        /// Hidden files, files matched by the ignore setting, and files
+        /// matched by `.gitignore` or `.scrivenerignore` are always left
+        /// out
+        #[structopt(long, parse(from_os_str), number_of_values = 1)]
+        ignore_file: Vec<PathBuf>,
+
+        #[structopt(flatten)]
+        infer: InferOptions,
+    },
+
+    /// Moves each note's file into the directory set for its tags in
+    /// the tag_dirs setting
+    ///
+    /// Notes with several such tags go in the directory of the first
+    Organize {},
+
+    /// Prints the details of a note
+    Info {
+        /// The name of the note
+        name: Option<String>,
+
+        /// Pick the note from a list if no name is given
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Check that every [[name]] link in the note leads to a note,
+        /// and list the notes that link to it
+        #[structopt(long)]
+        check_links: bool,
+    },
+
+    /// Prints the name of each note on its own line
+    Names {
+        /// Only print notes with this tag, ignoring case
+        ///
+        /// May be given more than once to only print notes with every
+        /// tag
+        #[structopt(long = "filter-tag")]
+        filter_tags: Vec<String>,
+    },
+
+    /// Shows statistics about all notes
+    Stats {},
+
+    /// Checks every note for problems, such as missing files
+    Verify {
+        /// First remove empty and duplicate tags from every note
+        #[structopt(long)]
+        fix: bool,
+    },
+
+    /// Adds tags to a note
+    ///
+    /// Tags the note already has are left as they are
+    Tag {
+        /// The name of the note to tag
+        name: String,
+
+        /// The tags to add
+        #[structopt(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Removes tags from a note
+    Untag {
+        /// The name of the note to untag
+        name: String,
+
+        /// The tags to remove
+        #[structopt(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Changes the name of a note, leaving its file as it is
+    Rename {
+        /// The note's current name
+        old: String,
+
+        /// The note's new name
+        new: String,
+
+        /// Check that the index is still consistent afterwards, and
+        /// undo the change if it is not
+        ///
+        /// Always done in debug builds
+        #[structopt(long)]
+        verify: bool,
+    },
+
+    /// Moves a note's file and updates the note to point to it
+    Move {
+        /// The name of the note to move
+        name: String,
+
+        /// Where to move the file, or a directory to move it into
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        /// Check that the index is still consistent afterwards, and
+        /// undo the change if it is not
+        ///
+        /// Always done in debug builds
+        #[structopt(long)]
+        verify: bool,
+
+        /// Also remove the directories that held the file if they are
+        /// left empty, up to the notes_root setting
+        #[structopt(long)]
+        prune_dirs: bool,
+    },
+
+    /// Renames a tag on every note that has it
+    RenameTag {
+        /// The tag to rename
+        old: String,
+
+        /// The tag's new name
+        new: String,
+
+        /// Allow renaming to a tag that is already in use, combining the
+        /// two
+        ///
+        /// Without this, the rename is refused if any note already has
+        /// the new tag
+        #[structopt(long)]
+        merge: bool,
+    },
+
+    /// Lists notes whose files are missing, and repairs their paths
+    /// after a notes directory has been moved
+    Doctor {
+        /// Change the paths of notes in the directory given by --from to
+        /// the same paths in the directory given by --to
+        #[structopt(long, requires_all = &["from", "to"])]
+        fix_paths: bool,
+
+        /// The directory the notes used to be in
+        #[structopt(long, parse(from_os_str), requires = "fix-paths")]
+        from: Option<PathBuf>,
+
+        /// The directory the notes are in now
+        #[structopt(long, parse(from_os_str), requires = "fix-paths")]
+        to: Option<PathBuf>,
+    },
+
+    /// Finds notes whose files have identical contents
+    Duplicates {
+        /// Keep the first note of each group, adding the others' tags to
+        /// it, and delete the others and their files
+        #[structopt(long)]
+        merge: bool,
+    },
+
+    /// Removes notes whose files no longer exist from the index
+    Prune {
+        /// Show which notes would be removed without removing them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+
+    /// Rewrites the notes index in another file format
+    ///
+    /// The setting `index_format` is changed to match
+    ConvertIndex {
+        /// The format to convert to: toml or json
+        format: IndexFormat,
+    },
+
+    /// Shows or changes settings
+    ///
+    /// With no arguments, shows every setting. With only a key, shows
+    /// that setting. With a key and a value, changes that setting. The
+    /// key may also follow `get` or `set`, as in `config set editor nvim`
+    Config {
+        #[structopt(subcommand)]
+        action: Option<ConfigAction>,
+
+        /// The name of the setting
+        key: Option<String>,
+
+        /// The new value of the setting
+        value: Option<String>,
+    },
+
+    /// Prints a script that completes scrv's commands in a shell
+    Completions {
+        /// The shell to complete commands in
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+
+        /// Write the script to where the shell loads completions from,
+        /// instead of printing it
+        ///
+        /// Supported for bash, fish, and zsh
+        #[structopt(long)]
+        install: bool,
+    },
+
+    /// Writes notes to a file that can be imported into another index
+    Export {
+        /// The file to write the notes to
+        #[structopt(parse(from_os_str), required_unless = "per-note")]
+        output: Option<PathBuf>,
+
+        /// Write each note to a JSON sidecar next to its file instead,
+        /// named like note.txt.scrivener.json
+        #[structopt(long, conflicts_with = "output")]
+        per_note: bool,
+
+        /// Treat the output as a directory, copy the note files and an
+        /// index of them into it, and commit them to a git repository
+        /// there
+        ///
+        /// A repository is created if there is none yet
+        #[structopt(long, conflicts_with = "per-note")]
+        git_init: bool,
+
+        /// Only export notes modified after this time
+        ///
+        /// Accepts a date such as 2020-05-04, an RFC 3339 timestamp, or
+        /// seconds since the Unix epoch. Dates are in UTC
+        #[structopt(long, parse(try_from_str = parse_time), conflicts_with = "git-init")]
+        since: Option<SystemTime>,
+    },
+
+    /// Adds the notes in an exported file to the notes index
+    ///
+    /// Notes with the same name as an imported note are replaced
+    Import {
+        /// The file to read the notes from
+        #[structopt(parse(from_os_str), required_unless = "from-sidecars")]
+        input: Option<PathBuf>,
+
+        /// Read the notes from the JSON sidecars in this directory and
+        /// its subdirectories instead
+        #[structopt(long, parse(from_os_str), conflicts_with = "input")]
+        from_sidecars: Option<PathBuf>,
+    },
+
+    /// Updates the tags of several notes at once from a JSON file
+    ///
+    /// The file maps note names to objects with "tags", which replaces
+    /// a note's tags, and "add_tags", which adds to them. Nothing is
+    /// changed unless every named note exists
+    ApplyMeta {
+        /// The JSON file to read the updates from
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Writes a Markdown page linking to every note, grouped by tag
+    ExportIndex {
+        /// The file to write the page to
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Runs a note if it is marked as executable
+    ///
+    /// Exits with the same status as the note
+    #[structopt(
+        setting = structopt::clap::AppSettings::TrailingVarArg,
+        setting = structopt::clap::AppSettings::AllowLeadingHyphen
+    )]
+    Run {
+        /// The name of the note to run
         name: String,
+
+        /// Arguments to pass to the note
+        args: Vec<String>,
+    },
+}
+
+/// Options that control how `list` displays notes.
+#[derive(Debug, Default, Clone, StructOpt)]
+pub struct ListOptions {
+    /// Show each note file's path
+    #[structopt(short = "p", long = "paths")]
+    show_paths: bool,
+
+    /// Show each note's tags
+    #[structopt(short = "t", long = "tags")]
+    show_tags: bool,
+
+    /// Show when each note was added to the index
+    #[structopt(long = "created")]
+    show_created: bool,
+
+    /// Show when each note was last modified
+    #[structopt(long = "modified")]
+    show_modified: bool,
+
+    /// Show times relative to now, from the `relative_time` setting
+    #[structopt(skip)]
+    relative_time: bool,
+
+    /// What to count in each file for --json-full, from the
+    /// `count_mode` setting
+    #[structopt(skip)]
+    count_mode: CountMode,
+
+    /// Sort the tags in each row alphabetically instead of in the order
+    /// they were added
+    #[structopt(long, requires = "show-tags")]
+    sort_tags: bool,
+
+    /// Show paths exactly as stored instead of relative to the current
+    /// directory
+    #[structopt(short = "a", long = "absolute")]
+    absolute_paths: bool,
+
+    /// How to write relative paths: dotted (./notes/a.txt) or bare
+    /// (notes/a.txt)
+    ///
+    /// Defaults to the path_style setting
+    #[structopt(long)]
+    path_style: Option<PathStyle>,
+
+    /// Leave out the row of column titles
+    #[structopt(long, alias = "no-title")]
+    no_header: bool,
+
+    /// Insert a header row before each new leading letter
+    ///
+    /// Notes are grouped by their leading letter, ignoring case, and
+    /// keep the --sort order within each group
+    #[structopt(long)]
+    sections: bool,
+
+    /// Only list notes with this tag, ignoring case
+    ///
+    /// May be given more than once to only list notes with every tag
+    #[structopt(long = "filter-tag")]
+    filter_tags: Vec<String>,
+
+    /// List notes with any of the filter tags instead of all of them
+    #[structopt(long, requires = "filter-tags")]
+    any: bool,
+
+    /// Match filter tags that contain the given text, not just whole
+    /// tags
+    #[structopt(long)]
+    partial: bool,
+
+    /// Only list notes whose files are empty
+    ///
+    /// Notes whose files are missing are left out
+    #[structopt(long)]
+    empty: bool,
+
+    /// Only list notes whose files were modified after the time the
+    /// index recorded, such as by editing them outside scrivener
+    #[structopt(long)]
+    changed: bool,
+
+    /// Record the current modification times and word counts of the
+    /// listed notes
+    #[structopt(long, requires = "changed")]
+    refresh: bool,
+
+    /// Shorten columns so that each line fits in this many characters
+    #[structopt(long)]
+    width: Option<usize>,
+
+    /// Shorten any column wider than this many characters
+    #[structopt(long)]
+    max_col: Option<usize>,
+
+    /// The order to list notes in: name (alphabetical), added (the
+    /// order they were added in), path, or tags (fewest tags first)
+    ///
+    /// Notes that tie, such as those added before the order was
+    /// recorded, stay in alphabetical order
+    #[structopt(long, default_value = "name")]
+    sort: ListSort,
+
+    /// List notes in the opposite order
+    #[structopt(long)]
+    reverse: bool,
+
+    /// Only list this many notes, followed by how many were left out
+    #[structopt(long)]
+    max_results: Option<usize>,
+
+    /// List notes that share a file next to each other, and show which
+    /// other notes share each file
+    #[structopt(long, conflicts_with = "sections")]
+    distinct_paths: bool,
+
+    /// Print one JSON object per note per line instead of a table
+    ///
+    /// Each object has the note's name, plus its path and tags if
+    /// --paths and --tags are given
+    #[structopt(long, conflicts_with_all = &["sections", "width", "max-col"])]
+    json_lines: bool,
+
+    /// Print the notes as a JSON array instead of a table
+    ///
+    /// Each object always has the note's name, path as stored, and tags,
+    /// whether or not --paths and --tags are given
+    #[structopt(
+        long,
+        conflicts_with_all = &["sections", "width", "max-col", "json-lines", "json-full"]
+    )]
+    json: bool,
+
+    /// Print the notes as a JSON array with every stored field, plus the
+    /// relative path, whether the file exists, its size, and its word
+    /// count
+    #[structopt(
+        long,
+        conflicts_with_all = &["sections", "width", "max-col", "json-lines"]
+    )]
+    json_full: bool,
+}
+
+/// The `get` and `set` forms of `config`.
+#[derive(Debug, StructOpt)]
+pub enum ConfigAction {
+    /// Shows a setting
+    Get {
+        /// The name of the setting
+        key: String,
+    },
+
+    /// Changes a setting
+    Set {
+        /// The name of the setting
+        key: String,
+
+        /// The new value of the setting
+        value: String,
     },
+}
+
+/// An order that `list` can show notes in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ListSort {
+    /// Alphabetical order by name
+    #[default]
+    Name,
+
+    /// The order the notes were added in
+    Added,
+
+    /// Alphabetical order by stored path
+    Path,
+
+    /// By the number of tags, from fewest to most
+    Tags,
+}
+
+impl std::str::FromStr for ListSort {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<ListSort, Error> {
+        match text {
+            "name" => Ok(ListSort::Name),
+            "added" => Ok(ListSort::Added),
+            "path" => Ok(ListSort::Path),
+            "tags" => Ok(ListSort::Tags),
+            _ => failure::bail!(
+                "`{}` is not a sort order. Use name, added, path, or tags.",
+                text
+            ),
+        }
+    }
+}
+
+/// Options for tagging notes by the directories they are in.
+#[derive(Debug, Default, StructOpt)]
+pub struct InferOptions {
+    /// Tag notes with the directories between the notes_root setting and
+    /// their files
+    #[structopt(long)]
+    infer_tags_from_path: bool,
+
+    /// Only use this many directories below notes_root as tags
+    #[structopt(long, requires = "infer-tags-from-path")]
+    tag_depth: Option<usize>,
+
+    /// Tag notes with the name of the directory their file is in
+    ///
+    /// Files directly in the notes_root setting get no tag
+    #[structopt(long)]
+    tag_from_parent: bool,
+}
+
+impl InferOptions {
+    /// Returns the tags inferred from the directories of `path`, or no
+    /// tags if inferring tags is turned off.
+    ///
+    /// # Errors
+    ///
+    /// - Inferring tags from the whole path is turned on, but no notes
+    ///   root is set.
+    fn tags(&self, path: &Path, config: &Config) -> Result<Vec<String>, Error> {
+        let mut tags = Vec::new();
+
+        if self.infer_tags_from_path {
+            match config.notes_root() {
+                Some(root) => tags = notes::tags_from_path(path, root, self.tag_depth),
+                None => {
+                    failure::bail!("Set notes_root with `config notes_root <dir>` to infer tags.")
+                }
+            }
+        }
+
+        if self.tag_from_parent {
+            tags.extend(notes::parent_tag(path, config.notes_root()));
+        }
+
+        Ok(tags)
+    }
+}
+
+impl Command {
+    /// Returns the key and value given to `config`, or `None` if this
+    /// is another subcommand.
+    pub fn config_setting(&self) -> Option<(Option<&str>, Option<&str>)> {
+        match self {
+            Command::Config { action, key, value } => Some(match action {
+                Some(ConfigAction::Get { key }) => (Some(key.as_str()), None),
+                Some(ConfigAction::Set { key, value }) => {
+                    (Some(key.as_str()), Some(value.as_str()))
+                }
+                None => (key.as_deref(), value.as_deref()),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the subcommand can change the `Index`.
+    pub fn modifies_index(&self) -> bool {
+        !matches!(
+            self,
+            Command::List {
+                options: ListOptions { refresh: false, .. }
+            } | Command::Show { .. }
+                | Command::Random { .. }
+                | Command::Search { .. }
+                | Command::Info { .. }
+                | Command::Names { .. }
+                | Command::Stats {}
+                | Command::Verify { fix: false }
+                | Command::Prune { dry_run: true }
+                | Command::Doctor {
+                    fix_paths: false,
+                    ..
+                }
+                | Command::Config { .. }
+                | Command::ConvertIndex { .. }
+                | Command::Completions { .. }
+                | Command::Export { .. }
+                | Command::ExportIndex { .. }
+                | Command::Run { .. }
+        )
+    }
+
+    /// Executes a function that corresponds to the outcome of a
+    /// subcommand, and returns the code that the program should exit
+    /// with, which is only nonzero if `run` ran a note that failed.
+    ///
+    /// If `assume_yes` is true, every confirmation prompt is answered
+    /// with yes.
+    pub fn execute(
+        &self,
+        index: &mut Index,
+        config: &Config,
+        assume_yes: bool,
+    ) -> Result<i32, Error> {
+        let result = match self {
+            Command::New {
+                name,
+                path,
+                tags,
+                template,
+                after_hook,
+                no_wait,
+                stdin,
+                editor,
+                strict_root,
+                infer,
+            } => {
+                let name = &normalize_name(name, config.name_case);
+                notes::validate_name(name, &config.name_regex)?;
+                let path = Some(new_note_path(name, path, config)?);
+                check_root(path.as_ref().unwrap(), config, *strict_root)?;
+                let inferred = infer.tags(path.as_ref().unwrap(), config)?;
+                let piped = editor.is_none() && !no_wait && !atty::is(atty::Stream::Stdin);
+                if *stdin || piped {
+                    let text = read_stdin()?;
+                    create_new_note(index, name, &path, tags, &text, None, true, config)?;
+                } else {
+                    let template = read_template(config.template_for(template, tags))?;
+                    let editor = editor::choose(editor, config);
+                    create_new_note(
+                        index,
+                        name,
+                        &path,
+                        tags,
+                        &template,
+                        Some(&editor),
+                        !no_wait,
+                        config,
+                    )?;
+                }
+                index.add_tags(name, &inferred);
+                run_after_hook(index, name, after_hook)
+            }
+            Command::Add {
+                name,
+                path,
+                tags,
+                infer,
+                after_hook,
+                strict_root,
+                move_into_root,
+            } => {
+                let name = &normalize_name(name, config.name_case);
+                notes::validate_name(name, &config.name_regex)?;
+                failure::ensure!(!index.contains(name), errors::already_exists(name));
+                let path = &transaction::run(index, |index, transaction| {
+                    let path = if *move_into_root {
+                        move_into_notes_root(path, config, transaction)?
+                    } else {
+                        check_root(path, config, *strict_root)?;
+                        path.clone()
+                    };
+                    add_note(index, name, &path, tags)?;
+                    Ok(path)
+                })?;
+                announce(index, name, "added", config);
+                index.add_tags(name, &infer.tags(path, config)?);
+                warn_if_misplaced(index, name, config);
+                run_after_hook(index, name, after_hook)
+            }
+            Command::Edit {
+                name,
+                interactive,
+                no_wait,
+                editor,
+                backup,
+            } => {
+                let name = resolve_name(index, name, *interactive)?;
+                edit_note(
+                    index,
+                    &name,
+                    &editor::choose(editor, config),
+                    !no_wait,
+                    *backup,
+                    assume_yes,
+                    config,
+                )
+            }
+            Command::Show {
+                name,
+                interactive,
+                lines,
+                no_frontmatter,
+                frontmatter_only,
+                paginate,
+            } => {
+                let name = resolve_name(index, name, *interactive)?;
+                let frontmatter = Frontmatter::from_flags(*no_frontmatter, *frontmatter_only);
+
+                if *paginate && atty::is(atty::Stream::Stdout) {
+                    let mut text = Vec::new();
+                    show_note(index, &name, *lines, frontmatter, &mut text)?;
+                    pager::page(&pager::default_pager(), &text)
+                } else {
+                    show_note(index, &name, *lines, frontmatter, &mut std::io::stdout())
+                }
+            }
+            Command::Random { tag, show, seed } => {
+                random_note(index, tag, *show, *seed, &editor::choose(&None, config))
+            }
+            Command::Touch {
+                name,
+                interactive,
+                file,
+            } => touch_note(
+                index,
+                &resolve_name(index, name, *interactive)?,
+                *file,
+                config,
+            ),
+            Command::Remove {
+                name,
+                interactive,
+                also_delete,
+            } => {
+                let name = resolve_name(index, name, *interactive)?;
+                delete_note(index, &name, *also_delete, None, assume_yes, config)
+            }
+            Command::Delete {
+                name,
+                interactive,
+                keep_file,
+                prune_dirs,
+            } => {
+                let name = resolve_name(index, name, *interactive)?;
+                let prune_root = prune_root(*prune_dirs, config)?;
+                delete_note(index, &name, !keep_file, prune_root, assume_yes, config)
+            }
+            Command::List { options } => {
+                let options = &ListOptions {
+                    path_style: options.path_style.or(Some(config.path_style)),
+                    relative_time: config.relative_time,
+                    count_mode: config.count_mode,
+                    ..options.clone()
+                };
+                list_notes(index, options, &mut std::io::stderr())?;
+                if options.refresh {
+                    refresh_notes(index, options, config);
+                }
+                Ok(())
+            }
+            Command::Search {
+                query,
+                open,
+                no_color,
+                json,
+                max_results,
+                name_only,
+                tags_only,
+                regex,
+            } => {
+                let color = !no_color && atty::is(atty::Stream::Stdout);
+                let editor = editor::choose(&None, config);
+                let scope = SearchScope::new(*name_only, *tags_only, *regex, query)?;
+                search_notes(
+                    index,
+                    query,
+                    &scope,
+                    *open,
+                    color,
+                    *json,
+                    *max_results,
+                    &editor,
+                    config,
+                )
+            }
+            Command::Scan {
+                dir,
+                recursive,
+                ignore_file,
+                infer,
+            } => {
+                let ignore = ScanIgnore {
+                    globs: config.ignore.clone(),
+                    files: ignore_file.clone(),
+                };
+                scan_directory(index, dir, *recursive, &ignore, infer, config, assume_yes)
+            }
+            Command::Organize {} => organize_notes(index, config, assume_yes),
+            Command::Info {
+                name,
+                interactive,
+                check_links,
+            } => show_info(
+                index,
+                &resolve_name(index, name, *interactive)?,
+                *check_links,
+                config,
+            ),
+            Command::Names { filter_tags } => {
+                let stdout = std::io::stdout();
+                write_names(index, filter_tags, &mut stdout.lock())
+            }
+            Command::Stats {} => show_stats(index, config),
+            Command::Tag { name, tags } => tag_note(index, name, tags, true, config),
+            Command::Untag { name, tags } => tag_note(index, name, tags, false, config),
+            Command::Rename { old, new, verify } => {
+                let new = &normalize_name(new, config.name_case);
+                notes::validate_name(new, &config.name_regex)?;
+                rename_note(index, old, new, *verify || cfg!(debug_assertions), config)
+            }
+            Command::Move {
+                name,
+                path,
+                verify,
+                prune_dirs,
+            } => {
+                let prune_root = prune_root(*prune_dirs, config)?;
+                let verify = *verify || cfg!(debug_assertions);
+                move_note(index, name, path, verify, prune_root, config)
+            }
+            Command::RenameTag { old, new, merge } => rename_tag(index, old, new, *merge, config),
+            Command::Verify { fix } => return verify_notes(index, *fix, config),
+            Command::Doctor { from, to, .. } => match (from, to) {
+                (Some(from), Some(to)) => fix_paths(index, from, to, config),
+                _ => report_missing_files(index),
+            },
+            Command::Duplicates { merge } => find_duplicates(index, *merge, assume_yes, config),
+            Command::Prune { dry_run } => prune_notes(index, *dry_run, config),
+            // Handled by `Args::execute` before the index is loaded.
+            Command::Config { .. } | Command::ConvertIndex { .. } | Command::Completions { .. } => {
+                Ok(())
+            }
+            Command::Export {
+                output,
+                per_note,
+                git_init,
+                since,
+            } => match output {
+                Some(output) if *git_init => export_git_repo(index, output, config),
+                Some(output) if !per_note => export_notes(index, output, *since, config),
+                _ => export_sidecars(index, *since, config),
+            },
+            Command::Import {
+                input,
+                from_sidecars,
+            } => match (input, from_sidecars) {
+                (_, Some(dir)) => import_sidecars(index, dir, config),
+                (Some(input), None) => import_notes(index, input, config),
+                (None, None) => failure::bail!("Give a file or --from-sidecars to import from."),
+            },
+            Command::ApplyMeta { file } => apply_meta(index, file, config),
+            Command::ExportIndex { output } => export_index(index, output, config),
+            Command::Run { name, args } => return run_note(index, name, args),
+        };
+
+        result.map(|()| 0)
+    }
+}
+
+/// Returns `name`, or asks the user to pick a `Note` if no name is
+/// given and `interactive` is true.
+///
+/// # Errors
+///
+/// - No name is given and `interactive` is false.
+/// - No name is given and standard input is not a terminal.
+/// - The user does not pick a `Note`.
+fn resolve_name(index: &Index, name: &Option<String>, interactive: bool) -> Result<String, Error> {
+    if let Some(name) = name {
+        return Ok(name.clone());
+    }
+
+    failure::ensure!(
+        interactive,
+        "A note name is required. Use --interactive to pick one."
+    );
+    failure::ensure!(
+        atty::is(atty::Stream::Stdin),
+        "A note name is required when not reading from a terminal."
+    );
+    failure::ensure!(
+        !index.notes().is_empty(),
+        "There are no notes to pick from."
+    );
+
+    let names: Vec<&str> = index.notes().iter().map(|note| note.name()).collect();
+    match prompt::pick("Which note?", &names)? {
+        Some(chosen) => Ok(names[chosen].to_string()),
+        None => failure::bail!("No note was picked."),
+    }
+}
+
+/// Adds an existing file to the `Index`.
+///
+/// # Errors
+///
+/// - When a `Note` with the same name as the one being added already
+///   exists in the `Index`.
+fn add_note(
+    index: &mut Index,
+    name: &str,
+    path: &PathBuf,
+    tags: &Option<Vec<String>>,
+) -> Result<(), Error> {
+    failure::ensure!(!index.contains(name), errors::already_exists(name));
+
+    index.add(name, path, tags)?;
+
+    Ok(())
+}
+
+/// Prints the `success_message` setting for the `Note` called `name`
+/// to stderr after it was added with `action`, unless the setting is
+/// empty or the `quiet` setting is on. See `errors::render_success`.
+fn announce(index: &Index, name: &str, action: &str, config: &Config) {
+    let note = match index.get(name) {
+        Some(note) if !config.success_message.is_empty() => note,
+        _ => return,
+    };
+
+    errors::success(
+        &errors::render_success(
+            &config.success_message,
+            name,
+            action,
+            note.path(),
+            &abs_to_rel(note.path(), config.path_style),
+        ),
+        config,
+    );
+}
+
+/// The environment variable that sets the directory new notes are
+/// created in, ahead of the `default_dir` setting.
+const NOTES_DIR_VAR: &str = "SCRIVENER_NOTES_DIR";
+
+/// Returns `path`, or a file named after `name` in the notes directory
+/// if `path` is `None`. See `notes_dir`.
+///
+/// The notes directory is created if it does not exist. If there is
+/// none, the file is in the current directory.
+///
+/// # Errors
+///
+/// - The notes directory cannot be created.
+/// - The current directory cannot be accessed.
+fn new_note_path(name: &str, path: &Option<PathBuf>, config: &Config) -> Result<PathBuf, Error> {
+    new_note_path_from(name, path, std::env::var_os(NOTES_DIR_VAR), config)
+}
+
+/// Returns the path for a new note like `new_note_path`, with
+/// `from_env` as the value of $SCRIVENER_NOTES_DIR.
+fn new_note_path_from(
+    name: &str,
+    path: &Option<PathBuf>,
+    from_env: Option<std::ffi::OsString>,
+    config: &Config,
+) -> Result<PathBuf, Error> {
+    match path {
+        Some(path) => Ok(path.clone()),
+        None => {
+            let mut path = match notes_dir(from_env, config) {
+                Some(dir) => {
+                    fs::create_dir_all(&dir)
+                        .with_context(|_| format!("Could not create {}.", dir.display()))?;
+                    dir
+                }
+                None => std::env::current_dir()
+                    .with_context(|_| errors::could_not("access current directory"))?,
+            };
+            path.push(format!("{}.txt", &name));
+            Ok(path)
+        }
+    }
+}
+
+/// Returns the directory that new notes are created in: `from_env`,
+/// the value of $SCRIVENER_NOTES_DIR, if it is set and not empty, or
+/// else the `default_dir` setting.
+fn notes_dir(from_env: Option<std::ffi::OsString>, config: &Config) -> Option<PathBuf> {
+    from_env
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| config.default_dir())
+}
+
+/// Checks that `path` is under the notes root if `strict` is true or
+/// the `strict_root` setting is on.
+///
+/// # Errors
+///
+/// - The check is on, but no notes root is set.
+/// - `path` is outside the notes root.
+fn check_root(path: &Path, config: &Config, strict: bool) -> Result<(), Error> {
+    if !strict && !config.strict_root {
+        return Ok(());
+    }
+
+    let root = match config.notes_root() {
+        Some(root) => root,
+        None => {
+            failure::bail!("Set notes_root with `config notes_root <dir>` to use a strict root.")
+        }
+    };
+
+    failure::ensure!(
+        notes::path_under_root(path, root),
+        "{} is outside the notes root {}. Use `add --move-into-root` to move it there first.",
+        path.display(),
+        root.display()
+    );
+
+    Ok(())
+}
+
+/// Moves the file at `path` into the notes root, unless it is already
+/// under it, and returns where the file now is. The move is recorded
+/// in `transaction` so that it can be undone.
+///
+/// # Errors
+///
+/// - No notes root is set.
+/// - A file with the same name is already in the notes root.
+/// - The file cannot be moved.
+fn move_into_notes_root(
+    path: &Path,
+    config: &Config,
+    transaction: &mut Transaction,
+) -> Result<PathBuf, Error> {
+    let root = match config.notes_root() {
+        Some(root) => root,
+        None => {
+            failure::bail!("Set notes_root with `config notes_root <dir>` to move files into it.")
+        }
+    };
+
+    if notes::path_under_root(path, root) {
+        return Ok(path.to_path_buf());
+    }
+
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => failure::bail!("{} does not name a file.", path.display()),
+    };
+
+    let moved = root.join(file_name);
+    failure::ensure!(
+        !moved.exists(),
+        "A file at {} already exists.",
+        moved.display()
+    );
+
+    transaction
+        .rename(path, &moved)
+        .with_context(|_| format!("Could not move {} to {}.", path.display(), moved.display()))?;
+
+    Ok(moved)
+}
+
+/// Creates a file and adds it as a `Note` to the `Index`
+///
+/// If `None` is given as the path, the path used is in the directory
+/// set by the `default_dir` setting, or the current working directory.
+///
+/// Prompts a user for input by opening a temportary file containing
+/// `template` with `editor`, or writes `template` as is if `editor` is
+/// `None`.
+///
+/// If `wait` is false, the file is created with only `template` and
+/// opened in `editor` without waiting for it to close, so nothing typed
+/// into the editor is captured.
+///
+/// The file is written with the line endings and byte order mark set
+/// in `config`.
+///
+/// # Errors
+///
+/// - A `Note` with the same name as the one being added exists.
+///
+/// - The path given is a directory, already has a file, or is
+///   otherwise inaccessible.
+///
+/// - The editor cannot be opened, in which case the created file is
+///   removed again.
+#[allow(clippy::too_many_arguments)]
+fn create_new_note(
+    index: &mut Index,
+    name: &str,
+    path: &Option<PathBuf>,
+    tags: &Option<Vec<String>>,
+    template: &str,
+    editor: Option<&str>,
+    wait: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    let path = new_note_path(name, path, config)?;
+
+    failure::ensure!(!index.contains(name), errors::already_exists(name));
+
+    failure::ensure!(
+        !path.is_dir(),
+        "{} is a directory, not a file.",
+        path.display()
+    );
+    failure::ensure!(
+        !path.exists(),
+        "A file at {} already exists.",
+        path.display()
+    );
+
+    let mut file =
+        File::create(&path).with_context(|_| format!("Could not create {}.", path.display()))?;
+    let created = CreatedFile::new(&path);
+
+    let editor = match editor {
+        Some(editor) => editor,
+        None => {
+            encoding::write(&mut file, template, config)?;
+            add_note(index, name, &path, tags)?;
+            created.keep();
+            announce(index, name, "created", config);
+            return Ok(());
+        }
+    };
+
+    if !wait {
+        encoding::write(&mut file, template, config)?;
+        editor::open(editor, &path, false)?;
+        add_note(index, name, &path, tags)?;
+        created.keep();
+        announce(index, name, "created", config);
+        return Ok(());
+    }
+
+    let text = editor::capture(editor, template)?;
+
+    encoding::write(&mut file, &text, config)?;
+
+    add_note(index, name, &path, tags)?;
+    created.keep();
+    announce(index, name, "created", config);
+
+    Ok(())
+}
+
+/// Reads all of standard input as text.
+///
+/// # Errors
+///
+/// - Standard input cannot be read or is not valid UTF-8.
+fn read_stdin() -> Result<String, Error> {
+    let mut text = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+        .with_context(|_| errors::could_not("read standard input"))?;
+    Ok(text)
+}
+
+/// A newly created file that is removed again when this is dropped,
+/// unless `keep` is called first.
+///
+/// This makes sure a command that fails partway through does not leave
+/// a stray file behind.
+struct CreatedFile<'a> {
+    path: &'a Path,
+    kept: bool,
+}
+
+impl<'a> CreatedFile<'a> {
+    fn new(path: &'a Path) -> CreatedFile<'a> {
+        CreatedFile { path, kept: false }
+    }
+
+    /// Keeps the file once everything that uses it has succeeded.
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for CreatedFile<'_> {
+    fn drop(&mut self) {
+        if !self.kept {
+            // The file may not have been written at all, so there is
+            // nothing to report if it cannot be removed.
+            let _ = fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Returns the contents of the template at `path`, or an empty string
+/// if there is no template.
+///
+/// # Errors
+///
+/// - The template cannot be read.
+fn read_template(path: Option<PathBuf>) -> Result<String, Error> {
+    match path {
+        Some(path) => Ok(fs::read_to_string(&path)
+            .with_context(|_| format!("Could not read template {}.", path.display()))?),
+        None => Ok(String::new()),
+    }
+}
+
+/// Runs `hook` through the shell with the path of the `Note` called
+/// `name` appended to it, if a hook is given.
+///
+/// The `Note` has already been added by the time the hook runs, so a
+/// hook that fails only causes a warning to be printed.
+fn run_after_hook(index: &Index, name: &str, hook: &Option<String>) -> Result<(), Error> {
+    let (hook, note) = match (hook, index.get(name)) {
+        (Some(hook), Some(note)) => (hook, note),
+        _ => return Ok(()),
+    };
+
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", hook))
+        .arg("sh")
+        .arg(note.path())
+        .status();
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: hook `{}` failed with {}.", hook, status),
+        Err(error) => eprintln!("Warning: could not run hook `{}`: {}.", hook, error),
+    }
+
+    Ok(())
+}
+
+/// What the user can do when a note's file changed since it was last
+/// recorded, in the order they are offered.
+const EDIT_CONFLICT_CHOICES: [&str; 3] = [
+    "Proceed and edit it anyway",
+    "Reload the file's current state, then edit it",
+    "Abort",
+];
+
+/// Edits an existing note.
+///
+/// Opens the note's file with `editor`. If `wait` is false, this
+/// returns without waiting for the editor to close, and without
+/// recording anything, since the editor has not written the file yet.
+/// Otherwise, the file is rewritten with the line endings and byte
+/// order mark set in `config`, and the note's modification time is
+/// updated from it.
+///
+/// If the file was changed by something else since its modification
+/// time was recorded, the user is warned and asked whether to proceed,
+/// reload the file's current state first, or abort. If `assume_yes`
+/// is true, it proceeds.
+///
+/// If `backup` is true, the file is copied before the editor is
+/// opened. See `backup::backup`.
+///
+/// # Errors
+///
+/// - There is no note with the `name` that is given.
+/// - `backup` is true and the file cannot be copied.
+/// - `editor` cannot be launched.
+fn edit_note(
+    index: &mut Index,
+    name: &str,
+    editor: &str,
+    wait: bool,
+    backup: bool,
+    assume_yes: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    let note = match index.get(name) {
+        Some(note) => note,
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+    let path = note.path().clone();
+
+    if let Some(warning) = edit_conflict(note) {
+        eprintln!("Warning: {}", warning);
+
+        let choice = if assume_yes {
+            Some(0)
+        } else {
+            prompt::choose("What should be done?", &EDIT_CONFLICT_CHOICES)?
+        };
+        match choice {
+            Some(0) => {}
+            Some(1) => {
+                index.refresh(name, config.count_mode);
+            }
+            _ => {
+                eprintln!("Note `{}` was not edited.", name);
+                return Ok(());
+            }
+        }
+    }
+
+    if backup {
+        let copy = backup::backup(&path, config)?;
+        errors::success(
+            &format!("Backed up note `{}` to {}.", name, copy.display()),
+            config,
+        );
+    }
+
+    if gzip::is_compressed(&path) {
+        failure::ensure!(
+            wait,
+            "Note `{}` is compressed, so it can only be edited by waiting for the editor to close.",
+            name
+        );
+        edit_compressed(editor, name, &path, config)?;
+    } else {
+        editor::open(editor, &path, wait)
+            .with_context(|_| errors::could_not_note("open", name, &path))?;
+
+        if !wait {
+            errors::success(&format!("Note `{}` has been opened.", name), config);
+            return Ok(());
+        }
+
+        encoding::rewrite(&path, config)?;
+    }
+
+    index.refresh(name, config.count_mode);
+    errors::successful(name, "edited", config);
+
+    Ok(())
+}
+
+/// Edits the note called `name`, whose file at `path` is compressed
+/// with gzip, in `editor`.
+///
+/// The file is decompressed into a temporary file for the editor, and
+/// what was written there is compressed back into `path` once the
+/// editor closes, with the line endings and byte order mark set in
+/// `config`. The temporary file has a random name and can only be
+/// read by its owner.
+///
+/// # Errors
+///
+/// - The temporary file cannot be created.
+/// - The file cannot be decompressed, compressed, or written.
+/// - The editor cannot be opened.
+fn edit_compressed(editor: &str, name: &str, path: &Path, config: &Config) -> Result<(), Error> {
+    // The extension under `.gz` is kept so editors still recognize the
+    // kind of file.
+    let file_name = path
+        .file_stem()
+        .map_or_else(|| "note".into(), |stem| stem.to_string_lossy());
+    let plain = tempfile::Builder::new()
+        .prefix("scrivener-")
+        .suffix(&format!("-{}", file_name))
+        .tempfile()
+        .with_context(|_| errors::could_not("create a temporary file"))?;
+    let plain_path = plain.path();
+
+    let text = gzip::read(path).with_context(|_| errors::could_not_note("read", name, path))?;
+    fs::write(plain_path, text)
+        .with_context(|_| format!("Could not write {}.", plain_path.display()))?;
+
+    let edited = editor::open(editor, plain_path, true)
+        .with_context(|_| errors::could_not_note("open", name, path))
+        .map_err(Error::from)
+        .and_then(|_| encoding::rewrite(plain_path, config))
+        .and_then(|_| Ok(fs::read(plain_path)?));
+    let _ = plain.close();
+
+    gzip::write(path, &edited?).with_context(|_| errors::could_not_note("write", name, path))?;
+
+    Ok(())
+}
+
+/// Returns a warning if `note`'s file was changed since its
+/// modification time was recorded, so editing it could lose those
+/// changes.
+///
+/// Notes with no recorded time are never warned about.
+fn edit_conflict(note: &Note) -> Option<String> {
+    if note.modified().is_none() || !note.changed_on_disk() {
+        return None;
+    }
+
+    Some(format!(
+        "The file of note `{}` was changed after scrivener last recorded it, \
+         possibly by another program.",
+        note.name()
+    ))
+}
+
+/// Writes the contents of a note to `out`, or only the lines in
+/// `lines`. Files compressed with gzip are decompressed first.
+///
+/// # Errors
+///
+/// - There is no note with the `name` that is given.
+/// - The note's file cannot be read.
+/// - `lines` goes past the end of the file.
+/// - `out` cannot be written to.
+fn show_note(
+    index: &Index,
+    name: &str,
+    lines: Option<LineRange>,
+    frontmatter: Frontmatter,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    let path = match index.get(name) {
+        Some(note) => note.path(),
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+
+    let text =
+        gzip::read_to_string(path).with_context(|_| errors::could_not_note("read", name, path))?;
+    let text = frontmatter.select(&text);
+
+    match lines {
+        Some(lines) => write!(out, "{}", lines.select(text)?)?,
+        None => write!(out, "{}", text)?,
+    }
+
+    Ok(())
+}
+
+/// Which part of a `Note` to print when it starts with frontmatter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frontmatter {
+    /// The whole `Note`
+    Keep,
+
+    /// Everything after the frontmatter
+    Strip,
+
+    /// Only the frontmatter
+    Only,
+}
+
+impl Frontmatter {
+    /// Chooses the part to print from `show`'s flags.
+    fn from_flags(no_frontmatter: bool, frontmatter_only: bool) -> Frontmatter {
+        if no_frontmatter {
+            Frontmatter::Strip
+        } else if frontmatter_only {
+            Frontmatter::Only
+        } else {
+            Frontmatter::Keep
+        }
+    }
+
+    /// Returns the chosen part of `text`. If `text` has no frontmatter,
+    /// it is returned unchanged.
+    fn select(self, text: &str) -> &str {
+        match (self, frontmatter::split(text)) {
+            (Frontmatter::Strip, Some((_, body))) => body,
+            (Frontmatter::Only, Some((block, _))) => block,
+            _ => text,
+        }
+    }
+}
+
+/// Opens a random `Note`, or prints it if `show` is true.
+///
+/// If `tag` is given, only `Note`s with that tag are chosen from. If
+/// `seed` is given, the same `Note` is chosen every time.
+///
+/// # Errors
+///
+/// - The `Note` cannot be opened or read.
+fn random_note(
+    index: &Index,
+    tag: &Option<String>,
+    show: bool,
+    seed: Option<u64>,
+    editor: &str,
+) -> Result<(), Error> {
+    let notes = match tag {
+        Some(tag) => index.search_by_tag(tag),
+        None => index.find_by(|_| true),
+    };
+
+    let note = match choose_note(&notes, seed) {
+        Some(note) => note,
+        None => {
+            match tag {
+                Some(tag) => eprintln!("No notes are tagged `{}`.", tag),
+                None => eprintln!("There are no notes to choose from."),
+            }
+            return Ok(());
+        }
+    };
+
+    if show {
+        return show_note(
+            index,
+            note.name(),
+            None,
+            Frontmatter::Keep,
+            &mut std::io::stdout(),
+        );
+    }
+
+    editor::open(editor, note.path(), true)
+        .with_context(|_| errors::could_not_note("open", note.name(), note.path()))?;
+
+    Ok(())
+}
+
+/// Returns a random `Note` from `notes`, or `None` if it is empty.
+///
+/// The same `seed` always gives the same `Note`.
+fn choose_note<'a>(notes: &[&'a Note], seed: Option<u64>) -> Option<&'a Note> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    notes.choose(&mut rng).copied()
+}
+
+/// Sets a note's modification time to now.
+///
+/// If `touch_file` is true, the modification time of the note's file
+/// is updated as well.
+///
+/// # Errors
+///
+/// - There is no `Note` in the `Index` with the given name.
+///
+/// - The file's modification time cannot be set.
+fn touch_note(
+    index: &mut Index,
+    name: &str,
+    touch_file: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    let note = match index.touch(name) {
+        Some(note) => note,
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+
+    if touch_file {
+        let path = note.path();
+        filetime::set_file_mtime(path, FileTime::now())
+            .with_context(|_| errors::could_not_note("touch", name, path))?;
+    }
+
+    errors::successful(name, "touched", config);
+
+    Ok(())
+}
+
+/// Removes a note from the `Index` WITHOUT deleting the
+/// corresponding file.
+///
+/// # Errors
+///
+/// - There is no `Note` in the `Index` with the given name.
+fn remove_note(index: &mut Index, name: &str, config: &Config) -> Result<(), Error> {
+    failure::ensure!(index.remove(name).is_some(), errors::does_not_exist(name));
+
+    errors::successful(name, "removed", config);
+
+    Ok(())
+}
+
+/// Removes a note from the `Index` AND deletes the corresponding
+/// file, or only removes it if `delete_file` is false.
+///
+/// Asks the user for confirmation before deleting unless `assume_yes`
+/// is true.
+///
+/// If `prune_root` is given, the directories that held the file are
+/// removed too if they are left empty, up to but not including
+/// `prune_root`. See `notes::prune_empty_dirs`.
+///
+/// # Errors
+///
+/// - There is no `Note` in the `Index` with the given name.
+///
+/// - The `Note` cannot be deleted.
+fn delete_note(
+    index: &mut Index,
+    name: &str,
+    delete_file: bool,
+    prune_root: Option<&Path>,
+    assume_yes: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    if !delete_file {
+        return remove_note(index, name, config);
+    }
+
+    let path = match index.get(name) {
+        Some(note) => note.path(),
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+
+    let question = format!("Delete note `{}` and {}?", name, path.display());
+    if !prompt::confirm(&question, assume_yes)? {
+        eprintln!("Note `{}` was not deleted.", name);
+        return Ok(());
+    }
+
+    // The note leaves the index before its file goes, so a failed delete
+    // never leaves an entry behind for a file that no longer exists.
+    let note = index.remove(name).unwrap();
+    let removed = fs::remove_file(note.path())
+        .with_context(|_| errors::could_not_note("delete", name, note.path()));
+    if let Err(err) = removed {
+        index.restore(note);
+        return Err(err.into());
+    }
+
+    if let Some(root) = prune_root {
+        prune_emptied_dirs(note.path(), root, config);
+    }
+
+    errors::successful(name, "deleted", config);
+
+    Ok(())
+}
+
+/// Returns the directory to prune empty directories up to: the
+/// notes_root setting if `prune_dirs` is true, or `None` otherwise.
+///
+/// # Errors
+///
+/// - `prune_dirs` is true and notes_root is not set.
+fn prune_root(prune_dirs: bool, config: &Config) -> Result<Option<&Path>, Error> {
+    if !prune_dirs {
+        return Ok(None);
+    }
+
+    match config.notes_root() {
+        Some(root) => Ok(Some(root)),
+        None => {
+            failure::bail!("Set notes_root with `config notes_root <dir>` to prune directories.")
+        }
+    }
+}
+
+/// Removes the directories that held the file at `path` if they are
+/// left empty, up to but not including `root`, and prints each one
+/// removed. See `notes::prune_empty_dirs`.
+///
+/// The file is already gone by the time this is called, so a directory
+/// that cannot be removed is only warned about.
+fn prune_emptied_dirs(path: &Path, root: &Path, config: &Config) {
+    match notes::prune_empty_dirs(path, root) {
+        Ok(pruned) => {
+            for dir in pruned {
+                errors::success(
+                    &format!("Removed empty directory {}.", dir.display()),
+                    config,
+                );
+            }
+        }
+        Err(err) => eprintln!("Warning: {}", err),
+    }
+}
+
+/// Lists all `Note`s in the `Index` in a table printed to the screen
+/// with or without its relative path and tags.
+///
+/// See `notes_table` for the layout of the table.
+///
+/// If the `Index` is empty, or no notes have the filter tags, then a
+/// helpful message is written to `err` instead. Footers about notes
+/// left out by `max_results` also go to `err`, so that only the notes
+/// themselves are printed to stdout.
+///
+/// # Errors
+///
+/// - The notes or `err` cannot be written to.
+fn list_notes(index: &Index, options: &ListOptions, err: &mut impl Write) -> Result<(), Error> {
+    let mut notes = listed_notes(index, options);
+    let omitted = limit_results(&mut notes, options.max_results);
+
+    // Print the stable fields as JSON, even if there are no notes, and
+    // keep the footer out of the JSON.
+    if options.json {
+        let stdout = std::io::stdout();
+        write_json(&notes, &mut stdout.lock())?;
+        if let Some(footer) = omitted_footer(omitted) {
+            writeln!(err, "{}", footer)?;
+        }
+        return Ok(());
+    }
+
+    // Print every field as JSON, even if there are no notes, and keep
+    // the footer out of the JSON.
+    if options.json_full {
+        let base = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let style = options.path_style.unwrap_or_default();
+        let views: Vec<view::NoteView> = notes
+            .iter()
+            .map(|note| view::NoteView::new(note, &base, style, options.count_mode))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&views)?);
+        if let Some(footer) = omitted_footer(omitted) {
+            writeln!(err, "{}", footer)?;
+        }
+        return Ok(());
+    }
+
+    // Print JSON lines as they are made, even if there are none, and
+    // keep the footer out of the JSON.
+    if options.json_lines {
+        let stdout = std::io::stdout();
+        write_json_lines(&notes, options, &mut stdout.lock())?;
+        if let Some(footer) = omitted_footer(omitted) {
+            writeln!(err, "{}", footer)?;
+        }
+        return Ok(());
+    }
+
+    // If index has no notes, print a helpful message and return.
+    if index.notes().is_empty() {
+        writeln!(err, "There are no notes to list!")?;
+        writeln!(err, "Create one with 'srcv new <name>'")?;
+        writeln!(err, "Try 'srcv --help' for more options.")?;
+        return Ok(());
+    }
+
+    if notes.is_empty() && omitted == 0 && !options.filter_tags.is_empty() {
+        writeln!(err, "No notes matched the given tags.")?;
+        return Ok(());
+    }
+
+    // Print the table
+    notes_table(&notes, options).printstd();
+    if let Some(footer) = omitted_footer(omitted) {
+        writeln!(err, "{}", footer)?;
+    }
+
+    Ok(())
+}
+
+/// Shortens `results` to at most `max` items, if given, and returns how
+/// many were left out.
+fn limit_results<T>(results: &mut Vec<T>, max: Option<usize>) -> usize {
+    let max = max.unwrap_or(usize::MAX);
+    let omitted = results.len().saturating_sub(max);
+    results.truncate(max);
+    omitted
+}
+
+/// Returns the line printed after results that were cut short by
+/// `--max-results`, or `None` if nothing was left out.
+fn omitted_footer(omitted: usize) -> Option<String> {
+    if omitted == 0 {
+        None
+    } else {
+        Some(format!("…and {} more", omitted))
+    }
+}
+
+/// Records the current modification times and word counts of the
+/// `Note`s that `list` shows with `options`, including only the first
+/// `max_results` of them like `list_notes`. See `Index::refresh`.
+fn refresh_notes(index: &mut Index, options: &ListOptions, config: &Config) {
+    let mut notes = listed_notes(index, options);
+    limit_results(&mut notes, options.max_results);
+    let names: Vec<String> = notes.iter().map(|note| note.name().to_string()).collect();
+
+    for name in &names {
+        index.refresh(name, config.count_mode);
+    }
+
+    errors::success(&format!("Refreshed {} notes.", names.len()), config);
+}
+
+/// Prints the name, path, tags, file size, and modification and
+/// creation times of a `Note`, followed by how many lines and words its
+/// file has if it can be read.
+///
+/// The path is also shown relative to the current directory in the
+/// `path_style` setting, and the times are shown as how long ago they
+/// were if the `relative_time` setting is on.
+///
+/// If `check_links` is true, this also prints each note that it links
+/// to, marking those that do not exist, and each note that links to it.
+///
+/// # Errors
+///
+/// - There is no note with the `name` that is given.
+/// - `check_links` is true and the note's file cannot be read or links
+///   to a note that does not exist.
+fn show_info(index: &Index, name: &str, check_links: bool, config: &Config) -> Result<(), Error> {
+    let note = match index.get(name) {
+        Some(note) => note,
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+    let relative_time = config.relative_time;
+
+    println!("Name: {}", note.name());
+    println!("Path: {}", note.path().display());
+    println!(
+        "Relative path: {}",
+        abs_to_rel(note.path(), config.path_style)
+    );
+    match note.tags() {
+        Some(tags) if !tags.is_empty() => println!("Tags: {}", tags.join(", ")),
+        _ => println!("Tags: none"),
+    }
+    match note.size() {
+        Some(size) => println!("Size: {} bytes", size),
+        None => println!("Size: unknown (the file is missing)"),
+    }
+    match note.modified() {
+        Some(time) => println!(
+            "Modified: {}",
+            time::format_timestamp(time, SystemTime::now(), relative_time)
+        ),
+        None => println!("Modified: unknown"),
+    }
+    match note.created() {
+        Some(time) => println!(
+            "Created: {}",
+            time::format_timestamp(time, SystemTime::now(), relative_time)
+        ),
+        None => println!("Created: unknown"),
+    }
+
+    if let Some((lines, words)) = line_and_word_counts(note, config.count_mode) {
+        println!("\nContents:");
+        println!("  Lines: {}", lines);
+        println!("  {}: {}", config.count_mode.unit(), words);
+    }
+
+    if !check_links {
+        return Ok(());
+    }
+
+    let links = outgoing_links(index, note)?;
+    println!("\nLinks to:");
+    for (link, exists) in &links {
+        if *exists {
+            println!("  {}", link);
+        } else {
+            println!("  {} (missing)", link);
+        }
+    }
+
+    println!("\nLinked from:");
+    for source in index.incoming_links(name) {
+        println!("  {}", source.name());
+    }
+
+    let broken = links.iter().filter(|(_, exists)| !exists).count();
+    failure::ensure!(broken == 0, "Note `{}` has {} broken links.", name, broken);
+
+    Ok(())
+}
+
+/// Returns how many lines and words or graphemes, depending on `mode`,
+/// `note`'s file has, or `None` if it cannot be read.
+fn line_and_word_counts(note: &Note, mode: CountMode) -> Option<(usize, usize)> {
+    let text = gzip::read_to_string(note.path()).ok()?;
+    Some((text.lines().count(), notes::count(&text, mode)))
+}
+
+/// Returns the name of each note that `note` links to, along with
+/// whether a note with that name exists.
+///
+/// # Errors
+///
+/// - The note's file cannot be read.
+fn outgoing_links(index: &Index, note: &Note) -> Result<Vec<(String, bool)>, Error> {
+    let links = note
+        .links()
+        .with_context(|_| errors::could_not_note("read", note.name(), note.path()))?;
+
+    Ok(links
+        .into_iter()
+        .map(|link| {
+            let exists = index.contains(&link);
+            (link, exists)
+        })
+        .collect())
+}
+
+/// Writes the name of each `Note` that has every tag in `filter_tags`
+/// to `out`, one per line.
+///
+/// # Errors
+///
+/// - `out` cannot be written to.
+fn write_names(index: &Index, filter_tags: &[String], out: &mut impl Write) -> Result<(), Error> {
+    for note in index.notes() {
+        write_name(note, filter_tags, out)?;
+    }
+
+    Ok(())
+}
+
+/// Does what `write_names` does for the `Index` stored at
+/// `index_path`, reading its `Note`s one at a time instead of loading
+/// it. See `Index::stream_path`.
+///
+/// # Errors
+///
+/// - The `Index` cannot be read.
+/// - `out` cannot be written to.
+pub(super) fn stream_names(
+    index_path: &Path,
+    filter_tags: &[String],
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    Index::stream_path(index_path, |note| write_name(&note, filter_tags, out))
+}
+
+/// Writes the name of `note` to `out` on its own line if it has every
+/// tag in `filter_tags`.
+///
+/// # Errors
+///
+/// - `out` cannot be written to.
+fn write_name(note: &Note, filter_tags: &[String], out: &mut impl Write) -> Result<(), Error> {
+    if filter_tags.iter().all(|tag| note.has_tag(tag, false)) {
+        writeln!(out, "{}", note.name())?;
+    }
+
+    Ok(())
+}
+
+/// Writes each `Note` to `out` as a JSON object on its own line,
+/// flushing after every line so the output can be read as it is
+/// written.
+///
+/// Paths and tags are only included if `show_paths` and `show_tags`
+/// are true.
+///
+/// # Errors
+///
+/// - `out` cannot be written to.
+fn write_json_lines(
+    notes: &[&Note],
+    options: &ListOptions,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    for note in notes {
+        let mut object = serde_json::Map::new();
+        object.insert("name".to_string(), note.name().into());
+
+        if options.show_paths {
+            let path = listed_path(note.path(), options);
+            object.insert("path".to_string(), path.into());
+        }
+
+        if options.show_tags {
+            let tags = note.tags().clone().unwrap_or_default();
+            object.insert("tags".to_string(), tags.into());
+        }
+
+        writeln!(out, "{}", serde_json::Value::Object(object))?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes `notes` to `out` as an indented JSON array of their names,
+/// paths as stored, and tags.
+///
+/// # Errors
+///
+/// - `out` cannot be written to.
+fn write_json(notes: &[&Note], out: &mut impl Write) -> Result<(), Error> {
+    let summaries: Vec<view::NoteSummary> = notes
+        .iter()
+        .map(|note| view::NoteSummary::new(note))
+        .collect();
+    writeln!(out, "{}", serde_json::to_string_pretty(&summaries)?)?;
+    Ok(())
+}
+
+/// Returns `path` as stored if `absolute_paths` is true, otherwise
+/// relative to the current directory in the chosen `path_style`.
+fn listed_path(path: &Path, options: &ListOptions) -> String {
+    if options.absolute_paths {
+        path.display().to_string()
+    } else {
+        abs_to_rel(path, options.path_style.unwrap_or_default())
+    }
+}
+
+/// Returns the `Note`s that have every tag in `filter_tags`, or any of
+/// them if `any` is true, and only those with empty files if `empty` is true and those changed on disk
+/// if `changed` is true, in the order given by `sort`.
+fn listed_notes<'a>(index: &'a Index, options: &ListOptions) -> Vec<&'a Note> {
+    let mut notes = index.find_by(|note| {
+        let mut filter_tags = options.filter_tags.iter();
+        let has_tag = |tag: &String| note.has_tag(tag, options.partial);
+        let tagged = if options.any {
+            filter_tags.any(has_tag)
+        } else {
+            filter_tags.all(has_tag)
+        };
+
+        (tagged || options.filter_tags.is_empty())
+            && (!options.empty || note.size() == Some(0))
+            && (!options.changed || note.changed_on_disk())
+    });
+
+    // Notes start alphabetical, and the sorts are stable, so ties stay
+    // that way. Notes without an order sort first. Sections ignore
+    // case, so names do too when they are shown.
+    match options.sort {
+        ListSort::Name if options.sections => notes.sort_by_key(|note| note.name().to_lowercase()),
+        ListSort::Name => {}
+        ListSort::Added => notes.sort_by_key(|note| note.order()),
+        ListSort::Path => notes.sort_by_key(|note| note.path()),
+        ListSort::Tags => notes.sort_by_key(|note| note.tags().as_ref().map_or(0, Vec::len)),
+    }
+
+    if options.reverse {
+        notes.reverse();
+    }
+
+    // Keep each section together, so that every heading appears once,
+    // whatever the notes are sorted by.
+    if options.sections {
+        notes.sort_by(|a, b| {
+            let order = section_of(a.name()).cmp(&section_of(b.name()));
+            if options.reverse {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+
+    if options.distinct_paths {
+        let groups = names_by_path(&notes);
+        let mut grouped = Vec::with_capacity(notes.len());
+        for note in &notes {
+            if !grouped.contains(note) {
+                let group = &groups[&canonical_path(note.path())];
+                grouped.extend(notes.iter().filter(|other| group.contains(&other.name())));
+            }
+        }
+        notes = grouped;
+    }
+
+    notes
+}
+
+/// Returns `path` with symbolic links and relative parts resolved, or
+/// unchanged if its file does not exist.
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Groups the names of `notes` by the canonical path of their files.
+fn names_by_path<'a>(notes: &[&'a Note]) -> BTreeMap<PathBuf, Vec<&'a str>> {
+    let mut groups: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for note in notes {
+        groups
+            .entry(canonical_path(note.path()))
+            .or_default()
+            .push(note.name());
+    }
+    groups
+}
+
+/// Builds a table of the given `Note`s.
+///
+/// If `show_paths` and `show_tags` are both false, then the table
+/// will have only one column that shows the `Note`s' names
+///
+/// If either `show_paths` or `show_tags` are true, the table will
+/// have two columns, one for the names and one for either the paths
+/// or tags, respectively.
+///
+/// If both `show_paths` and `show_tags` are true, then the table
+/// will have tree columns, with names, paths, and tags.
+///
+/// Paths are shown relative to the current working directory unless
+/// `absolute_paths` is true.
+///
+/// If `distinct_paths` is true, a last column lists the notes that
+/// share each `Note`'s file, if there are several.
+///
+/// The row of column titles is left out if `no_header` is true.
+///
+/// Columns are shortened to fit `width` and `max_col` if they are
+/// given. See `fit_columns`.
+fn notes_table(notes: &[&Note], options: &ListOptions) -> Table {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    // Add a cell to the title row that says "Notes" in bold.
+    let mut title = Row::new(vec![Cell::new("Notes").with_style(Attr::Bold)]);
+
+    // If show_paths is true, add a cell to the title row that says
+    // "Paths" in bold.
+    if options.show_paths {
+        title.add_cell(Cell::new("Paths").with_style(Attr::Bold));
+    }
+
+    // If show_tags is true, add a cell to the title row that says
+    // "Tags" in bold.
+    if options.show_tags {
+        title.add_cell(Cell::new("Tags").with_style(Attr::Bold));
+    }
+
+    // If show_created or show_modified is true, add a cell to the title
+    // row that says "Created" or "Modified" in bold.
+    if options.show_created {
+        title.add_cell(Cell::new("Created").with_style(Attr::Bold));
+    }
+    if options.show_modified {
+        title.add_cell(Cell::new("Modified").with_style(Attr::Bold));
+    }
+
+    // If distinct_paths is true, add a cell to the title row that says
+    // "Shared" in bold.
+    if options.distinct_paths {
+        title.add_cell(Cell::new("Shared").with_style(Attr::Bold));
+    }
+    let titles: Vec<String> = title.iter().map(Cell::get_content).collect();
+
+    // The names of the listed notes that share each file
+    let sharing = if options.distinct_paths {
+        names_by_path(notes)
+    } else {
+        BTreeMap::new()
+    };
+
+    // Leave out the title row if no_header is true.
+    if !options.no_header {
+        table.set_titles(title);
+    }
+
+    // The section of the previous note, if sections are shown
+    let mut previous_section = None;
+
+    // For every note in the index
+    for note in notes {
+        // If sections is true and the note starts a new section, add a
+        // row with the section's letter in bold.
+        if options.sections {
+            let section = section_of(note.name());
+            if previous_section.as_ref() != Some(&section) {
+                table.add_row(Row::new(vec![Cell::new(&section).with_style(Attr::Bold)]));
+                previous_section = Some(section);
+            }
+        }
+
+        // Initialize a row with the note's name in the first cell.
+        let mut row = Row::new(vec![Cell::new(note.name())]);
+
+        // If show_paths is true
+        if options.show_paths {
+            // Use the stored path as-is if absolute_paths is true,
+            // otherwise make it relative to the current directory.
+            let path = listed_path(note.path(), options);
+
+            // Add the path to the row.
+            row.add_cell(Cell::new(&path));
+        }
+
+        // If show_tags is true
+        if options.show_tags {
+            let tags = note.tags();
+
+            // If the note has tags associated with it
+            if let Some(tags) = tags {
+                // Sort the tags alphabetically if sort_tags is true,
+                // otherwise keep them in the order they were stored.
+                let mut tags: Vec<&String> = tags.iter().collect();
+                if options.sort_tags {
+                    tags.sort_by_key(|tag| (tag.to_lowercase(), tag.as_str()));
+                }
+
+                // Initialize tag_list as a new String.
+                let mut tag_list = String::new();
+
+                // Then, split the tag list into the first element and
+                // every other element
+                if let Some((first, rest)) = tags.split_first() {
+                    // Push the first tag to tag_list
+                    tag_list.push_str(first);
+
+                    // For any the remaining tags
+                    for tag in rest {
+                        // Append it to tag_list after a comma and a newline.
+                        tag_list.push_str(&format!(",\n{}", tag));
+                    }
+                }
+                // Add tag_list to the row
+                row.add_cell(Cell::new(&tag_list));
+            } else {
+                // Else, add an empty string to the row
+                row.add_cell(Cell::new(""));
+            }
+        }
+
+        // Add the creation and modification times if they are shown,
+        // leaving the cell empty for notes that did not record them.
+        let now = SystemTime::now();
+        let times = [
+            (options.show_created, note.created()),
+            (options.show_modified, note.modified()),
+        ];
+        for (shown, recorded) in &times {
+            if *shown {
+                let text =
+                    recorded.map(|time| time::format_timestamp(time, now, options.relative_time));
+                row.add_cell(Cell::new(&text.unwrap_or_default()));
+            }
+        }
+
+        // If distinct_paths is true, list every note sharing the file
+        // if there are several.
+        if options.distinct_paths {
+            match sharing.get(&canonical_path(note.path())) {
+                Some(names) if names.len() > 1 => {
+                    row.add_cell(Cell::new(&format!("shared by: {}", names.join(", "))))
+                }
+                _ => row.add_cell(Cell::new("")),
+            }
+        }
+
+        // Add the row to the table
+        table.add_row(row);
+    }
+
+    // Shorten the columns if a width or maximum column width is given.
+    if options.width.is_some() || options.max_col.is_some() {
+        let floors: Vec<usize> = titles
+            .iter()
+            .map(|title| if options.no_header { 1 } else { title.len() })
+            .collect();
+
+        fit_columns(&mut table, &floors, options.width, options.max_col);
+    }
+
+    table
+}
+
+/// Shortens the cells of `table` so that no column is wider than
+/// `max_col` and no line is wider than `width`, ending shortened lines
+/// with `…`.
+///
+/// To fit `width`, the widest column is shortened one character at a
+/// time, but never below its entry in `floors`, which has one entry
+/// per column.
+fn fit_columns(table: &mut Table, floors: &[usize], width: Option<usize>, max_col: Option<usize>) {
+    let line_width = |text: &str| text.lines().map(|line| line.chars().count()).max();
+
+    let mut limits: Vec<usize> = (0..floors.len())
+        .map(|column| {
+            // `column_iter` stops at the first row without the column,
+            // such as a section heading, so look at every row instead.
+            let widest = table
+                .row_iter()
+                .filter_map(|row| row.get_cell(column))
+                .filter_map(|cell| line_width(&cell.get_content()))
+                .max()
+                .unwrap_or(0);
+            widest
+                .min(max_col.unwrap_or(usize::MAX))
+                .max(floors[column])
+        })
+        .collect();
+
+    if let Some(width) = width {
+        // Each cell is padded by a space on both sides, and columns are
+        // separated by a single character.
+        let available = width.saturating_sub(3 * limits.len() - 1);
+
+        while limits.iter().sum::<usize>() > available {
+            let widest = limits
+                .iter()
+                .enumerate()
+                .filter(|&(column, &limit)| limit > floors[column])
+                .max_by_key(|&(_, &limit)| limit)
+                .map(|(column, _)| column);
+
+            match widest {
+                Some(column) => limits[column] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    for row in table.row_iter_mut() {
+        for (column, cell) in row.iter_mut().enumerate() {
+            let text = cell.get_content();
+            let fitted: Vec<String> = text
+                .lines()
+                .map(|line| truncate(line, limits[column]))
+                .collect();
+            let fitted = fitted.join("\n");
+
+            if fitted != text {
+                *cell = Cell::new(&fitted);
+            }
+        }
+    }
+}
+
+/// Returns `line` shortened to `limit` characters, ending with `…` if
+/// anything was cut off.
+fn truncate(line: &str, limit: usize) -> String {
+    if line.chars().count() <= limit {
+        return line.to_string();
+    }
+
+    let mut short: String = line.chars().take(limit.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+/// Returns the section a `Note` named `name` is listed under: its
+/// first letter in uppercase, or "#" if it does not start with a letter.
+fn section_of(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+/// Prints a table of every `Note` whose name or tags contain `query`,
+/// ignoring case, with the most relevant first. `scope` limits where
+/// plain text is looked for, and may match it as a regular expression.
+///
+/// If `json` is true, the matches are printed as a JSON array instead.
+/// If `max_results` is given, only that many matches are used.
+/// If `open` is true, the matching `Note` is edited in `editor` with
+/// `config` instead, as by `edit_note`. If several `Note`s match, the
+/// user chooses one from a list, unless the terminal is not
+/// interactive, in which case the matches are printed as usual.
+///
+/// # Errors
+///
+/// - The chosen `Note` cannot be opened.
+#[allow(clippy::too_many_arguments)]
+fn search_notes(
+    index: &mut Index,
+    query: &str,
+    scope: &SearchScope,
+    open: bool,
+    color: bool,
+    json: bool,
+    max_results: Option<usize>,
+    editor: &str,
+    config: &Config,
+) -> Result<(), Error> {
+    let parsed = if scope.regex.is_some() {
+        Query::Text(query.to_string())
+    } else {
+        query.parse()?
+    };
+    let mut matches = matching_notes(index, &parsed, scope);
+    let omitted = limit_results(&mut matches, max_results);
+    let footer = omitted_footer(omitted);
+
+    if json {
+        let objects: Vec<serde_json::Value> = matches.iter().map(match_json).collect();
+        println!("{}", serde_json::to_string_pretty(&objects)?);
+        if let Some(footer) = footer {
+            eprintln!("{}", footer);
+        }
+        return Ok(());
+    }
+
+    if matches.is_empty() {
+        eprintln!("No notes matched `{}`.", query);
+        return Ok(());
+    }
+
+    if open {
+        let interactive = atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stdout);
+
+        let chosen = if matches.len() == 1 {
+            Some(0)
+        } else if interactive {
+            let names: Vec<&str> = matches.iter().map(|found| found.note.name()).collect();
+            prompt::choose("Which note should be opened?", &names)?
+        } else {
+            None
+        };
+
+        if let Some(chosen) = chosen {
+            let name = matches[chosen].note.name().to_string();
+            return edit_note(index, &name, editor, true, false, false, config);
+        }
+    }
+
+    search_table(&matches, color).printstd();
+    if let Some(footer) = footer {
+        eprintln!("{}", footer);
+    }
+
+    Ok(())
+}
+
+/// Where `search` looks for plain text, and how it matches it.
+#[derive(Debug, Default)]
+struct SearchScope {
+    /// Only look in names
+    name_only: bool,
+
+    /// Only look in tags
+    tags_only: bool,
+
+    /// The regular expression to match instead of plain text
+    regex: Option<Regex>,
+}
+
+impl SearchScope {
+    /// Creates a `SearchScope` from `search`'s flags, compiling `query`
+    /// if `regex` is true.
+    ///
+    /// # Errors
+    ///
+    /// - `regex` is true and `query` is not a valid regular expression.
+    fn new(
+        name_only: bool,
+        tags_only: bool,
+        regex: bool,
+        query: &str,
+    ) -> Result<SearchScope, Error> {
+        let regex = if regex {
+            let regex = RegexBuilder::new(query)
+                .case_insensitive(true)
+                .build()
+                .with_context(|_| format!("`{}` is not a valid regular expression.", query))?;
+            Some(regex)
+        } else {
+            None
+        };
+
+        Ok(SearchScope {
+            name_only,
+            tags_only,
+            regex,
+        })
+    }
+
+    /// Returns `true` if plain text is looked for in names.
+    fn names(&self) -> bool {
+        self.name_only || !self.tags_only
+    }
+
+    /// Returns `true` if plain text is looked for in tags.
+    fn tags(&self) -> bool {
+        self.tags_only || !self.name_only
+    }
+
+    /// Returns the byte range of the first match of `query` in `text`,
+    /// or of the regular expression if there is one.
+    fn span(&self, text: &str, query: &str) -> Option<Range<usize>> {
+        match &self.regex {
+            Some(regex) => regex.find(text).map(|found| found.range()),
+            None => highlight::match_span(text, query),
+        }
+    }
+}
+
+/// A `Note` that matched a search, along with where it matched.
+struct SearchMatch<'a> {
+    /// The `Note` that matched
+    note: &'a Note,
+
+    /// The part of the `Note`'s name that matched, if any
+    name: Option<Range<usize>>,
+
+    /// Each of the `Note`'s tags that matched, with the part that
+    /// matched
+    tags: Vec<(&'a str, Range<usize>)>,
+
+    /// How closely the `Note` matched, which is the best of
+    /// `highlight::relevance` for its name and tags
+    score: u32,
+}
+
+/// Returns the object `search --json` prints for `found`: the `Note`'s
+/// name, path, and all of its tags, the tags that matched, and the
+/// relevance score.
+fn match_json(found: &SearchMatch) -> serde_json::Value {
+    serde_json::json!({
+        "name": found.note.name(),
+        "path": found.note.path(),
+        "tags": found.note.tags().iter().flatten().collect::<Vec<_>>(),
+        "matched_tags": found.tags.iter().map(|(tag, _)| tag).collect::<Vec<_>>(),
+        "score": found.score,
+    })
+}
+
+/// Returns every `Note` that matches `query` within `scope`, along
+/// with where each one matched.
+///
+/// The most relevant `Note`s come first, and those that are as relevant
+/// as each other are in alphabetical order by name.
+fn matching_notes<'a>(
+    index: &'a Index,
+    query: &Query,
+    scope: &SearchScope,
+) -> Vec<SearchMatch<'a>> {
+    let wanted = query.wanted();
+
+    let mut matches: Vec<SearchMatch> = index
+        .notes()
+        .iter()
+        .filter_map(|note| {
+            let name = wanted.iter().find_map(|term| match term {
+                Query::Text(text) if scope.names() => scope.span(note.name(), text),
+                Query::Name(text) => highlight::match_span(note.name(), text),
+                _ => None,
+            });
+            let tags: Vec<(&str, Range<usize>)> = note
+                .tags()
+                .iter()
+                .flatten()
+                .filter_map(|tag| {
+                    let span = wanted.iter().find_map(|term| match term {
+                        Query::Text(text) if scope.tags() => scope.span(tag, text),
+                        Query::Tag(wanted) if tag.to_lowercase() == wanted.to_lowercase() => {
+                            Some(0..tag.len())
+                        }
+                        _ => None,
+                    });
+                    span.map(|span| (tag.as_str(), span))
+                })
+                .collect();
+
+            // Notes that only matched by path count as loosely as
+            // possible.
+            let name_score = name
+                .as_ref()
+                .map(|span| highlight::relevance(note.name(), span));
+            let score = tags
+                .iter()
+                .map(|(tag, span)| highlight::relevance(tag, span))
+                .chain(name_score)
+                .max()
+                .unwrap_or(1);
+
+            // Plain text only matches where `scope` looks for it.
+            let matched = match query {
+                Query::Text(_) => name.is_some() || !tags.is_empty(),
+                query => query.matches(note),
+            };
+
+            if !matched {
+                return None;
+            }
+
+            Some(SearchMatch {
+                note,
+                name,
+                tags,
+                score,
+            })
+        })
+        .collect();
+
+    matches.sort_by_key(|found| std::cmp::Reverse(found.score));
+    matches
+}
+
+/// Returns a `Table` of the matched `Note`s, with the matched part of
+/// each name and tag highlighted if `color` is true.
+///
+/// A column of matched tags is only added if some `Note` matched by
+/// tag.
+fn search_table(matches: &[SearchMatch], color: bool) -> Table {
+    let mut table = Table::new();
+
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    let show_tags = matches.iter().any(|found| !found.tags.is_empty());
+
+    let mut title = Row::new(vec![Cell::new("Notes").with_style(Attr::Bold)]);
+    if show_tags {
+        title.add_cell(Cell::new("Matched Tags").with_style(Attr::Bold));
+    }
+    table.set_titles(title);
+
+    for found in matches {
+        let name = highlight::highlight(found.note.name(), &found.name, color);
+        let mut row = Row::new(vec![Cell::new(&name)]);
+
+        if show_tags {
+            let tags: Vec<String> = found
+                .tags
+                .iter()
+                .map(|(tag, span)| highlight::highlight(tag, &Some(span.clone()), color))
+                .collect();
+            row.add_cell(Cell::new(&tags.join(", ")));
+        }
+
+        table.add_row(row);
+    }
+
+    table
+}
+
+/// Adds every plaintext file in `dir` to the `Index`, after asking the
+/// user for confirmation unless `assume_yes` is true.
+///
+/// See `Index::from_directory` and `Index::merge` for how the files
+/// are found and named.
+///
+/// # Errors
+///
+/// - `dir` or one of its subdirectories cannot be read.
+/// - A pattern or file in `ignore` is not valid.
+fn scan_directory(
+    index: &mut Index,
+    dir: &Path,
+    recursive: bool,
+    ignore: &ScanIgnore,
+    infer: &InferOptions,
+    config: &Config,
+    assume_yes: bool,
+) -> Result<(), Error> {
+    let progress = Progress::on_stderr("Scanning", config.quiet);
+    let scanned = Index::from_directory_with(
+        dir,
+        recursive,
+        config.strip_all_extensions,
+        ignore,
+        |done, total| progress.update(done, total),
+    );
+    progress.finish();
+    let mut scanned = scanned?;
+
+    for note in index.notes() {
+        scanned.remove_path(note.path());
+    }
+
+    let paths: Vec<(String, PathBuf)> = scanned
+        .notes()
+        .iter()
+        .map(|note| (note.name().to_string(), note.path().clone()))
+        .collect();
+    for (name, path) in paths {
+        scanned.add_tags(&name, &infer.tags(&path, config)?);
+    }
+
+    if scanned.notes().is_empty() {
+        eprintln!("No new plaintext files found in {}.", dir.display());
+        return Ok(());
+    }
+
+    let question = format!(
+        "Add {} notes from {}?",
+        scanned.notes().len(),
+        dir.display()
+    );
+    if !prompt::confirm(&question, assume_yes)? {
+        eprintln!("No notes were added.");
+        return Ok(());
+    }
+
+    let added = index.merge(scanned);
+
+    errors::success(
+        &format!("Added {} notes: {}", added.len(), added.join(", ")),
+        config,
+    );
+
+    Ok(())
+}
+
+/// Returns where the file of `note` belongs according to the tag_dirs
+/// setting, or `None` if it has no such tag or is already there.
+fn misplaced(note: &Note, config: &Config) -> Option<PathBuf> {
+    let dir = config.dir_for(note.tags())?;
+
+    if notes::path_under_root(note.path(), &dir) {
+        None
+    } else {
+        Some(dir)
+    }
+}
+
+/// Prints a warning if the file of the `Note` named `name` is not in
+/// the directory set for its tags.
+fn warn_if_misplaced(index: &Index, name: &str, config: &Config) {
+    if let Some(dir) = index.get(name).and_then(|note| misplaced(note, config)) {
+        eprintln!(
+            "Warning: note `{}` belongs in {} because of its tags. Run `organize` to move it.",
+            name,
+            dir.display()
+        );
+    }
+}
+
+/// Moves the file of every `Note` that is not in the directory set for
+/// its tags into that directory, after asking the user for
+/// confirmation unless `assume_yes` is true.
+///
+/// Files that would replace an existing file are left where they are.
+/// Every `Note` that shares a moved file is pointed at its new path. If
+/// a file cannot be moved, the moves already made are undone.
+///
+/// # Errors
+///
+/// - A directory cannot be created or a file cannot be moved.
+fn organize_notes(index: &mut Index, config: &Config, assume_yes: bool) -> Result<(), Error> {
+    let moves: Vec<(String, PathBuf, PathBuf)> = index
+        .notes()
+        .iter()
+        .filter_map(|note| {
+            let dir = misplaced(note, config)?;
+            let target = dir.join(note.path().file_name()?);
+            Some((note.name().to_string(), note.path().clone(), target))
+        })
+        .collect();
+
+    if moves.is_empty() {
+        eprintln!("Every note is already in its tag's directory.");
+        return Ok(());
+    }
+
+    let question = format!("Move {} notes into their tags' directories?", moves.len());
+    if !prompt::confirm(&question, assume_yes)? {
+        eprintln!("No notes were moved.");
+        return Ok(());
+    }
+
+    transaction::run(index, |index, transaction| {
+        let mut moved = 0;
+        for (name, from, to) in moves {
+            // A note whose file it shares was moved already, and took
+            // this note along with it.
+            if index.get(&name).is_some_and(|note| note.path() != &from) {
+                continue;
+            }
+
+            if to.exists() {
+                eprintln!(
+                    "Warning: not moving note `{}` because {} already exists.",
+                    name,
+                    to.display()
+                );
+                continue;
+            }
+
+            if let Some(dir) = to.parent() {
+                transaction.create_dir_all(dir)?;
+            }
+            transaction
+                .rename(&from, &to)
+                .with_context(|_| errors::could_not_note("move", &name, &from))?;
+
+            for name in index.move_path(&from, &to)? {
+                errors::success(&format!("Moved `{}` to {}.", name, to.display()), config);
+                moved += 1;
+            }
+        }
+
+        errors::success(&format!("Organized {} notes.", moved), config);
+
+        Ok(())
+    })
+}
+
+/// Prints statistics about the `Note`s in the `Index`, followed by
+/// the number of `Note`s with each tag.
+fn show_stats(index: &Index, config: &Config) -> Result<(), Error> {
+    let mode = config.count_mode;
+    let progress = Progress::on_stderr(
+        &format!("Counting {}", mode.unit().to_lowercase()),
+        config.quiet,
+    );
+    let stats = index.stats_with(mode, |done, total| progress.update(done, total));
+    progress.finish();
+
+    println!("Notes:         {}", stats.total);
+    println!("Tagged:        {}", stats.tagged);
+    println!("Untagged:      {}", stats.untagged);
+    println!("{:<15}{}", format!("{}:", mode.unit()), stats.total_words);
+    println!("Missing files: {}", stats.missing_files);
+
+    if !stats.tag_counts.is_empty() {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_CLEAN);
+        table.set_titles(Row::new(vec![
+            Cell::new("Tags").with_style(Attr::Bold),
+            Cell::new("Notes").with_style(Attr::Bold),
+        ]));
+
+        for (tag, count) in &stats.tag_counts {
+            table.add_row(Row::new(vec![
+                Cell::new(tag),
+                Cell::new(&count.to_string()),
+            ]));
+        }
+
+        println!();
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+/// Prints groups of `Note`s whose files have identical contents.
+///
+/// If `merge` is true, the first `Note` of each group absorbs the
+/// others' tags, and the others are removed along with their files,
+/// after asking the user for confirmation unless `assume_yes` is true.
+/// If a file cannot be deleted, the whole merge is undone.
+///
+/// # Errors
+///
+/// - A duplicate file cannot be deleted.
+fn find_duplicates(
+    index: &mut Index,
+    merge: bool,
+    assume_yes: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    let unreadable = index.find_by(|note| File::open(note.path()).is_err()).len();
+    if unreadable > 0 {
+        eprintln!(
+            "Warning: skipped {} notes whose files could not be read.",
+            unreadable
+        );
+    }
+
+    // Collect the names and paths so that the index can be changed.
+    let groups: Vec<Vec<(String, PathBuf)>> = index
+        .content_duplicates()
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|note| (note.name().to_string(), note.path().clone()))
+                .collect()
+        })
+        .collect();
+
+    if groups.is_empty() {
+        eprintln!("No notes have identical contents.");
+        return Ok(());
+    }
+
+    for group in &groups {
+        let names: Vec<&str> = group.iter().map(|(name, _)| name.as_str()).collect();
+        println!("{}", names.join(", "));
+    }
+
+    if !merge {
+        return Ok(());
+    }
+
+    let extra: usize = groups.iter().map(|group| group.len() - 1).sum();
+    let question = format!("Delete {} duplicate notes and their files?", extra);
+    if !prompt::confirm(&question, assume_yes)? {
+        eprintln!("No notes were merged.");
+        return Ok(());
+    }
+
+    transaction::run(index, |index, transaction| {
+        for group in &groups {
+            let (keep, keep_path) = &group[0];
+
+            for (name, path) in &group[1..] {
+                // Two notes can share a file, which must then be kept.
+                if path != keep_path {
+                    transaction
+                        .remove_file(path)
+                        .with_context(|_| errors::could_not_note("delete", name, path))?;
+                }
+
+                index.absorb(keep, name);
+            }
+
+            errors::success(
+                &format!("Merged {} notes into `{}`.", group.len() - 1, keep),
+                config,
+            );
+        }
+
+        Ok(())
+    })
+}
+
+/// Prints the completion script for `shell`, or installs it where the
+/// shell loads completions from if `install` is true. See
+/// `completions::install`.
+///
+/// # Errors
+///
+/// - `install` is true and the script cannot be installed.
+pub(super) fn completions(shell: Shell, install: bool) -> Result<(), Error> {
+    if !install {
+        completions::write(shell, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    let dirs = match BaseDirs::new() {
+        Some(dirs) => dirs,
+        None => failure::bail!("Could not find the home directory."),
+    };
+    let path = completions::install(shell, dirs.data_dir(), dirs.config_dir())?;
+
+    eprintln!("Completions installed to {}.", path.display());
+    if let Shell::Zsh = shell {
+        if let Some(dir) = path.parent() {
+            eprintln!(
+                "Make sure {} is in your fpath before compinit runs.",
+                dir.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Shows or changes the settings stored at `path`.
+///
+/// Prints every setting if `key` is `None`, prints the setting named
+/// `key` if `value` is `None`, and otherwise sets it to `value`.
+///
+/// # Errors
+///
+/// - `key` is not a setting.
+/// - `value` is not valid for the setting.
+/// - The settings cannot be read or written.
+pub(super) fn configure(
+    path: &Path,
+    key: Option<&str>,
+    value: Option<&str>,
+    quiet: bool,
+) -> Result<(), Error> {
+    let mut config = Config::load_path(path)?;
+
+    match (key, value) {
+        (None, _) => {
+            for (key, value) in config.settings() {
+                println!("{} = {}", key, value);
+            }
+        }
+        (Some(key), None) => println!("{}", config.get(key)?),
+        (Some(key), Some(value)) => {
+            config.set(key, value)?;
+            config.store_path(path)?;
+            let message = format!("Set {} to {}.", key, config.get(key)?);
+            config.quiet |= quiet;
+            errors::success(&message, &config);
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the `Index` at `old_path` to `new_path`, rewriting it in
+/// `format`, and stores a copy of `config` using `format` at
+/// `config_path`.
+///
+/// # Errors
+///
+/// - The `Index` cannot be read or written.
+/// - The settings cannot be written.
+pub(super) fn convert_index(
+    config: &Config,
+    config_path: &Path,
+    old_path: &Path,
+    new_path: &Path,
+    format: IndexFormat,
+) -> Result<(), Error> {
+    let config = Config {
+        index_format: format,
+        ..config.clone()
+    };
+
+    let index = Index::load_path(old_path)?;
+    index.store_path(new_path, &config)?;
+    config.store_path(config_path)?;
+
+    if old_path != new_path && old_path.exists() {
+        fs::remove_file(old_path)
+            .with_context(|_| format!("Could not remove {}.", old_path.display()))?;
+    }
+
+    errors::success(
+        &format!(
+            "Converted {} notes to {}.",
+            index.notes().len(),
+            new_path.display()
+        ),
+        &config,
+    );
+
+    Ok(())
+}
+
+/// Prints the problems found with each `Note` in the `Index`, and
+/// returns 1 if there are any or 0 if not.
+///
+/// If `fix` is true, empty and duplicate tags are removed first. See
+/// `Index::clean_tags`. Problems are reported through the returned code
+/// rather than an error, so that the fix is still saved.
+fn verify_notes(index: &mut Index, fix: bool, config: &Config) -> Result<i32, Error> {
+    if fix {
+        let cleaned = index.clean_tags();
+        errors::success(&format!("Cleaned the tags of {} notes.", cleaned), config);
+    }
+
+    let mut broken = 0;
+
+    for note in index.notes() {
+        if let Err(problems) = note.validate() {
+            broken += 1;
+            for problem in problems {
+                println!("{}: {}", note.name(), problem);
+            }
+        }
+    }
+
+    if broken > 0 {
+        eprintln!("{} notes have problems.", broken);
+        return Ok(1);
+    }
+
+    errors::success(
+        &format!("All {} notes are valid.", index.notes().len()),
+        config,
+    );
+
+    Ok(0)
+}
+
+/// Adds `tags` to the `Note` called `name`, or removes them if `add`
+/// is false, and prints the tags it is left with. See `Index::add_tags`
+/// and `Index::remove_tags`.
+///
+/// # Errors
+///
+/// - One of `tags` is empty or only whitespace.
+/// - There is no `Note` called `name`.
+fn tag_note(
+    index: &mut Index,
+    name: &str,
+    tags: &[String],
+    add: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    failure::ensure!(
+        tags.iter().all(|tag| !tag.trim().is_empty()),
+        "Tags cannot be empty."
+    );
+
+    let note = if add {
+        index.add_tags(name, tags)
+    } else {
+        index.remove_tags(name, tags)
+    };
+
+    let message = match note.and_then(|note| note.tags().as_ref()) {
+        Some(tags) => format!("Note `{}` is tagged {}.", name, tags.join(", ")),
+        None if note.is_some() => format!("Note `{}` has no tags.", name),
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+    errors::success(&message, config);
+
+    Ok(())
+}
+
+/// Renames the `Note` called `old` to `new`. See `Index::rename`.
+///
+/// If `verify` is true, the rename is undone if it would leave the
+/// `Index` inconsistent. See `Transaction::verify`.
+///
+/// # Errors
+///
+/// - There is no `Note` called `old`.
+/// - A `Note` called `new` already exists.
+/// - `verify` is true and the `Index` would be left inconsistent.
+fn rename_note(
+    index: &mut Index,
+    old: &str,
+    new: &str,
+    verify: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    failure::ensure!(index.contains(old), errors::does_not_exist(old));
+    failure::ensure!(!index.contains(new), errors::already_exists(new));
+
+    transaction::run(index, |index, transaction| {
+        index.rename(old, new)?;
+
+        if verify {
+            transaction.verify(index)?;
+        }
+        Ok(())
+    })?;
+    errors::successful(old, &format!("renamed to `{}`", new), config);
+
+    Ok(())
+}
+
+/// Moves the file of the `Note` called `name` to `to` and updates the
+/// path of every `Note` with that file. If `to` is a directory, the
+/// file is moved into it with the same file name.
+///
+/// If the `Note` cannot be updated after the file has moved, the file
+/// is moved back. So is the `Note` if `verify` is true and the `Index`
+/// would be left inconsistent. See `Transaction::verify`.
+///
+/// If `prune_root` is given, the directories that held the file are
+/// removed afterwards if they are left empty, as in `delete_note`.
+///
+/// # Errors
+///
+/// - There is no `Note` called `name`.
+/// - A file already exists at the destination.
+/// - The file cannot be moved.
+/// - `verify` is true and the `Index` would be left inconsistent.
+fn move_note(
+    index: &mut Index,
+    name: &str,
+    to: &Path,
+    verify: bool,
+    prune_root: Option<&Path>,
+    config: &Config,
+) -> Result<(), Error> {
+    let from = match index.get(name) {
+        Some(note) => note.path().clone(),
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+
+    let to = match from.file_name() {
+        Some(file_name) if to.is_dir() => to.join(file_name),
+        _ => to.to_path_buf(),
+    };
+    failure::ensure!(!to.exists(), "A file at {} already exists.", to.display());
+
+    let moved = transaction::run(index, |index, transaction| {
+        transaction
+            .rename(&from, &to)
+            .with_context(|_| errors::could_not_note("move", name, &from))?;
+        let moved = index.move_path(&from, &to)?;
+
+        if verify {
+            transaction.verify(index)?;
+        }
+        Ok(moved)
+    })?;
+
+    for name in moved {
+        errors::success(&format!("Moved `{}` to {}.", name, to.display()), config);
+    }
+
+    if let Some(root) = prune_root {
+        prune_emptied_dirs(&from, root, config);
+    }
+
+    Ok(())
+}
+
+/// Renames the tag `old` to `new` on every `Note` and prints what
+/// happened to each. See `Index::rename_tag`.
+///
+/// # Errors
+///
+/// - `old` and `new` are the same.
+/// - `merge` is false and some `Note` already has the tag `new`.
+fn rename_tag(
+    index: &mut Index,
+    old: &str,
+    new: &str,
+    merge: bool,
+    config: &Config,
+) -> Result<(), Error> {
+    failure::ensure!(old != new, "The tag `{}` already has that name.", old);
+
+    if !merge {
+        let users = index.find_by(|note| note.tags().iter().flatten().any(|tag| tag == new));
+        failure::ensure!(
+            users.is_empty(),
+            "The tag `{}` is already used by {} notes. Use --merge to combine it with `{}`.",
+            new,
+            users.len(),
+            old
+        );
+    }
+
+    let outcomes = index.rename_tag(old, new);
+
+    if outcomes.is_empty() {
+        eprintln!("No notes are tagged `{}`.", old);
+        return Ok(());
+    }
+
+    let mut message = String::new();
+    for (name, outcome) in &outcomes {
+        message.push_str(&format!("{}: {}\n", name, outcome));
+    }
+    message.push_str(&format!(
+        "Renamed `{}` to `{}` on {} notes.",
+        old,
+        new,
+        outcomes.len()
+    ));
+    errors::success(&message, config);
+
+    Ok(())
+}
+
+/// Removes every `Note` whose file is missing and prints their names,
+/// or only prints them if `dry_run` is true.
+fn prune_notes(index: &mut Index, dry_run: bool, config: &Config) -> Result<(), Error> {
+    let names: Vec<String> = if dry_run {
+        index
+            .find_by(|note| !note.path().exists())
+            .iter()
+            .map(|note| note.name().to_string())
+            .collect()
+    } else {
+        index.prune()
+    };
+
+    if names.is_empty() {
+        eprintln!("Every note's file exists, so nothing was pruned.");
+    } else if dry_run {
+        eprintln!("Would prune {} notes: {}", names.len(), names.join(", "));
+    } else {
+        errors::success(
+            &format!("Pruned {} notes: {}", names.len(), names.join(", ")),
+            config,
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints every `Note` whose file is missing.
+fn report_missing_files(index: &Index) -> Result<(), Error> {
+    let missing = index.find_by(|note| !note.path().exists());
+
+    if missing.is_empty() {
+        eprintln!("Every note's file exists.");
+        return Ok(());
+    }
+
+    println!("{} notes have missing files:", missing.len());
+    for note in &missing {
+        println!("  {}: {}", note.name(), note.path().display());
+    }
+    eprintln!(
+        "If their directory was moved, fix them with `doctor --fix-paths --from <old> --to <new>`."
+    );
+
+    Ok(())
+}
+
+/// Moves the stored paths of the `Note`s under `from` to the same
+/// places under `to`, and prints how many were fixed and which could
+/// not be. See `Index::relocate`.
+///
+/// # Errors
+///
+/// - `to` does not exist.
+fn fix_paths(index: &mut Index, from: &Path, to: &Path, config: &Config) -> Result<(), Error> {
+    failure::ensure!(to.is_dir(), "{} is not a directory.", to.display());
+
+    let (fixed, unresolved) = index.relocate(from, to);
+
+    errors::success(
+        &format!("Fixed the paths of {} notes.", fixed.len()),
+        config,
+    );
+    if !unresolved.is_empty() {
+        eprintln!("These notes still have no file under {}:", to.display());
+        for name in &unresolved {
+            eprintln!("  {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the `Note`s in the `Index` to `output` in the same format as
+/// scrivener.toml.
+///
+/// If `since` is given, only `Note`s modified after it are written,
+/// so that the file can be imported as a set of changes.
+///
+/// # Errors
+///
+/// - `output` cannot be written to.
+fn export_notes(
+    index: &Index,
+    output: &Path,
+    since: Option<SystemTime>,
+    config: &Config,
+) -> Result<(), Error> {
+    let exported = index.subset(|note| note.modified_since(since));
+
+    exported.store_path(output, config)?;
+
+    errors::success(
+        &format!(
+            "Exported {} notes to {}.",
+            exported.notes().len(),
+            output.display()
+        ),
+        config,
+    );
+
+    Ok(())
+}
+
+/// The `.gitignore` written to new notes repositories
+const GITIGNORE: &str = "# Editor backups and swap files\n*~\n*.swp\n.DS_Store\n";
+
+/// Copies the files of the `Note`s in the `Index` into `dir/notes`,
+/// writes an index of the copies to `dir`, and commits everything to a
+/// git repository in `dir`, creating it if needed.
+///
+/// The copies listed in the index of an earlier export are replaced,
+/// so notes that were removed since then are removed from the
+/// repository too. Other files in `dir/notes` are left alone. A
+/// `.gitignore` is written unless there already is one.
+///
+/// # Errors
+///
+/// - The file of a `Note` is inside `dir`.
+/// - A file cannot be copied, removed, or written.
+/// - git cannot be run or fails.
+fn export_git_repo(index: &Index, dir: &Path, config: &Config) -> Result<(), Error> {
+    if let Some(note) = index
+        .notes()
+        .iter()
+        .find(|note| notes::path_under_root(note.path(), dir))
+    {
+        failure::bail!(
+            "Note `{}` is inside {}, so it cannot be exported there.",
+            note.name(),
+            dir.display()
+        );
+    }
+
+    let index_path = dir.join(format!("scrivener.{}", config.index_format.extension()));
+    let notes_dir = dir.join("notes");
+    if index_path.exists() {
+        let previous = Index::load_path(&index_path)?;
+        for note in previous.notes() {
+            let copy = note.path();
+            if copy.is_file() && notes::path_under_root(copy, &notes_dir) {
+                fs::remove_file(copy)
+                    .with_context(|_| format!("Could not remove {}.", copy.display()))?;
+            }
+        }
+    }
+    fs::create_dir_all(&notes_dir)
+        .with_context(|_| format!("Could not create {}.", notes_dir.display()))?;
+
+    // The copies keep the times of the notes, so exporting unchanged
+    // notes again leaves nothing to commit.
+    let mut exported = index.clone();
+    for note in index.notes() {
+        let copy = unused_path(&notes_dir, note.path());
+        fs::copy(note.path(), &copy)
+            .and_then(|_| fs::metadata(note.path()))
+            .and_then(|metadata| {
+                filetime::set_file_mtime(&copy, FileTime::from_last_modification_time(&metadata))
+            })
+            .with_context(|_| errors::could_not_note("export", note.name(), note.path()))?;
+        exported.set_path(note.name(), &copy)?;
+    }
+
+    exported.store_path(&index_path, config)?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, GITIGNORE)
+            .with_context(|_| format!("Could not write {}.", gitignore.display()))?;
+    }
+
+    if !git::init(dir)? {
+        eprintln!("Using the existing git repository in {}.", dir.display());
+    }
+
+    let message = format!("Export {} notes from scrivener", exported.notes().len());
+    let message = if git::commit_all(dir, &message)? {
+        format!(
+            "Exported {} notes to {} and committed them.",
+            exported.notes().len(),
+            dir.display()
+        )
+    } else {
+        format!(
+            "Nothing changed in {} since the last export.",
+            dir.display()
+        )
+    };
+    errors::success(&message, config);
+
+    Ok(())
+}
+
+/// Returns a path in `dir` with the file name of `path`, numbered like
+/// `note-2.txt` if a file with that name is already there.
+fn unused_path(dir: &Path, path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut candidate = dir.join(format!("{}{}", stem, extension));
+    let mut number = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{}-{}{}", stem, number, extension));
+        number += 1;
+    }
+
+    candidate
+}
+
+/// Writes each `Note` in the `Index` to a JSON sidecar next to its
+/// file, or only those modified after `since` if it is given.
+///
+/// # Errors
+///
+/// - A sidecar cannot be written.
+fn export_sidecars(index: &Index, since: Option<SystemTime>, config: &Config) -> Result<(), Error> {
+    let notes = index.find_by(|note| note.modified_since(since));
+
+    for note in &notes {
+        note.write_sidecar()
+            .with_context(|_| errors::could_not_note("export", note.name(), note.path()))?;
+    }
+
+    errors::success(
+        &format!("Exported {} notes to sidecars.", notes.len()),
+        config,
+    );
+
+    Ok(())
+}
+
+/// Adds the `Note`s described by the sidecars in `dir` to the `Index`,
+/// replacing any `Note`s with the same names.
+///
+/// # Errors
+///
+/// - `dir` or a sidecar cannot be read.
+fn import_sidecars(index: &mut Index, dir: &Path, config: &Config) -> Result<(), Error> {
+    let imported = Index::from_sidecars(dir)?;
+    let count = imported.notes().len();
+    let replaced = index.apply(imported);
+
+    errors::success(
+        &format!(
+            "Imported {} notes ({} replaced) from sidecars in {}.",
+            count,
+            replaced.len(),
+            dir.display()
+        ),
+        config,
+    );
+
+    Ok(())
+}
+
+/// Adds the `Note`s exported to `input` to the `Index`, replacing any
+/// `Note`s with the same names.
+///
+/// # Errors
+///
+/// - `input` cannot be read or is not an exported file.
+fn import_notes(index: &mut Index, input: &Path, config: &Config) -> Result<(), Error> {
+    failure::ensure!(input.exists(), "{} does not exist.", input.display());
+
+    let imported = Index::load_path(input)?;
+    let count = imported.notes().len();
+    let replaced = index.apply(imported);
+
+    errors::success(
+        &format!(
+            "Imported {} notes ({} replaced) from {}.",
+            count,
+            replaced.len(),
+            input.display()
+        ),
+        config,
+    );
+
+    Ok(())
+}
+
+/// Applies the metadata updates in the JSON file at `file` to the
+/// `Index`. See `Index::apply_meta`.
+///
+/// # Errors
+///
+/// - `file` cannot be read or is not a valid update file.
+/// - A note named in `file` does not exist.
+fn apply_meta(index: &mut Index, file: &Path, config: &Config) -> Result<(), Error> {
+    let text =
+        fs::read_to_string(file).with_context(|_| format!("Could not read {}.", file.display()))?;
+    let updates: BTreeMap<String, notes::MetaUpdate> = serde_json::from_str(&text)
+        .with_context(|_| format!("{} is not a valid update file.", file.display()))?;
+
+    let changed = index.apply_meta(&updates)?;
+
+    let mut message = format!(
+        "Updated {} of {} notes from {}.",
+        changed.len(),
+        updates.len(),
+        file.display()
+    );
+    for name in changed {
+        message.push_str(&format!("\n  {}", name));
+    }
+    errors::success(&message, config);
+
+    Ok(())
+}
+
+/// Parses a time given on the command line.
+///
+/// Accepts a date (2020-05-04), an RFC 3339 timestamp
+/// (2020-05-04T12:00:00+02:00), or whole seconds since the Unix epoch.
+/// Dates are taken to be at midnight UTC.
+fn parse_time(text: &str) -> Result<SystemTime, Error> {
+    let secs = if let Ok(secs) = text.parse::<u64>() {
+        secs as i64
+    } else if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        time.timestamp()
+    } else {
+        failure::bail!("`{}` is not a date, timestamp, or number of seconds.", text);
+    };
+
+    failure::ensure!(secs >= 0, "`{}` is before 1970.", text);
+
+    Ok(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// Writes a Markdown page to `output` that links to every `Note` in
+/// the `Index`, grouped by tag.
+///
+/// Links are relative to the directory containing `output`. An
+/// existing file at `output` is overwritten.
+///
+/// # Errors
+///
+/// - The directory containing `output` is inaccessible.
+///
+/// - The page cannot be written.
+fn export_index(index: &Index, output: &Path, config: &Config) -> Result<(), Error> {
+    let dir = match output.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let base = dir
+        .canonicalize()
+        .with_context(|_| format!("Could not access {}.", dir.display()))?;
+
+    fs::write(output, export::index_page(index, &base))
+        .with_context(|_| format!("Could not write to {}.", output.display()))?;
+
+    errors::success(
+        &format!("Index page written to {}.", output.display()),
+        config,
+    );
+
+    Ok(())
+}
+
+/// Runs the file of the `Note` called `name` with `args` and returns
+/// its exit code.
+///
+/// # Errors
+///
+/// - The `Note` does not exist.
+/// - The `Note`'s file is not executable or cannot be started.
+fn run_note(index: &Index, name: &str, args: &[String]) -> Result<i32, Error> {
+    let note = match index.get(name) {
+        Some(note) => note,
+        None => failure::bail!(errors::does_not_exist(name)),
+    };
+
+    failure::ensure!(
+        run::is_executable(note.path()),
+        errors::not_executable(name, note.path())
+    );
+
+    run::run(note.path(), args)
+}
+
+/// Converts an absolute path pointing to a file to a relative path
+/// based on the current working directory unless the current working
+/// directory is inaccessible or the path points to a file in root.
+///
+/// See `relative_to` for how the path is written.
+fn abs_to_rel(path: &Path, style: PathStyle) -> String {
+    match std::env::current_dir() {
+        Ok(current_dir) => relative_to(path, &current_dir, style),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Writes the absolute `path` relative to the directory `base`.
+///
+/// Paths below `base` start with `./` in the `Dotted` style and with
+/// their first directory in the `Bare` style. Other paths start with a
+/// `../` for each directory between `base` and the directory the two
+/// paths share. A path to a directory never ends with a separator, so
+/// `base` itself is `.` and its parent is `..`.
+///
+/// Files directly in root are written as absolute paths.
+fn relative_to(path: &Path, base: &Path, style: PathStyle) -> String {
+    if path.parent() == Some(Path::new("/")) {
+        return path.display().to_string();
+    }
+
+    // Find the closest directory that contains both paths, counting
+    // how many directories up from base it is.
+    let mut ancestor = base;
+    let mut ups = 0;
+    let rest = loop {
+        if let Ok(rest) = path.strip_prefix(ancestor) {
+            break rest;
+        }
+
+        match ancestor.parent() {
+            Some(parent) => ancestor = parent,
+            None => return path.display().to_string(),
+        }
+        ups += 1;
+    };
+
+    let rest = rest.display().to_string();
+    if ups > 0 {
+        let prefix = vec![".."; ups].join("/");
+        if rest.is_empty() {
+            prefix
+        } else {
+            format!("{}/{}", prefix, rest)
+        }
+    } else if rest.is_empty() {
+        String::from(".")
+    } else {
+        match style {
+            PathStyle::Dotted => format!("./{}", rest),
+            PathStyle::Bare => rest,
+        }
+    }
+}
+
+//TODO: Improve tests
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::scrivener::config::NameCase;
+    use crate::scrivener::test_utils::tags;
+
+    /// Writes `content` to `path` and adds it to `index` as `name`.
+    fn add_written_note(
+        index: &mut Index,
+        name: &str,
+        path: &Path,
+        content: &str,
+        tags: &Option<Vec<String>>,
+    ) {
+        fs::write(path, content).unwrap();
+        add_note(index, name, &path.to_path_buf(), tags).unwrap();
+    }
+
+    /// Writes an executable editor to `dir` that runs `script` on the
+    /// file it is given as `$1`, and returns its path.
+    #[cfg(unix)]
+    fn stub_editor(dir: &Path, script: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let editor = dir.join("editor");
+        fs::write(&editor, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+        editor.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn add_a_note() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Add";
+        let path = file.path().to_path_buf();
+        let tags = Some(vec![String::from("one"), String::from("two")]);
+
+        add_note(&mut index, name, &path, &tags).unwrap();
+
+        let mut expected = Index::new();
+        let expected = expected.add(name, &path, &tags).unwrap();
+
+        let note = index.get(name).unwrap();
+        assert_eq!(note.path(), expected.path());
+        assert_eq!(note.tags(), expected.tags());
+        assert_eq!(index.notes().len(), 1);
+    }
+
+    #[test]
+    fn add_normalizes_names() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let config = Config {
+            name_case: NameCase::Kebab,
+            ..Config::default()
+        };
+
+        let add = |name| crate::scrivener::args::Args::from_iter(&["scrv", "add", name, path]);
+
+        add("My Note")
+            .cmd
+            .execute(&mut index, &config, false)
+            .unwrap();
+        assert!(index.contains("my-note"));
+        assert!(!index.contains("My Note"));
+
+        // The normalized name is what collides.
+        let error = add("MY_NOTE")
+            .cmd
+            .execute(&mut index, &config, false)
+            .unwrap_err();
+        assert!(error.to_string().contains("my-note"));
+    }
+
+    #[test]
+    fn add_validates_names() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let add = |index: &mut Index, config: &Config, name| {
+            crate::scrivener::args::Args::from_iter(&["scrv", "add", "--", name, path])
+                .cmd
+                .execute(index, config, false)
+        };
+
+        let config = Config::default();
+        assert!(add(&mut index, &config, "a/b").is_err());
+        assert!(add(&mut index, &config, "-b").is_err());
+        add(&mut index, &config, "Fine").unwrap();
+
+        let config = Config {
+            name_regex: "^[a-z]+$".to_string(),
+            ..Config::default()
+        };
+        let error = add(&mut index, &config, "Upper").unwrap_err();
+        assert!(error.to_string().contains("^[a-z]+$"));
+        add(&mut index, &config, "lower").unwrap();
+        assert_eq!(index.notes().len(), 2);
+    }
+
+    #[test]
+    fn add_with_strict_root() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("notes");
+        fs::create_dir(&root).unwrap();
+        let inside = root.join("inside.txt");
+        let outside = dir.path().join("outside.txt");
+        fs::write(&inside, "").unwrap();
+        fs::write(&outside, "").unwrap();
+
+        let mut index = Index::new();
+        let config = Config {
+            notes_root: root.clone(),
+            ..Config::default()
+        };
+        let add = |index: &mut Index, config: &Config, args: &[&str]| {
+            let args = [&["scrv", "add"], args].concat();
+            crate::scrivener::args::Args::from_iter(&args)
+                .cmd
+                .execute(index, config, false)
+        };
+
+        // Permissive by default.
+        add(&mut index, &config, &["loose", outside.to_str().unwrap()]).unwrap();
+        index.remove("loose");
+
+        let error = add(
+            &mut index,
+            &config,
+            &["--strict-root", "stray", outside.to_str().unwrap()],
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("--move-into-root"));
+        assert!(!index.contains("stray"));
+
+        let strict = Config {
+            strict_root: true,
+            ..config.clone()
+        };
+        add(&mut index, &strict, &["inside", inside.to_str().unwrap()]).unwrap();
+        assert!(add(&mut index, &strict, &["stray", outside.to_str().unwrap()]).is_err());
+
+        add(
+            &mut index,
+            &strict,
+            &["--move-into-root", "moved", outside.to_str().unwrap()],
+        )
+        .unwrap();
+        assert!(!outside.exists());
+        assert!(notes::path_under_root(
+            index.get("moved").unwrap().path(),
+            &root
+        ));
+
+        // A taken name leaves the file where it was.
+        fs::write(&outside, "").unwrap();
+        let error = add(
+            &mut index,
+            &strict,
+            &["--move-into-root", "moved", outside.to_str().unwrap()],
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert!(outside.exists());
+
+        // A strict root needs a root.
+        let unset = Config {
+            strict_root: true,
+            ..Config::default()
+        };
+        assert!(add(&mut index, &unset, &["again", inside.to_str().unwrap()]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_note_from_tag_template() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = stub_editor(dir.path(), "true");
+        let editor = editor.as_str();
+
+        let template = dir.path().join("meeting.md");
+        fs::write(&template, "# Attendees\n").unwrap();
+        let mut config = Config::default();
+        config
+            .tag_templates
+            .insert("meeting".to_string(), template.clone());
+
+        let mut index = Index::new();
+        let tagged = Some(vec!["meeting".to_string()]);
+        for (name, tags) in &[("tagged", &tagged), ("untagged", &None)] {
+            let path = Some(dir.path().join(name));
+            let text = read_template(config.template_for(&None, tags)).unwrap();
+            create_new_note(
+                &mut index,
+                name,
+                &path,
+                tags,
+                &text,
+                Some(editor),
+                true,
+                &config,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("tagged")).unwrap(),
+            "# Attendees\n"
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("untagged")).unwrap(), "");
+        assert!(read_template(Some(dir.path().join("missing.md"))).is_err());
+    }
+
+    #[test]
+    fn new_without_editor() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        let mut index = Index::new();
+
+        create_new_note(
+            &mut index,
+            "Note",
+            &Some(path.clone()),
+            &None,
+            "piped text\n",
+            None,
+            true,
+            &Config::default(),
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "piped text\n");
+        assert!(index.contains("Note"));
+    }
+
+    #[test]
+    fn edit_with_missing_editor() {
+        let file = NamedTempFile::new().unwrap();
+        let mut index = Index::new();
+        add_note(&mut index, "Note", &file.path().to_path_buf(), &None).unwrap();
+
+        let error = edit_note(
+            &mut index,
+            "Note",
+            "definitely-not-a-real-binary",
+            true,
+            false,
+            true,
+            &Config::default(),
+        )
+        .unwrap_err();
+        assert!(error
+            .iter_chain()
+            .any(|cause| cause.to_string().contains("definitely-not-a-real-binary")));
+    }
+
+    #[test]
+    fn new_in_default_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            default_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let mut index = Index::new();
+
+        create_new_note(&mut index, "Note", &None, &None, "", None, true, &config).unwrap();
+        assert!(dir.path().join("Note.txt").is_file());
+
+        let nested = Config {
+            default_dir: dir.path().join("not/yet"),
+            ..Config::default()
+        };
+        create_new_note(&mut index, "Nested", &None, &None, "", None, true, &nested).unwrap();
+        assert!(dir.path().join("not/yet/Nested.txt").is_file());
+
+        let given = dir.path().join("given.md");
+        assert_eq!(
+            new_note_path("Note", &Some(given.clone()), &config).unwrap(),
+            given
+        );
+    }
+
+    #[test]
+    fn notes_dir_from_env_first() {
+        let config = Config {
+            default_dir: PathBuf::from("/setting"),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            notes_dir(Some("/env".into()), &config),
+            Some(PathBuf::from("/env"))
+        );
+        assert_eq!(
+            notes_dir(Some("".into()), &config),
+            Some(PathBuf::from("/setting"))
+        );
+        assert_eq!(notes_dir(None, &config), Some(PathBuf::from("/setting")));
+        assert_eq!(notes_dir(None, &Config::default()), None);
+    }
+
+    #[test]
+    fn new_note_path_creates_env_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            default_dir: dir.path().join("setting"),
+            ..Config::default()
+        };
+
+        let env_dir = dir.path().join("env/not/yet");
+        let path =
+            new_note_path_from("Note", &None, Some(env_dir.clone().into()), &config).unwrap();
+        assert_eq!(path, env_dir.join("Note.txt"));
+        assert!(env_dir.is_dir());
+        assert!(!config.default_dir.exists());
+
+        let path = new_note_path_from("Note", &None, Some("".into()), &config).unwrap();
+        assert_eq!(path, config.default_dir.join("Note.txt"));
+        assert!(config.default_dir.is_dir());
+    }
+
+    #[test]
+    fn failed_new_leaves_no_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = dir.path().join("missing-editor");
+        let editor = editor.to_str().unwrap();
+        let mut index = Index::new();
+
+        for &wait in &[true, false] {
+            let path = dir.path().join("note.txt");
+            let result = create_new_note(
+                &mut index,
+                "Note",
+                &Some(path.clone()),
+                &None,
+                "",
+                Some(editor),
+                wait,
+                &Config::default(),
+            );
+
+            assert!(result.is_err());
+            assert!(!path.exists());
+            assert!(!index.contains("Note"));
+        }
+    }
+
+    #[test]
+    fn random_with_seed() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tagged = Some(vec!["tag".to_string()]);
+
+        for name in &["a", "b", "c", "d"] {
+            add_note(&mut index, name, &path, &tagged).unwrap();
+        }
+        add_note(&mut index, "untagged", &path, &None).unwrap();
+
+        let notes = index.search_by_tag("tag");
+        let chosen = choose_note(&notes, Some(42)).unwrap();
+        assert!(notes.contains(&chosen));
+        assert_eq!(choose_note(&notes, Some(42)), Some(chosen));
+
+        assert_eq!(choose_note(&[], Some(42)), None);
+
+        let empty = Some("missing".to_string());
+        assert!(random_note(&index, &empty, true, Some(42), "true").is_ok());
+    }
+
+    #[test]
+    fn add_and_scan_infer_tags() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let nested = dir.path().join("work/acme");
+        fs::create_dir_all(&nested).unwrap();
+        let note_path = nested.join("note.txt");
+        fs::write(&note_path, "").unwrap();
+        fs::write(dir.path().join("work/other.txt"), "").unwrap();
+
+        let mut config = Config::default();
+        let infer = InferOptions {
+            infer_tags_from_path: true,
+            tag_depth: None,
+            tag_from_parent: false,
+        };
+
+        // Without a notes root, there is nothing to infer from.
+        assert!(infer.tags(&note_path, &config).is_err());
+        assert!(InferOptions::default()
+            .tags(&note_path, &config)
+            .unwrap()
+            .is_empty());
+
+        config.notes_root = dir.path().to_path_buf();
+        let mut index = Index::new();
+        let path = note_path.to_str().unwrap();
+        let add = crate::scrivener::args::Args::from_iter(&[
+            "scrv",
+            "add",
+            "note",
+            path,
+            "--tags",
+            "acme",
+            "mine",
+            "--infer-tags-from-path",
+        ]);
+        add.cmd.execute(&mut index, &config, false).unwrap();
+        assert_eq!(
+            index.get("note").unwrap().tags(),
+            &Some(vec!["acme".into(), "mine".into(), "work".into()])
+        );
+
+        let infer = InferOptions {
+            infer_tags_from_path: true,
+            tag_depth: Some(1),
+            tag_from_parent: false,
+        };
+        scan_directory(
+            &mut index,
+            dir.path(),
+            true,
+            &ScanIgnore::default(),
+            &infer,
+            &config,
+            true,
+        )
+        .unwrap();
+        assert_eq!(index.get("other").unwrap().tags(), &tags(&["work"]));
+    }
+
+    #[test]
+    fn tag_from_parent_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("recipes")).unwrap();
+        let nested = dir.path().join("recipes/soup.txt");
+        let top = dir.path().join("top.txt");
+        fs::write(&nested, "").unwrap();
+        fs::write(&top, "").unwrap();
+
+        let config = Config {
+            notes_root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let mut index = Index::new();
+        let add = |index: &mut Index, name: &str, path: &Path, tags: &[&str]| {
+            let args = [
+                &[
+                    "scrv",
+                    "add",
+                    name,
+                    path.to_str().unwrap(),
+                    "--tag-from-parent",
+                ],
+                tags,
+            ]
+            .concat();
+            crate::scrivener::args::Args::from_iter(&args)
+                .cmd
+                .execute(index, &config, false)
+                .unwrap();
+        };
+
+        add(
+            &mut index,
+            "soup",
+            &nested,
+            &["--tags", "dinner", "recipes"],
+        );
+        assert_eq!(
+            index.get("soup").unwrap().tags(),
+            &Some(vec!["dinner".to_string(), "recipes".to_string()])
+        );
+
+        add(&mut index, "top", &top, &[]);
+        assert_eq!(index.get("top").unwrap().tags(), &None);
+    }
+
+    #[test]
+    fn names_are_required() {
+        let index = Index::new();
+
+        let name = Some("Note".to_string());
+        assert_eq!(resolve_name(&index, &name, false).unwrap(), "Note");
+        assert_eq!(resolve_name(&index, &name, true).unwrap(), "Note");
+
+        let error = resolve_name(&index, &None, false).unwrap_err();
+        assert!(error.to_string().contains("--interactive"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edit_warns_about_external_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = stub_editor(dir.path(), "echo edited >> \"$1\"");
+        let editor = editor.as_str();
+
+        let path = dir.path().join("note.txt");
+        let mut index = Index::new();
+        add_written_note(&mut index, "Note", &path, "first\n", &None);
+        assert_eq!(edit_conflict(index.get("Note").unwrap()), None);
+
+        // Another program writes to the file after it was recorded.
+        let later = FileTime::from_system_time(SystemTime::now() + Duration::from_secs(60));
+        filetime::set_file_mtime(&path, later).unwrap();
+        let warning = edit_conflict(index.get("Note").unwrap()).unwrap();
+        assert!(warning.contains("`Note` was changed"));
+
+        edit_note(
+            &mut index,
+            "Note",
+            editor,
+            true,
+            false,
+            true,
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nedited\n");
+        assert_eq!(edit_conflict(index.get("Note").unwrap()), None);
+
+        // Not waiting for the editor records nothing, since the editor
+        // may still be writing the file.
+        let later = FileTime::from_system_time(SystemTime::now() + Duration::from_secs(120));
+        filetime::set_file_mtime(&path, later).unwrap();
+        edit_note(
+            &mut index,
+            "Note",
+            "true",
+            false,
+            false,
+            true,
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(edit_conflict(index.get("Note").unwrap()).is_some());
+    }
+
+    #[test]
+    fn info_checks_links() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+        for (name, text) in &[("Hub", "[[Valid]] and [[Dangling]]"), ("Valid", "[[Hub]]")] {
+            let path = dir.path().join(name);
+            add_written_note(&mut index, name, &path, text, &None);
+        }
+
+        assert_eq!(
+            outgoing_links(&index, index.get("Hub").unwrap()).unwrap(),
+            [("Valid".to_string(), true), ("Dangling".to_string(), false)]
+        );
+        assert_eq!(
+            outgoing_links(&index, index.get("Valid").unwrap()).unwrap(),
+            [("Hub".to_string(), true)]
+        );
+
+        assert!(show_info(&index, "Hub", false, &Config::default()).is_ok());
+        let error = show_info(&index, "Hub", true, &Config::default()).unwrap_err();
+        assert!(error.to_string().contains("1 broken links"));
+        assert!(show_info(&index, "Valid", true, &Config::default()).is_ok());
+        assert!(show_info(&index, "Missing", true, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn info_counts_lines_and_words() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+
+        let mut index = Index::new();
+        add_written_note(
+            &mut index,
+            "Note",
+            &path,
+            "# Title\n\nA few words here.\n",
+            &None,
+        );
+        assert_eq!(
+            line_and_word_counts(index.get("Note").unwrap(), CountMode::Words),
+            Some((3, 5))
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            line_and_word_counts(index.get("Note").unwrap(), CountMode::Words),
+            None
+        );
+        assert!(show_info(&index, "Note", false, &Config::default()).is_ok());
+    }
+
+    #[test]
+    fn list_messages_go_to_stderr() {
+        let mut index = Index::new();
+        let mut err = Vec::new();
+        list_notes(&index, &ListOptions::default(), &mut err).unwrap();
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.starts_with("There are no notes to list!\n"));
+
+        let file = NamedTempFile::new().unwrap();
+        add_note(&mut index, "Note", &file.path().to_path_buf(), &None).unwrap();
+        let options = ListOptions {
+            filter_tags: vec!["missing".to_string()],
+            ..ListOptions::default()
+        };
+        let mut err = Vec::new();
+        list_notes(&index, &options, &mut err).unwrap();
+        assert_eq!(err, b"No notes matched the given tags.\n");
+    }
+
+    #[test]
+    fn list_paths_with_deleted_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let deleted = dir.path().join("deleted.txt");
+        let kept = dir.path().join("kept.txt");
+        fs::write(&deleted, "").unwrap();
+        fs::write(&kept, "").unwrap();
+
+        let mut index = Index::new();
+        add_note(&mut index, "Deleted", &deleted, &None).unwrap();
+        add_note(&mut index, "Kept", &kept, &None).unwrap();
+        fs::remove_file(&deleted).unwrap();
+
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "list", "--paths"]);
+        args.cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
+
+        let options = match args.cmd {
+            Command::List { options } => options,
+            _ => panic!("expected list"),
+        };
+        let table = notes_table(&listed_notes(&index, &options), &options).to_string();
+        assert!(table.contains("Deleted"));
+        assert!(table.contains("deleted.txt"));
+        assert!(table.contains("Kept"));
+    }
+
+    #[test]
+    fn prune_missing_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let deleted = dir.path().join("deleted.txt");
+        let kept = dir.path().join("kept.txt");
+        fs::write(&deleted, "").unwrap();
+        fs::write(&kept, "").unwrap();
+
+        let mut index = Index::new();
+        add_note(&mut index, "Deleted", &deleted, &None).unwrap();
+        add_note(&mut index, "Kept", &kept, &None).unwrap();
+        fs::remove_file(&deleted).unwrap();
+
+        let dry_run = crate::scrivener::args::Args::from_iter(&["scrv", "prune", "--dry-run"]);
+        assert!(!dry_run.cmd.modifies_index());
+        dry_run
+            .cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
+        assert!(index.get("Deleted").is_some());
+
+        let prune = crate::scrivener::args::Args::from_iter(&["scrv", "prune"]);
+        assert!(prune.cmd.modifies_index());
+        prune
+            .cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
+        assert!(index.get("Deleted").is_none());
+        assert!(index.get("Kept").is_some());
+    }
+
+    #[test]
+    fn list_distinct_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("shared.txt");
+        let own = dir.path().join("own.txt");
+        fs::write(&shared, "").unwrap();
+        fs::write(&own, "").unwrap();
+
+        let mut index = Index::new();
+        add_note(&mut index, "a", &shared, &None).unwrap();
+        add_note(&mut index, "b", &own, &None).unwrap();
+        add_note(&mut index, "c", &shared, &None).unwrap();
+
+        let options = ListOptions {
+            distinct_paths: true,
+            no_header: true,
+            ..ListOptions::default()
+        };
+        let notes = listed_notes(&index, &options);
+        let names: Vec<&str> = notes.iter().map(|note| note.name()).collect();
+        assert_eq!(names, ["a", "c", "b"]);
+
+        let table = notes_table(&notes, &options).to_string();
+        let lines: Vec<&str> = table.lines().map(str::trim).collect();
+        assert_eq!(lines, ["a | shared by: a, c", "c | shared by: a, c", "b |"]);
+    }
+
+    #[test]
+    fn names_one_per_line() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let mut index = Index::new();
+        add_note(&mut index, "b note", &path, &tags(&["Work"])).unwrap();
+        add_note(&mut index, "a note", &path, &None).unwrap();
+
+        let names = |filter_tags: &[String]| {
+            let mut out = Vec::new();
+            write_names(&index, filter_tags, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let expected: String = index.names().map(|name| format!("{}\n", name)).collect();
+        assert_eq!(names(&[]), expected);
+        assert_eq!(names(&[]), "a note\nb note\n");
+        assert_eq!(names(&["work".to_string()]), "b note\n");
+        assert_eq!(names(&["missing".to_string()]), "");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let index_path = dir.path().join("scrivener.json");
+        index.store_path(&index_path, &Config::default()).unwrap();
+        let mut out = Vec::new();
+        stream_names(&index_path, &["WORK".to_string()], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b note\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_and_edit_use_line_endings() {
+        use crate::scrivener::config::{Bom, LineEndings};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = stub_editor(dir.path(), "printf 'line\\n' >> \"$1\"");
+        let editor = editor.as_str();
+
+        let config = Config {
+            line_endings: LineEndings::Crlf,
+            bom: Bom::Always,
+            ..Config::default()
+        };
+        let path = dir.path().join("note.txt");
+        let mut index = Index::new();
+
+        create_new_note(
+            &mut index,
+            "Note",
+            &Some(path.clone()),
+            &None,
+            "# Title\n",
+            Some(editor),
+            true,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\xef\xbb\xbf# Title\r\nline\r\n");
+
+        edit_note(
+            &mut index,
+            "Note",
+            editor,
+            true,
+            false,
+            true,
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"\xef\xbb\xbf# Title\r\nline\r\nline\n"
+        );
+
+        let lf = Config {
+            line_endings: LineEndings::Lf,
+            bom: Bom::Never,
+            ..Config::default()
+        };
+        edit_note(&mut index, "Note", editor, true, false, true, &lf).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"# Title\nline\nline\nline\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edit_backs_up_first() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = stub_editor(dir.path(), "printf 'mangled\\n' > \"$1\"");
+        let editor = editor.as_str();
+
+        let path = dir.path().join("note.txt");
+        let mut index = Index::new();
+        add_written_note(&mut index, "Note", &path, "precious\n", &None);
+
+        let config = Config::default();
+        edit_note(&mut index, "Note", editor, true, true, true, &config).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "mangled\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("note.txt.bak")).unwrap(),
+            "precious\n"
+        );
+
+        edit_note(&mut index, "Note", editor, true, false, true, &config).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("note.txt.bak")).unwrap(),
+            "precious\n"
+        );
+    }
+
+    #[test]
+    fn show_missing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+
+        let mut index = Index::new();
+        add_written_note(&mut index, "Shown", &path, "one\ntwo\n", &None);
+
+        assert!(show_note(&index, "Shown", None, Frontmatter::Keep, &mut Vec::new()).is_ok());
+        assert!(show_note(
+            &index,
+            "Shown",
+            Some("2:".parse().unwrap()),
+            Frontmatter::Keep,
+            &mut Vec::new()
+        )
+        .is_ok());
+        assert!(show_note(
+            &index,
+            "Shown",
+            Some("3:".parse().unwrap()),
+            Frontmatter::Keep,
+            &mut Vec::new()
+        )
+        .is_err());
+        assert!(show_note(&index, "Hidden", None, Frontmatter::Keep, &mut Vec::new()).is_err());
+
+        fs::remove_file(&path).unwrap();
+        let error =
+            show_note(&index, "Shown", None, Frontmatter::Keep, &mut Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("Could not read note `Shown`"));
+
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "cat", "Shown", "--paginate"]);
+        match args.cmd {
+            Command::Show { name, paginate, .. } => {
+                assert_eq!(name.as_deref(), Some("Shown"));
+                assert!(paginate);
+            }
+            _ => panic!("expected show"),
+        }
+    }
+
+    #[cfg(all(unix, feature = "gzip"))]
+    #[test]
+    fn compressed_notes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("archive.md.gz");
+        gzip::write(&path, b"three archived words\n").unwrap();
+
+        let mut index = Index::new();
+        add_note(&mut index, "Archive", &path, &None).unwrap();
+
+        let mut out = Vec::new();
+        show_note(&index, "Archive", None, Frontmatter::Keep, &mut out).unwrap();
+        assert_eq!(out, b"three archived words\n");
+        assert_eq!(
+            index
+                .get("Archive")
+                .unwrap()
+                .word_count(CountMode::Words)
+                .unwrap(),
+            3
+        );
+
+        let editor = stub_editor(
+            dir.path(),
+            "case \"$1\" in *.md) printf 'more\\n' >> \"$1\";; esac",
+        );
+        let editor = editor.as_str();
+
+        let config = Config::default();
+        edit_note(&mut index, "Archive", editor, true, false, true, &config).unwrap();
+        assert!(gzip::is_compressed(&path));
+        assert_eq!(
+            gzip::read_to_string(&path).unwrap(),
+            "three archived words\nmore\n"
+        );
+
+        // What was written back is read decompressed again.
+        let note = index.get("Archive").unwrap();
+        assert_eq!(line_and_word_counts(note, CountMode::Words), Some((2, 4)));
+        let mut out = Vec::new();
+        show_note(&index, "Archive", None, Frontmatter::Keep, &mut out).unwrap();
+        assert_eq!(out, b"three archived words\nmore\n");
+        assert!(!fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .flatten()
+            .any(|entry| entry.file_name().to_string_lossy().ends_with("-archive.md")));
+
+        let error = edit_note(&mut index, "Archive", editor, false, false, true, &config);
+        assert!(error.unwrap_err().to_string().contains("is compressed"));
+    }
+
+    #[test]
+    fn show_with_frontmatter_flags() {
+        let with = "---\ntitle: A\n---\nBody\n";
+        let without = "Body\n---\n";
+
+        assert_eq!(Frontmatter::Keep.select(with), with);
+        assert_eq!(Frontmatter::Strip.select(with), "Body\n");
+        assert_eq!(Frontmatter::Only.select(with), "---\ntitle: A\n---\n");
+
+        assert_eq!(Frontmatter::Strip.select(without), without);
+        assert_eq!(Frontmatter::Only.select(without), without);
+
+        assert_eq!(Frontmatter::from_flags(true, false), Frontmatter::Strip);
+        assert_eq!(Frontmatter::from_flags(false, true), Frontmatter::Only);
+        assert!(crate::scrivener::args::Args::from_iter_safe(&[
+            "scrv",
+            "show",
+            "Note",
+            "--no-frontmatter",
+            "--frontmatter-only"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn remove_a_note() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Remove";
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, name, &path, &None).unwrap();
+
+        remove_note(&mut index, name, &Config::default()).unwrap();
+
+        assert_eq!(index, Index::new());
+    }
+
+    #[test]
+    fn touch_a_note() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Touch";
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, name, &path, &None).unwrap();
+        filetime::set_file_mtime(&path, FileTime::zero()).unwrap();
+
+        touch_note(&mut index, name, true, &Config::default()).unwrap();
+
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        assert!(mtime > FileTime::zero());
+        assert!(touch_note(&mut index, "missing", false, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn list_absolute_paths() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new_in(std::env::current_dir().unwrap()).unwrap();
+
+        let path = file.path().canonicalize().unwrap();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+
+        add_note(&mut index, "Test List", &path, &None).unwrap();
+
+        let mut options = ListOptions {
+            show_paths: true,
+            ..ListOptions::default()
+        };
+        let relative = notes_table(&index.find_by(|_| true), &options).to_string();
+        assert!(relative.contains(&format!("./{}", file_name)));
+        assert!(!relative.contains(path.to_str().unwrap()));
+
+        options.absolute_paths = true;
+        let absolute = notes_table(&index.find_by(|_| true), &options).to_string();
+        assert!(absolute.contains(path.to_str().unwrap()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn search_opens_single_match() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = stub_editor(dir.path(), "echo opened >> \"$1\"");
+        let editor = editor.as_str();
+
+        let mut index = Index::new();
+        let found = dir.path().join("found.txt");
+        let other = dir.path().join("other.txt");
+        for (name, path) in &[("Recipes", &found), ("Journal", &other)] {
+            add_written_note(&mut index, name, path, "", &None);
+        }
+
+        search_notes(
+            &mut index,
+            "recipe",
+            &SearchScope::default(),
+            true,
+            false,
+            false,
+            None,
+            editor,
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&found).unwrap(), "opened\n");
+
+        search_notes(
+            &mut index,
+            "nothing",
+            &SearchScope::default(),
+            true,
+            false,
+            false,
+            None,
+            editor,
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&found).unwrap(), "opened\n");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "");
+    }
+
+    #[test]
+    fn search_names_and_tags() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "Meeting", &path, &None).unwrap();
+        add_note(&mut index, "Budget", &path, &tags(&["Work"])).unwrap();
+        add_note(&mut index, "Diary", &path, &None).unwrap();
+
+        let names = |query: &str| -> Vec<&str> {
+            let matches = matching_notes(&index, &query.parse().unwrap(), &SearchScope::default());
+            matches.iter().map(|found| found.note.name()).collect()
+        };
+
+        assert_eq!(names("MEET"), ["Meeting"]);
+        assert_eq!(names("work"), ["Budget"]);
+        assert!(names("holiday").is_empty());
+    }
+
+    #[test]
+    fn search_scopes() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "Work log", &path, &None).unwrap();
+        add_note(&mut index, "Budget", &path, &tags(&["Work"])).unwrap();
+        add_note(&mut index, "Budget 2", &path, &tags(&["home"])).unwrap();
+
+        let names = |query: &str, name_only, tags_only, regex| -> Vec<&str> {
+            let scope = SearchScope::new(name_only, tags_only, regex, query).unwrap();
+            let query = Query::Text(query.to_string());
+            let matches = matching_notes(&index, &query, &scope);
+            let mut names: Vec<&str> = matches.iter().map(|found| found.note.name()).collect();
+            names.sort_unstable();
+            names
+        };
+
+        assert_eq!(names("work", false, false, false), ["Budget", "Work log"]);
+        assert_eq!(names("work", true, true, false), ["Budget", "Work log"]);
+        assert_eq!(names("work", true, false, false), ["Work log"]);
+        assert_eq!(names("work", false, true, false), ["Budget"]);
+        assert_eq!(names("^budget \\d$", false, false, true), ["Budget 2"]);
+        assert_eq!(
+            names("^(work|home)$", false, true, true),
+            ["Budget", "Budget 2"]
+        );
+
+        let found = matching_notes(
+            &index,
+            &Query::Text("t \\d".to_string()),
+            &SearchScope::new(false, false, true, "t \\d").unwrap(),
+        );
+        assert_eq!(found[0].name, Some(5..8));
+
+        assert!(SearchScope::new(false, false, true, "(unclosed").is_err());
+    }
+
+    #[test]
+    fn search_boolean_queries() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "Q1 report", &path, &tags(&["work", "urgent"])).unwrap();
+        add_note(&mut index, "Old report", &path, &tags(&["work"])).unwrap();
+        add_note(&mut index, "Groceries", &path, &tags(&["today"])).unwrap();
+
+        let matches = matching_notes(
+            &index,
+            &"tag:work NOT tag:urgent".parse().unwrap(),
+            &SearchScope::default(),
+        );
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].note.name(), "Old report");
+        assert_eq!(matches[0].tags, [("work", 0..4)]);
+
+        let matches = matching_notes(
+            &index,
+            &"report AND tag:urgent".parse().unwrap(),
+            &SearchScope::default(),
+        );
+        assert_eq!(matches[0].name, Some(3..9));
+        assert_eq!(matches[0].tags, [("urgent", 0..6)]);
+
+        assert!(search_notes(
+            &mut index,
+            "tag:work AND",
+            &SearchScope::default(),
+            false,
+            false,
+            false,
+            None,
+            "true",
+            &Config::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn search_ranking() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "A plan", &path, &None).unwrap();
+        add_note(&mut index, "Plans", &path, &None).unwrap();
+        add_note(&mut index, "Diary", &path, &tags(&["plan"])).unwrap();
+        add_note(&mut index, "plan", &path, &None).unwrap();
+        add_note(&mut index, "Unrelated", &path, &None).unwrap();
+
+        let ranked: Vec<(&str, u32)> = matching_notes(
+            &index,
+            &Query::Text("Plan".to_string()),
+            &SearchScope::default(),
+        )
+        .iter()
+        .map(|found| (found.note.name(), found.score))
+        .collect();
+        assert_eq!(
+            ranked,
+            [("Diary", 3), ("plan", 3), ("Plans", 2), ("A plan", 1)]
+        );
+    }
+
+    #[test]
+    fn search_match_spans() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let tags = Some(vec!["Work".to_string(), "Homework".to_string()]);
+        add_note(&mut index, "Workshop", &path, &tags).unwrap();
+
+        let matches = matching_notes(
+            &index,
+            &Query::Text("WORK".to_string()),
+            &SearchScope::default(),
+        );
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, Some(0..4));
+        assert_eq!(matches[0].tags, [("Work", 0..4), ("Homework", 4..8)]);
+
+        let matches = matching_notes(
+            &index,
+            &Query::Text("shop".to_string()),
+            &SearchScope::default(),
+        );
+        assert_eq!(matches[0].name, Some(4..8));
+        assert!(matches[0].tags.is_empty());
+
+        let matches = matching_notes(
+            &index,
+            &Query::Text("home".to_string()),
+            &SearchScope::default(),
+        );
+        let object = match_json(&matches[0]);
+        assert_eq!(object["tags"], serde_json::json!(["Work", "Homework"]));
+        assert_eq!(object["matched_tags"], serde_json::json!(["Homework"]));
+    }
+
+    #[test]
+    fn after_hook_gets_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sentinel = dir.path().join("sentinel");
+        let path = dir.path().join("note.txt");
+        fs::write(&path, "").unwrap();
+
+        let mut index = Index::new();
+        let hook = format!("echo >> '{}'", sentinel.display());
+        let args = crate::scrivener::args::Args::from_iter(&[
+            "scrv",
+            "add",
+            "Hooked",
+            path.to_str().unwrap(),
+            "--after-hook",
+            &hook,
+        ]);
+        args.cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
+
+        let canonical = path.canonicalize().unwrap();
+        assert_eq!(
+            fs::read_to_string(&sentinel).unwrap(),
+            format!("{}\n", canonical.display())
+        );
+
+        // A failing hook does not undo the addition.
+        run_after_hook(&index, "Hooked", &Some("false".to_string())).unwrap();
+        assert!(index.contains("Hooked"));
+    }
+
+    #[test]
+    fn list_sections() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        for name in &["1st", "Apple", "Avocado", "Banana"] {
+            add_note(&mut index, name, &path, &None).unwrap();
+        }
+
+        let options = ListOptions {
+            sections: true,
+            no_header: true,
+            ..ListOptions::default()
+        };
+        let notes = index.find_by(|_| true);
+        let table = notes_table(&notes, &options).to_string();
+        let lines: Vec<_> = table.lines().map(str::trim).collect();
+
+        assert_eq!(lines, ["#", "1st", "A", "Apple", "Avocado", "B", "Banana"]);
+
+        // Lowercase names join their section, whatever the sort.
+        add_note(&mut index, "apricot", &path, &None).unwrap();
+        let listed = |sort| {
+            let options = ListOptions {
+                sort,
+                ..options.clone()
+            };
+            let notes = listed_notes(&index, &options);
+            let table = notes_table(&notes, &options).to_string();
+            table
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            listed(ListSort::Name),
+            ["#", "1st", "A", "Apple", "apricot", "Avocado", "B", "Banana"]
+        );
+        assert_eq!(
+            listed(ListSort::Added),
+            ["#", "1st", "A", "Apple", "Avocado", "apricot", "B", "Banana"]
+        );
+    }
+
+    #[test]
+    fn list_filter_tags() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "acme", &path, &tags(&["project/acme", "work"])).unwrap();
+        add_note(&mut index, "other", &path, &tags(&["project/other"])).unwrap();
+        add_note(&mut index, "untagged", &path, &None).unwrap();
+
+        let listed = |filter_tags: &[&str], partial| {
+            let options = ListOptions {
+                filter_tags: filter_tags.iter().map(|tag| tag.to_string()).collect(),
+                partial,
+                ..ListOptions::default()
+            };
+            listed_notes(&index, &options)
+                .iter()
+                .map(|note| note.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        let listed_any = |filter_tags: &[&str]| {
+            let options = ListOptions {
+                filter_tags: filter_tags.iter().map(|tag| tag.to_string()).collect(),
+                any: true,
+                ..ListOptions::default()
+            };
+            listed_notes(&index, &options)
+                .iter()
+                .map(|note| note.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(listed(&[], false).len(), 3);
+        assert!(listed(&["acme"], false).is_empty());
+        assert_eq!(listed(&["Project/ACME"], false), ["acme"]);
+        assert_eq!(listed(&["acme"], true), ["acme"]);
+        assert_eq!(listed(&["project/"], true), ["acme", "other"]);
+        assert_eq!(listed(&["project/", "work"], true), ["acme"]);
+        assert_eq!(listed_any(&["WORK", "project/other"]), ["acme", "other"]);
+        assert!(listed_any(&["missing"]).is_empty());
+    }
+
+    #[test]
+    fn list_empty_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+
+        for (name, contents) in &[("blank", ""), ("written", "words"), ("missing", "")] {
+            let path = dir.path().join(name);
+            add_written_note(&mut index, name, &path, contents, &None);
+        }
+        fs::remove_file(dir.path().join("missing")).unwrap();
+
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "list", "--empty", "-t"]);
+        let options = match args.cmd {
+            Command::List { options } => options,
+            _ => unreachable!(),
+        };
+        assert!(options.show_tags);
+
+        let listed: Vec<&str> = listed_notes(&index, &options)
+            .iter()
+            .map(|note| note.name())
+            .collect();
+        assert_eq!(listed, ["blank"]);
+    }
+
+    #[test]
+    fn list_times() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        add_note(&mut index, "Timed", &file.path().to_path_buf(), &None).unwrap();
+        let legacy: Index = toml::from_str("[[notes]]\nname = 'a'\npath = '/a'\n").unwrap();
+
+        let options = ListOptions {
+            show_created: true,
+            show_modified: true,
+            ..ListOptions::default()
+        };
+        let table = notes_table(&index.find_by(|_| true), &options);
+        let cells: Vec<String> = table
+            .get_row(0)
+            .unwrap()
+            .iter()
+            .map(Cell::get_content)
+            .collect();
+        assert_eq!(cells.len(), 3);
+        let created = index.get("Timed").unwrap().created().unwrap();
+        assert_eq!(
+            cells[1],
+            time::format_timestamp(created, SystemTime::now(), false)
+        );
+
+        let table = notes_table(&legacy.find_by(|_| true), &options);
+        let row = table.get_row(0).unwrap();
+        assert_eq!(row.get_cell(1).unwrap().get_content(), "");
+        assert_eq!(row.get_cell(2).unwrap().get_content(), "");
+
+        let relative = ListOptions {
+            relative_time: true,
+            ..options
+        };
+        let table = notes_table(&index.find_by(|_| true), &relative);
+        let row = table.get_row(0).unwrap();
+        assert_eq!(row.get_cell(1).unwrap().get_content(), "just now");
+    }
+
+    #[test]
+    fn list_sorted_tags() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags = ["zebra", "Apple", "mango"]
+            .iter()
+            .map(|tag| tag.to_string());
+
+        add_note(&mut index, "Fruit", &path, &Some(tags.collect())).unwrap();
+
+        let notes = index.find_by(|_| true);
+        let tag_cell = |sort_tags| {
+            let options = ListOptions {
+                show_tags: true,
+                sort_tags,
+                ..ListOptions::default()
+            };
+            notes_table(&notes, &options)
+                .get_row(0)
+                .unwrap()
+                .get_cell(1)
+                .unwrap()
+                .get_content()
+        };
+
+        assert_eq!(tag_cell(false), "zebra,\nApple,\nmango");
+        assert_eq!(tag_cell(true), "Apple,\nmango,\nzebra");
+    }
+
+    #[test]
+    fn list_changed_notes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+
+        for name in &["edited", "untouched"] {
+            let path = dir.path().join(name);
+            add_written_note(&mut index, name, &path, "", &None);
+        }
+
+        let recorded = index.get("edited").unwrap().modified().unwrap();
+        let later = FileTime::from_system_time(recorded + Duration::from_secs(60));
+        filetime::set_file_mtime(dir.path().join("edited"), later).unwrap();
+
+        let list = |index: &mut Index, args: &[&str]| {
+            let args = crate::scrivener::args::Args::from_iter(&[&["scrv", "list"], args].concat());
+            let options = match &args.cmd {
+                Command::List { options } => options,
+                _ => unreachable!(),
+            };
+            let listed: Vec<String> = listed_notes(index, options)
+                .iter()
+                .map(|note| note.name().to_string())
+                .collect();
+            assert_eq!(args.cmd.modifies_index(), options.refresh);
+            args.cmd.execute(index, &Config::default(), false).unwrap();
+            listed
+        };
+
+        assert_eq!(list(&mut index, &["--changed"]), ["edited"]);
+        assert_eq!(list(&mut index, &["--changed", "--refresh"]), ["edited"]);
+        assert!(list(&mut index, &["--changed"]).is_empty());
+
+        // Only the notes that are shown are refreshed.
+        for name in &["edited", "untouched"] {
+            let later = FileTime::from_system_time(recorded + Duration::from_secs(120));
+            filetime::set_file_mtime(dir.path().join(name), later).unwrap();
+        }
+        list(
+            &mut index,
+            &["--changed", "--refresh", "--max-results", "1"],
+        );
+        assert_eq!(list(&mut index, &["--changed"]), ["untouched"]);
+    }
+
+    #[test]
+    fn list_in_added_order() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        for name in &["Zebra", "Apple", "Mango"] {
+            add_note(&mut index, name, &path, &None).unwrap();
+        }
+
+        let listed = |args: &[&str]| {
+            let args = crate::scrivener::args::Args::from_iter(&[&["scrv", "list"], args].concat());
+            match args.cmd {
+                Command::List { options } => listed_notes(&index, &options)
+                    .iter()
+                    .map(|note| note.name().to_string())
+                    .collect::<Vec<_>>(),
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(listed(&[]), ["Apple", "Mango", "Zebra"]);
+        assert_eq!(listed(&["--sort", "added"]), ["Zebra", "Apple", "Mango"]);
+        assert_eq!(listed(&["--reverse"]), ["Zebra", "Mango", "Apple"]);
+    }
+
+    #[test]
+    fn list_by_path_and_tags() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+        let tags = |count| Some((0..count).map(|tag| tag.to_string()).collect());
+
+        for (name, file, count) in &[("Apple", "c", 2), ("Mango", "a", 0), ("Zebra", "b", 1)] {
+            let path = dir.path().join(file);
+            add_written_note(&mut index, name, &path, "", &tags(*count));
+        }
+
+        let listed = |args: &[&str]| {
+            let args = crate::scrivener::args::Args::from_iter(&[&["scrv", "list"], args].concat());
+            match args.cmd {
+                Command::List { options } => listed_notes(&index, &options)
+                    .iter()
+                    .map(|note| note.name().to_string())
+                    .collect::<Vec<_>>(),
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(listed(&["--sort", "path"]), ["Mango", "Zebra", "Apple"]);
+        assert_eq!(listed(&["--sort", "tags"]), ["Mango", "Zebra", "Apple"]);
+        assert_eq!(
+            listed(&["--sort", "tags", "--reverse"]),
+            ["Apple", "Zebra", "Mango"]
+        );
+        assert!("size".parse::<ListSort>().is_err());
+    }
+
+    #[test]
+    fn list_width_and_max_col() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags = Some(vec!["a-rather-long-tag".to_string(), "short".to_string()]);
+
+        add_note(
+            &mut index,
+            "A note with a very long name indeed",
+            &path,
+            &tags,
+        )
+        .unwrap();
+        add_note(&mut index, "Short", &path, &None).unwrap();
+
+        let notes = index.find_by(|_| true);
+        let widths = |options: &ListOptions| {
+            notes_table(&notes, options)
+                .to_string()
+                .lines()
+                .map(|line| line.trim_end().chars().count())
+                .collect::<Vec<_>>()
+        };
+
+        let options = ListOptions {
+            show_tags: true,
+            max_col: Some(10),
+            ..ListOptions::default()
+        };
+        let table = notes_table(&notes, &options).to_string();
+        assert!(table.contains("A note wi…"));
+        assert!(table.contains("a-rather-…"));
+        assert!(table.contains("short"));
+        assert!(!table.contains("very long"));
+
+        let options = ListOptions {
+            show_tags: true,
+            width: Some(24),
+            ..ListOptions::default()
+        };
+        assert!(widths(&options).iter().all(|&width| width <= 24));
+
+        let unlimited = ListOptions {
+            show_tags: true,
+            ..ListOptions::default()
+        };
+        assert!(widths(&unlimited).iter().any(|&width| width > 24));
+
+        // Section headings have a single cell, which must not hide the
+        // rows below them from the other columns.
+        let sectioned = ListOptions {
+            show_tags: true,
+            sections: true,
+            max_col: Some(30),
+            ..ListOptions::default()
+        };
+        let table = notes_table(&notes, &sectioned).to_string();
+        assert!(table.contains("a-rather-long-tag"));
+    }
+
+    #[test]
+    fn list_json() {
+        let mut index = Index::new();
+        let json = |index: &Index| {
+            let mut out = Vec::new();
+            write_json(&index.find_by(|_| true), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(json(&index), "[]\n");
+
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        add_note(&mut index, "First", &path, &tags(&["tag"])).unwrap();
+        add_note(&mut index, "Second", &path, &None).unwrap();
+
+        let notes: serde_json::Value = serde_json::from_str(&json(&index)).unwrap();
+        let stored = index.get("First").unwrap().path().to_str().unwrap();
+        assert_eq!(
+            notes,
+            serde_json::json!([
+                {"name": "First", "path": stored, "tags": ["tag"]},
+                {"name": "Second", "path": stored, "tags": []},
+            ])
+        );
+    }
+
+    #[test]
+    fn list_json_lines() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "First", &path, &tags(&["tag"])).unwrap();
+        add_note(&mut index, "Second", &path, &None).unwrap();
+        add_note(&mut index, "Third", &path, &None).unwrap();
+
+        let notes = index.find_by(|_| true);
+        let lines = |options: &ListOptions| {
+            let mut out = Vec::new();
+            write_json_lines(&notes, options, &mut out).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let options = ListOptions {
+            show_tags: true,
+            absolute_paths: true,
+            ..ListOptions::default()
+        };
+        let objects = lines(&options);
+        assert_eq!(objects.len(), 3);
+        assert!(objects.iter().all(serde_json::Value::is_object));
+        assert_eq!(objects[0]["name"], "First");
+        assert_eq!(objects[0]["tags"], serde_json::json!(["tag"]));
+        assert_eq!(objects[1]["tags"], serde_json::json!([]));
+        assert!(objects[0].get("path").is_none());
+
+        let options = ListOptions {
+            show_paths: true,
+            absolute_paths: true,
+            ..ListOptions::default()
+        };
+        let objects = lines(&options);
+        assert_eq!(objects[2]["path"], path.to_str().unwrap());
+        assert!(objects[2].get("tags").is_none());
+    }
+
+    #[test]
+    fn relative_path_styles() {
+        let base = Path::new("/home/user/notes");
+        let rel = |path, style| relative_to(Path::new(path), base, style);
+
+        for &(path, dotted, bare) in &[
+            ("/home/user/notes/a.txt", "./a.txt", "a.txt"),
+            ("/home/user/notes/work/a.txt", "./work/a.txt", "work/a.txt"),
+            ("/home/user/other/a.txt", "../other/a.txt", "../other/a.txt"),
+            ("/home/a.txt", "../../a.txt", "../../a.txt"),
+            ("/home/user/notes", ".", "."),
+            ("/home/user", "..", ".."),
+            ("/home", "/home", "/home"),
+            ("/a.txt", "/a.txt", "/a.txt"),
+        ] {
+            assert_eq!(rel(path, PathStyle::Dotted), dotted);
+            assert_eq!(rel(path, PathStyle::Bare), bare);
+        }
+    }
+
+    #[test]
+    fn max_results_cap() {
+        let mut results: Vec<u32> = (0..10).collect();
+        assert_eq!(limit_results(&mut results, Some(3)), 7);
+        assert_eq!(results, [0, 1, 2]);
+        assert_eq!(omitted_footer(7).unwrap(), "…and 7 more");
+
+        assert_eq!(limit_results(&mut results, Some(5)), 0);
+        assert_eq!(limit_results(&mut results, None), 0);
+        assert_eq!(results.len(), 3);
+        assert_eq!(omitted_footer(0), None);
+
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "list", "--max-results", "2"]);
+        match args.cmd {
+            Command::List { options } => assert_eq!(options.max_results, Some(2)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn truncate_lines() {
+        assert_eq!(truncate("abcdef", 6), "abcdef");
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("åäöüéè", 3), "åä…");
+    }
+
+    #[test]
+    fn list_without_header() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        add_note(&mut index, "Test List", &path, &tags(&["tag"])).unwrap();
+
+        let mut options = ListOptions {
+            show_tags: true,
+            ..ListOptions::default()
+        };
+        let notes = index.find_by(|_| true);
+
+        let table = notes_table(&notes, &options).to_string();
+        assert!(table.contains("Notes"));
+        assert!(table.contains("Tags"));
+
+        options.no_header = true;
+        let table = notes_table(&notes, &options).to_string();
+        assert!(!table.contains("Notes"));
+        assert!(!table.contains("Tags"));
+        assert_eq!(table.lines().count(), 1);
+        assert!(table.contains("Test List"));
+    }
+
+    #[test]
+    fn convert_to_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("scrivener-config.toml");
+        let toml_path = dir.path().join("scrivener.toml");
+        let json_path = dir.path().join("scrivener.json");
+        let note_path = dir.path().join("note.txt");
+        fs::write(&note_path, "").unwrap();
+
+        let config = Config::default();
+        let mut index = Index::new();
+        add_note(&mut index, "Note", &note_path, &None).unwrap();
+        index.store_path(&toml_path, &config).unwrap();
+
+        convert_index(
+            &config,
+            &config_path,
+            &toml_path,
+            &json_path,
+            IndexFormat::Json,
+        )
+        .unwrap();
+
+        assert!(!toml_path.exists());
+        assert_eq!(Index::load_path(&json_path).unwrap(), index);
+        assert_eq!(
+            Config::load_path(&config_path).unwrap().index_format,
+            IndexFormat::Json
+        );
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+
+        for (name, contents) in &[("a", "same"), ("b", "same"), ("c", "other")] {
+            let path = dir.path().join(name);
+            add_written_note(&mut index, name, &path, contents, &tags(&[name]));
+        }
+
+        find_duplicates(&mut index, false, true, &Config::default()).unwrap();
+        assert_eq!(index.notes().len(), 3);
+
+        find_duplicates(&mut index, true, true, &Config::default()).unwrap();
+        assert!(!index.contains("b"));
+        assert!(!dir.path().join("b").exists());
+        assert!(dir.path().join("a").exists());
+        assert_eq!(
+            index.get("a").unwrap().tags(),
+            &Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(index.contains("c"));
+    }
+
+    #[test]
+    fn failed_merge_is_undone() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+
+        for (name, contents) in &[("a", "one"), ("b", "one"), ("c", "two"), ("d", "two")] {
+            let path = dir.path().join(name);
+            add_written_note(&mut index, name, &path, contents, &tags(&[name]));
+        }
+        // `e` shares `d`'s file, which is gone by the time `e` is merged.
+        add_note(&mut index, "e", &dir.path().join("d"), &None).unwrap();
+        let before = index.clone();
+
+        let error = find_duplicates(&mut index, true, true, &Config::default()).unwrap_err();
+        assert!(error.to_string().contains("Could not delete note `e`"));
+
+        assert_eq!(index, before);
+        for name in &["a", "b", "c", "d"] {
+            assert!(dir.path().join(name).exists());
+        }
+        assert_eq!(fs::read_to_string(dir.path().join("d")).unwrap(), "two");
+    }
+
+    #[test]
+    fn configure_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config/scrivener-config.toml");
+
+        configure(&path, Some("strip_all_extensions"), Some("true"), false).unwrap();
+        assert!(Config::load_path(&path).unwrap().strip_all_extensions);
+
+        assert!(configure(&path, Some("editor_colour"), Some("red"), false).is_err());
+        assert!(configure(&path, Some("editor_colour"), None, false).is_err());
+        assert!(configure(&path, None, None, false).is_ok());
+    }
+
+    #[test]
+    fn config_get_and_set_forms() {
+        let setting = |argv: &[&str]| {
+            let args = crate::scrivener::args::Args::from_iter(argv);
+            args.cmd
+                .config_setting()
+                .map(|(key, value)| (key.map(String::from), value.map(String::from)))
+        };
+        let some =
+            |key: &str, value: Option<&str>| Some((Some(key.to_string()), value.map(String::from)));
+
+        assert_eq!(
+            setting(&["scrv", "config", "set", "editor", "nvim"]),
+            some("editor", Some("nvim"))
+        );
+        assert_eq!(
+            setting(&["scrv", "config", "editor", "nvim"]),
+            some("editor", Some("nvim"))
+        );
+        assert_eq!(
+            setting(&["scrv", "config", "get", "default-dir"]),
+            some("default-dir", None)
+        );
+        assert_eq!(setting(&["scrv", "config", "editor"]), some("editor", None));
+        assert_eq!(setting(&["scrv", "config"]), Some((None, None)));
+        assert_eq!(setting(&["scrv", "list"]), None);
+    }
+
+    #[test]
+    fn verify_missing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let note_path = dir.path().join("note.txt");
+
+        let mut index = Index::new();
+        add_written_note(&mut index, "Note", &note_path, "", &tags(&["work", "work"]));
+        assert_eq!(
+            verify_notes(&mut index, false, &Config::default()).unwrap(),
+            1
+        );
+
+        // The tags are fixed even though the missing file is still a problem.
+        fs::remove_file(&note_path).unwrap();
+        assert_eq!(
+            verify_notes(&mut index, true, &Config::default()).unwrap(),
+            1
+        );
+        assert_eq!(index.get("Note").unwrap().tags(), &tags(&["work"]));
+        assert!(index.is_dirty());
+
+        fs::write(&note_path, "").unwrap();
+        assert_eq!(
+            verify_notes(&mut index, false, &Config::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn export_and_import_sidecars() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let note_path = dir.path().join("note.txt");
+
+        let mut index = Index::new();
+        add_written_note(&mut index, "Note", &note_path, "", &tags(&["tag"]));
+
+        let export = crate::scrivener::args::Args::from_iter(&["scrv", "export", "--per-note"]);
+        export
+            .cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
+        assert!(dir.path().join("note.txt.scrivener.json").exists());
+
+        let mut rebuilt = Index::new();
+        import_sidecars(&mut rebuilt, dir.path(), &Config::default()).unwrap();
+        assert_eq!(rebuilt, index);
+        assert_eq!(rebuilt.get("Note").unwrap().tags(), &tags(&["tag"]));
+    }
+
+    #[test]
+    fn parse_times() {
+        let secs = |text| {
+            let time = parse_time(text).unwrap();
+            time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        };
+
+        assert_eq!(secs("86400"), 86400);
+        assert_eq!(secs("1970-01-02"), 86400);
+        assert_eq!(secs("1970-01-02T01:00:00+01:00"), 86400);
+        assert!(parse_time("yesterday").is_err());
+        assert!(parse_time("1969-12-31").is_err());
+    }
+
+    #[test]
+    fn export_since() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let note_path = dir.path().join("note.txt");
+        let bundle = dir.path().join("bundle.toml");
 
-    /// Removes a note from the notes index without deleting the file
-    Remove {
-        /// The name of the note to remove
-        name: String,
-    },
-    /// Removes a note from the notes index and deletes its file
-    Delete {
-        /// The name of the note to delete
-        name: String,
-    },
-    /// Lists all notes
-    List {
-        /// Show each note file's path
-        #[structopt(short = "p", long = "paths")]
-        show_paths: bool,
+        let mut index = Index::new();
+        add_written_note(&mut index, "Recent", &note_path, "", &None);
 
-        /// Show each note's tags
-        #[structopt(short = "t", long = "tags")]
-        show_tags: bool,
-    }, // /// Searches all notes for notes with a given name or tag
-       // TODO: Search {}
+        let config = Config::default();
+        let future = SystemTime::now() + Duration::from_secs(3600);
 
-       // /// Runs a note if it is marked as executable
-       // TODO: Run {}
-}
+        export_notes(&index, &bundle, Some(future), &config).unwrap();
+        assert!(Index::load_path(&bundle).unwrap().notes().is_empty());
 
-impl Command {
-    /// Executes a function that corresponds to the outcome of a
-    /// subcommand.
-    pub fn execute(&self, index: &mut Index) -> Result<(), Error> {
-        match self {
-            Command::New { name, path, tags } => create_new_note(index, name, path, tags),
-            Command::Add { name, path, tags } => add_note(index, name, path, tags),
-            Command::Edit { name } => edit_note(index, name),
-            Command::Remove { name } => remove_note(index, name),
-            Command::Delete { name } => delete_note(index, name),
-            Command::List {
-                show_paths,
-                show_tags,
-            } => list_notes(index, *show_paths, *show_tags),
-        }
+        export_notes(&index, &bundle, Some(UNIX_EPOCH), &config).unwrap();
+
+        let mut other = Index::new();
+        import_notes(&mut other, &bundle, &Config::default()).unwrap();
+        assert!(other.contains("Recent"));
     }
-}
 
-/// Adds an existing file to the `Index`.
-///
-/// # Errors
-///
-/// - When a `Note` with the same name as the one being added already
-/// exists in the `Index`.
-fn add_note(
-    index: &mut Index,
-    name: &str,
-    path: &PathBuf,
-    tags: &Option<Vec<String>>,
-) -> Result<(), Error> {
-    failure::ensure!(!index.contains(name), errors::already_exists(name));
+    #[test]
+    fn apply_meta_from_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("note.txt");
+        fs::write(&file, "").unwrap();
 
-    index.add(name, path, tags)?;
+        let mut index = Index::new();
+        for name in &["First", "Second", "Third"] {
+            add_note(&mut index, name, &file, &tags(&["draft"])).unwrap();
+        }
 
-    println!("Note `{}` at {} added successfully.", name, path.display());
+        let fixture = dir.path().join("meta.json");
+        fs::write(
+            &fixture,
+            r#"{
+                "First": {"tags": ["work", "done"]},
+                "Second": {"add_tags": ["work"]},
+                "Third": {"tags": []}
+            }"#,
+        )
+        .unwrap();
 
-    Ok(())
-}
+        let args = ["scrv", "apply-meta", fixture.to_str().unwrap()];
+        crate::scrivener::args::Args::from_iter(&args)
+            .cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
 
-/// Creates a file and adds it as a `Note` to the `Index`
-///
-/// If `None` is given as the path, the path used is the current
-/// working directory.
-///
-/// Prompts a user for input by opening a temportary file with
-/// the user's default texteditor.
-///
-/// For Linux, this is the value of $EDITOR. If $EDITOR is not set,
-/// vi is used instead.
-///
-/// # Panics
-///
-/// - $EDITOR is set to an invalid command
-///
-/// # Errors
-///
-/// - A `Note` with the same name as the one being added exists.
-///
-/// - The path given is a directory, already has a file, or is
-/// otherwise inaccessible.
-fn create_new_note(
-    index: &mut Index,
-    name: &str,
-    path: &Option<PathBuf>,
-    tags: &Option<Vec<String>>,
-) -> Result<(), Error> {
-    let path = match path {
-        Some(path) => path.clone(),
-        None => {
-            let mut path = std::env::current_dir()
-                .with_context(|_| errors::could_not("access current directory"))?;
-            path.push(format!("{}.txt", &name));
-            path
+        let tags =
+            |index: &Index, name| index.get(name).unwrap().tags().clone().unwrap_or_default();
+        assert_eq!(tags(&index, "First"), ["work", "done"]);
+        assert_eq!(tags(&index, "Second"), ["draft", "work"]);
+        assert!(tags(&index, "Third").is_empty());
+
+        fs::write(&fixture, r#"{"First": {"tags": []}, "Missing": {}}"#).unwrap();
+        assert!(apply_meta(&mut index, &fixture, &Config::default()).is_err());
+        assert_eq!(tags(&index, "First"), ["work", "done"]);
+    }
+
+    #[test]
+    fn export_to_git_repo() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = dir.path().join("repo");
+        let mut index = Index::new();
+
+        for (name, file) in &[("First", "a/note.txt"), ("Second", "b/note.txt")] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            add_written_note(&mut index, name, &path, name, &None);
         }
-    };
 
-    failure::ensure!(!index.contains(name), errors::already_exists(name));
+        let export = |index: &mut Index| {
+            let args = ["scrv", "export", "--git-init", repo.to_str().unwrap()];
+            crate::scrivener::args::Args::from_iter(&args)
+                .cmd
+                .execute(index, &Config::default(), false)
+                .unwrap();
+        };
+        let log = || {
+            let output = process::Command::new("git")
+                .args(["log", "--format=%s"])
+                .current_dir(&repo)
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
 
-    failure::ensure!(
-        !path.is_dir(),
-        "{} is a directory, not a file.",
-        path.display()
-    );
-    failure::ensure!(
-        !path.exists(),
-        "A file at {} already exists.",
-        path.display()
-    );
+        export(&mut index);
+        assert!(repo.join(".git").exists());
+        assert!(repo.join(".gitignore").exists());
+        assert_eq!(
+            fs::read_to_string(repo.join("notes/note.txt")).unwrap(),
+            "First"
+        );
+        assert_eq!(
+            fs::read_to_string(repo.join("notes/note-2.txt")).unwrap(),
+            "Second"
+        );
+        assert_eq!(log(), "Export 2 notes from scrivener\n");
 
-    let mut file =
-        File::create(&path).with_context(|_| format!("Could not create {}.", path.display()))?;
+        let exported = Index::load_path(&repo.join("scrivener.toml")).unwrap();
+        assert_eq!(
+            exported.get("Second").unwrap().path(),
+            &repo.join("notes/note-2.txt").canonicalize().unwrap()
+        );
 
-    let text = scrawl::new().with_context(|_| errors::could_not("open editor"))?;
+        // Exporting into the existing repository only commits changes.
+        export(&mut index);
+        assert_eq!(log().lines().count(), 1);
 
-    file.write_all(&text.as_bytes())
-        .with_context(|_| errors::could_not("write to file"))?;
+        // Files that earlier exports did not write are kept.
+        fs::write(repo.join("notes/keep.txt"), "mine").unwrap();
+        index.remove("First");
+        export(&mut index);
+        assert_eq!(log().lines().count(), 2);
+        assert!(!repo.join("notes/note-2.txt").exists());
+        assert!(repo.join("notes/keep.txt").exists());
 
-    add_note(index, name, &path, tags)?;
+        // Notes inside the repository are never exported into it.
+        let inside = repo.join("notes/keep.txt");
+        add_note(&mut index, "Inside", &inside, &None).unwrap();
+        let args = ["scrv", "export", "--git-init", repo.to_str().unwrap()];
+        let error = crate::scrivener::args::Args::from_iter(&args)
+            .cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap_err();
+        assert!(error.to_string().contains("inside"));
+        assert_eq!(fs::read_to_string(&inside).unwrap(), "mine");
 
-    Ok(())
-}
+        let args = ["scrv", "export", "--git-init", "repo", "--since", "0"];
+        assert!(crate::scrivener::args::Args::from_iter_safe(&args).is_err());
+    }
 
-/// Edits an existing note.
-///
-/// Prompts the user for input by opening a temporary file with
-/// the default text editor.
-///
-/// For Linux, this is the value of $EDITOR. If $EDITOR is not set,
-/// vi is used instead.
-///
-/// # Panics
-///
-/// - $EDITOR is set to an invalid command
-///
-/// # Errors
-///
-/// - There is no note with the `name` that is given.
-fn edit_note(index: &mut Index, name: &str) -> Result<(), Error> {
-    let path = match index.get(name) {
-        Some(note) => note.path(),
-        None => failure::bail!(errors::does_not_exist(name)),
-    };
+    #[test]
+    fn organize_into_tag_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
 
-    scrawl::edit(path).with_context(|_| errors::could_not_note("open", name, path))?;
+        for (name, note_tags) in &[
+            ("report", tags(&["misc", "work"])),
+            ("loose", tags(&["misc"])),
+            ("placed", tags(&["work"])),
+        ] {
+            let path = dir.path().join(format!("{}.txt", name));
+            add_written_note(&mut index, name, &path, name, note_tags);
+        }
 
-    errors::successful(name, "edited");
+        let mut config = Config {
+            notes_root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config
+            .tag_dirs
+            .insert("work".to_string(), PathBuf::from("work/reports"));
+        let work = dir.path().join("work/reports");
+        fs::create_dir_all(&work).unwrap();
+        fs::rename(dir.path().join("placed.txt"), work.join("placed.txt")).unwrap();
+        index.set_path("placed", &work.join("placed.txt")).unwrap();
 
-    Ok(())
-}
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "organize"]);
+        args.cmd.execute(&mut index, &config, true).unwrap();
 
-/// Removes a note from the `Index` WITHOUT deleting the
-/// corresponding file.
-///
-/// # Errors
-///
-/// - There is no `Note` in the `Index` with the given name.
-fn remove_note(index: &mut Index, name: &str) -> Result<(), Error> {
-    let status = index.remove(name);
+        let moved = work.join("report.txt").canonicalize().unwrap();
+        assert_eq!(index.get("report").unwrap().path(), &moved);
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "report");
+        assert!(!dir.path().join("report.txt").exists());
+        assert!(dir.path().join("loose.txt").exists());
+        assert!(index
+            .get("placed")
+            .unwrap()
+            .path()
+            .ends_with("work/reports/placed.txt"));
 
-    failure::ensure!(status, errors::does_not_exist(name));
+        // A note sharing a moved file follows it.
+        let shared = dir.path().join("shared.txt");
+        add_written_note(&mut index, "tagged", &shared, "", &tags(&["work"]));
+        add_note(&mut index, "untagged", &shared, &None).unwrap();
+        args.cmd.execute(&mut index, &config, true).unwrap();
 
-    errors::successful(name, "removed");
+        let moved = work.join("shared.txt").canonicalize().unwrap();
+        assert_eq!(index.get("tagged").unwrap().path(), &moved);
+        assert_eq!(index.get("untagged").unwrap().path(), &moved);
+    }
 
-    Ok(())
-}
+    #[test]
+    fn tag_and_untag() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        add_note(&mut index, "mynote", &path, &None).unwrap();
 
-/// Removes a note from the `Index` AND deletes the corresponding
-/// file.
-///
-/// # Errors
-///
-/// - There is no `Note` in the `Index` with the given name.
-///
-/// - The `Note` cannot be deleted.
-fn delete_note(index: &mut Index, name: &str) -> Result<(), Error> {
-    let path = match index.get(name) {
-        Some(note) => note.path(),
-        None => failure::bail!(errors::does_not_exist(name)),
-    };
+        let run = |index: &mut Index, args: &[&str]| {
+            let args = [&["scrv"], args].concat();
+            crate::scrivener::args::Args::from_iter(&args).cmd.execute(
+                index,
+                &Config::default(),
+                false,
+            )
+        };
+        let tags = |index: &Index| index.get("mynote").unwrap().tags().clone();
 
-    fs::remove_file(path).with_context(|_| errors::could_not_note("delete", name, path))?;
+        run(
+            &mut index,
+            &["tag", "mynote", "--", "urgent", "work", "urgent"],
+        )
+        .unwrap();
+        assert_eq!(
+            tags(&index),
+            Some(vec!["urgent".to_string(), "work".to_string()])
+        );
 
-    remove_note(index, name)?;
+        run(&mut index, &["tag", "mynote", "--", "Work"]).unwrap();
+        let error = run(&mut index, &["tag", "mynote", "--", " "]).unwrap_err();
+        assert_eq!(error.to_string(), "Tags cannot be empty.");
+        assert_eq!(
+            tags(&index),
+            Some(vec!["urgent".to_string(), "work".to_string()])
+        );
 
-    errors::successful(name, "deleted");
+        run(&mut index, &["untag", "mynote", "--", "WORK"]).unwrap();
+        assert_eq!(tags(&index), Some(vec!["urgent".to_string()]));
 
-    Ok(())
-}
+        run(&mut index, &["untag", "mynote", "urgent"]).unwrap();
+        assert_eq!(tags(&index), None);
 
-/// Lists all `Note`s in the `Index` in a table printed to the screen
-/// with or without its relative path and tags.
-///
-/// If `show_paths` and `show_tags` are both false, then the table
-/// will have only one column that shows the `Note`s' names
-///
-/// If either `show_paths` or `show_tags` are true, the table will
-/// have two columns, one for the names and one for either the paths
-/// or tags, respectively.
-///
-/// If both `show_paths` and `show_tags` are true, then the table
-/// will have tree columns, with names, paths, and tags.
-///
-/// If the `Index` is empty, then a helpful message will be shown
-/// instead.
-fn list_notes(index: &Index, show_paths: bool, show_tags: bool) -> Result<(), Error> {
-    // If index has no notes, print a helpful message and return.
-    if index.notes().is_empty() {
-        println!("There are no notes to list!");
-        println!("Create one with 'srcv new <name>'");
-        println!("Try 'srcv --help' for more options.");
-        return Ok(());
+        let error = run(&mut index, &["tag", "missing", "work"]).unwrap_err();
+        assert_eq!(error.to_string(), errors::does_not_exist("missing"));
+        assert!(crate::scrivener::args::Args::from_iter_safe(&["scrv", "tag", "mynote"]).is_err());
     }
 
-    let mut table = Table::new();
+    #[test]
+    fn rename_keeps_file() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        add_note(&mut index, "old", &path, &None).unwrap();
+        add_note(&mut index, "taken", &path, &None).unwrap();
 
-    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+        let rename = |index: &mut Index, args: &[&str]| {
+            let args = [&["scrv", "rename"], args].concat();
+            crate::scrivener::args::Args::from_iter(&args).cmd.execute(
+                index,
+                &Config::default(),
+                false,
+            )
+        };
 
-    // Add a cell to the title row that says "Notes" in bold.
-    let mut title = Row::new(vec![Cell::new("Notes").with_style(Attr::Bold)]);
+        let error = rename(&mut index, &["missing", "new"]).unwrap_err();
+        assert_eq!(error.to_string(), errors::does_not_exist("missing"));
+        let error = rename(&mut index, &["old", "taken"]).unwrap_err();
+        assert_eq!(error.to_string(), errors::already_exists("taken"));
 
-    // If show_paths is true, add a cell to the title row that says
-    // "Paths" in bold.
-    if show_paths {
-        title.add_cell(Cell::new("Paths").with_style(Attr::Bold));
+        rename(&mut index, &["old", "new"]).unwrap();
+        assert!(!index.contains("old"));
+        assert_eq!(
+            index.get("new").unwrap().path(),
+            &path.canonicalize().unwrap()
+        );
+        assert!(path.exists());
     }
 
-    // If show_tags is true, add a cell to the title row that says
-    // "Tags" in bold.
-    if show_tags {
-        title.add_cell(Cell::new("Tags").with_style(Attr::Bold));
-    }
+    #[test]
+    fn move_into_directory_or_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        let sub = dir.path().join("sub");
+        fs::write(&path, "words").unwrap();
+        fs::create_dir(&sub).unwrap();
 
-    table.set_titles(title);
+        let mut index = Index::new();
+        add_note(&mut index, "Note", &path, &None).unwrap();
 
-    // For every note in the index
-    for note in &mut index.notes().iter() {
-        // Initialize a row with the note's name in the first cell.
-        let mut row = Row::new(vec![Cell::new(note.name())]);
+        move_note(&mut index, "Note", &sub, true, None, &Config::default()).unwrap();
+        let moved = sub.join("note.txt");
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "words");
+        assert_eq!(
+            index.get("Note").unwrap().path(),
+            &moved.canonicalize().unwrap()
+        );
 
-        // If show_paths is true
-        if show_paths {
-            // Get the current working directory
-            let note_path = note.path();
-            let path = abs_to_rel(note_path);
+        let renamed = dir.path().join("renamed.md");
+        move_note(&mut index, "Note", &renamed, true, None, &Config::default()).unwrap();
+        assert!(renamed.exists());
 
-            // Add the path to the row.
-            row.add_cell(Cell::new(&path));
-        }
+        fs::write(&path, "other").unwrap();
+        let error =
+            move_note(&mut index, "Note", &path, true, None, &Config::default()).unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "other");
 
-        // If show_tags is true
-        if show_tags {
-            let tags = note.tags();
+        let error = move_note(
+            &mut index,
+            "Note",
+            &dir.path().join("missing/x.md"),
+            true,
+            None,
+            &Config::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("Could not move note `Note`"));
+        assert_eq!(
+            index.get("Note").unwrap().path(),
+            &renamed.canonicalize().unwrap()
+        );
 
-            // If the note has tags associated with it
-            if let Some(tags) = tags {
-                // Initialize tag_list as a new String.
-                let mut tag_list = String::new();
+        let error =
+            move_note(&mut index, "Missing", &sub, true, None, &Config::default()).unwrap_err();
+        assert_eq!(error.to_string(), errors::does_not_exist("Missing"));
 
-                // Then, split the tag list into the first element and
-                // every other element
-                if let Some((first, rest)) = tags.split_first() {
-                    // Push the first tag to tag_list
-                    tag_list.push_str(first);
+        // A note sharing the file moves with it.
+        add_note(&mut index, "Sharer", &renamed, &None).unwrap();
+        move_note(&mut index, "Note", &sub, true, None, &Config::default()).unwrap();
+        let moved = sub.join("renamed.md").canonicalize().unwrap();
+        assert_eq!(index.get("Note").unwrap().path(), &moved);
+        assert_eq!(index.get("Sharer").unwrap().path(), &moved);
+    }
 
-                    // For any the remaining tags
-                    for tag in rest {
-                        // Append it to tag_list after a comma and a newline.
-                        tag_list.push_str(&format!(",\n{}", tag));
-                    }
-                }
-                // Add tag_list to the row
-                row.add_cell(Cell::new(&tag_list));
-            } else {
-                // Else, add an empty string to the row
-                row.add_cell(Cell::new(&String::new()));
-            }
-        }
+    #[test]
+    fn rename_tag_with_and_without_merge() {
+        let mut index = Index::new();
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
 
-        // Add the row to the table
-        table.add_row(row);
+        add_note(&mut index, "Both", &path, &tags(&["old", "new"])).unwrap();
+        add_note(&mut index, "Old", &path, &tags(&["old"])).unwrap();
+
+        let rename = |index: &mut Index, args: &[&str]| {
+            let args = [&["scrv", "rename-tag"], args].concat();
+            crate::scrivener::args::Args::from_iter(&args).cmd.execute(
+                index,
+                &Config::default(),
+                false,
+            )
+        };
+
+        let error = rename(&mut index, &["old", "new"]).unwrap_err();
+        assert!(error.to_string().contains("--merge"));
+        assert_eq!(index.get("Both").unwrap().tags(), &tags(&["old", "new"]));
+
+        rename(&mut index, &["old", "new", "--merge"]).unwrap();
+        assert_eq!(index.get("Both").unwrap().tags(), &tags(&["new"]));
+        assert_eq!(index.get("Old").unwrap().tags(), &tags(&["new"]));
+
+        rename(&mut index, &["new", "fresh"]).unwrap();
+        assert_eq!(index.get("Both").unwrap().tags(), &tags(&["fresh"]));
+
+        assert!(rename(&mut index, &["fresh", "fresh", "--merge"]).is_err());
+        assert_eq!(index.get("Both").unwrap().tags(), &tags(&["fresh"]));
+        assert_eq!(index.get("Old").unwrap().tags(), &tags(&["fresh"]));
     }
 
-    // Print the table
-    table.printstd();
+    #[test]
+    fn doctor_fixes_moved_paths() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old = dir.path().join("old");
+        fs::create_dir_all(old.join("work")).unwrap();
 
-    Ok(())
-}
+        let mut index = Index::new();
+        for (name, file) in &[
+            ("top", "top.txt"),
+            ("nested", "work/nested.txt"),
+            ("lost", "lost.txt"),
+        ] {
+            let path = old.join(file);
+            add_written_note(&mut index, name, &path, "", &None);
+        }
+        let outside = NamedTempFile::new().unwrap();
+        add_note(&mut index, "outside", &outside.path().to_path_buf(), &None).unwrap();
 
-/// Determines whether a path is directly inside root
-fn is_in_root(path: &Path) -> bool {
-    let root = "/";
-    path.canonicalize().unwrap().parent().unwrap() == Path::new(root)
-}
+        let old = old.canonicalize().unwrap();
+        let new = dir.path().join("new");
+        fs::rename(&old, &new).unwrap();
+        fs::remove_file(new.join("lost.txt")).unwrap();
 
-/// Converts an absolute path pointing to a file to a relative path
-/// based on the current working directory unless the current working
-/// directory is inaccessible or the path points to a file in root
-fn abs_to_rel(path: &Path) -> String {
-    // If the current directory is accessible
-    if let Ok(current_dir) = std::env::current_dir() {
-        // And If path has the current working directory as its
-        // parent directory
-        if let Ok(rel_path) = path.strip_prefix(&current_dir) {
-            // Then strip path's prefix, add "./{}" to it,
-            // and return it
-            return format!("./{}", rel_path.display());
-        } else {
-            let parent_dir = "../";
+        // The old directory is gone, but is still matched by a path that
+        // only resolves through its parent.
+        let from = new.join("../old");
+        let args = [
+            "scrv",
+            "doctor",
+            "--fix-paths",
+            "--from",
+            from.to_str().unwrap(),
+            "--to",
+            new.to_str().unwrap(),
+        ];
+        let args = crate::scrivener::args::Args::from_iter(&args);
+        assert!(args.cmd.modifies_index());
+        args.cmd
+            .execute(&mut index, &Config::default(), false)
+            .unwrap();
 
-            // Else, set rel_prefix to "../"
-            let mut rel_prefix = String::from(parent_dir);
+        let new = new.canonicalize().unwrap();
+        assert_eq!(index.get("top").unwrap().path(), &new.join("top.txt"));
+        assert_eq!(
+            index.get("nested").unwrap().path(),
+            &new.join("work/nested.txt")
+        );
+        assert_eq!(index.get("lost").unwrap().path(), &old.join("lost.txt"));
+        assert_eq!(index.get("outside").unwrap().path(), outside.path());
 
-            // Set ancestor to the current directory's parent directory
-            let mut ancestor = current_dir.parent().unwrap().to_path_buf();
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "doctor"]);
+        assert!(!args.cmd.modifies_index());
+    }
 
-            let rel_path = loop {
-                // If note_path has parent_dir as a prefix,
-                if let Ok(rel_path) = path.strip_prefix(&ancestor) {
-                    // Then break the loop and return rel_prefix with
-                    // rel_path appended to it.
-                    break format!("{}{}", rel_prefix, rel_path.display());
-                }
+    #[test]
+    fn remove_and_delete_flags() {
+        let dir = tempfile::TempDir::new().unwrap();
 
-                //Else, pop the last component off of parent_dir
-                ancestor.pop();
+        for (args, file_kept) in &[
+            (&["remove"][..], true),
+            (&["remove", "--also-delete"][..], false),
+            (&["delete"][..], false),
+            (&["delete", "--keep-file"][..], true),
+        ] {
+            let path = dir.path().join("note.txt");
+            let mut index = Index::new();
+            add_written_note(&mut index, "Note", &path, "", &None);
 
-                // And append "../" to rel_prefix
-                rel_prefix.push_str(parent_dir);
-            };
+            let mut argv = vec!["scrv", "--yes"];
+            argv.extend(args.iter());
+            argv.push("Note");
+            let args = crate::scrivener::args::Args::from_iter(&argv);
+            args.cmd
+                .execute(&mut index, &Config::default(), args.assume_yes)
+                .unwrap();
 
-            // If rel_paths's parent directory is not root
-            if !is_in_root(Path::new(&rel_path)) {
-                // Then return rel_path
-                rel_path
-            } else {
-                //Else, return path as a string
-                path.to_str().unwrap().to_string()
-            }
+            assert!(!index.contains("Note"));
+            assert_eq!(path.exists(), *file_kept, "{:?}", argv);
         }
-    } else {
-        // Else, return path as a string
-        path.to_str().unwrap().to_string()
     }
-}
 
-//TODO: Improve tests
-#[cfg(test)]
-mod tests {
-    use tempfile::NamedTempFile;
+    #[test]
+    fn delete_prunes_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("notes");
+        let deep = root.join("work/acme");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(root.join("work/stray.pdf"), "").unwrap();
 
-    use super::*;
+        let mut index = Index::new();
+        for name in &["first", "second"] {
+            let path = deep.join(format!("{}.txt", name));
+            add_written_note(&mut index, name, &path, "", &None);
+        }
+
+        let config = Config {
+            notes_root: root.clone(),
+            ..Config::default()
+        };
+        let delete = |index: &mut Index, name| {
+            let args = ["scrv", "--yes", "delete", "--prune-dirs", name];
+            crate::scrivener::args::Args::from_iter(&args)
+                .cmd
+                .execute(index, &config, true)
+                .unwrap();
+        };
+
+        delete(&mut index, "first");
+        assert!(deep.exists());
+
+        delete(&mut index, "second");
+        assert!(!deep.exists());
+        assert!(root.join("work/stray.pdf").exists());
+
+        // Moving a note away prunes the same way.
+        let old = root.join("old/2020");
+        fs::create_dir_all(&old).unwrap();
+        add_written_note(&mut index, "third", &old.join("third.txt"), "", &None);
+        let args = [
+            "scrv",
+            "move",
+            "--prune-dirs",
+            "third",
+            root.to_str().unwrap(),
+        ];
+        crate::scrivener::args::Args::from_iter(&args)
+            .cmd
+            .execute(&mut index, &config, true)
+            .unwrap();
+        assert!(root.join("third.txt").exists());
+        assert!(!root.join("old").exists());
+    }
 
     #[test]
-    fn add_a_note() {
-        let mut index = Index::new();
-        let file = NamedTempFile::new().unwrap();
+    fn failed_delete_keeps_note() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
 
-        let name = "Test Add";
-        let path = file.path().to_path_buf();
-        let tags = Some(vec![String::from("one"), String::from("two")]);
+        let mut index = Index::new();
+        add_written_note(&mut index, "Note", &path, "", &tags(&["kept"]));
 
-        add_note(&mut index, name, &path, &tags).unwrap();
+        // A directory in the note's place can't be removed as a file.
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
 
-        let mut expected = Index::new();
-        expected.add(name, &path, &tags).unwrap();
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "delete", "Note", "--yes"]);
+        assert!(args
+            .cmd
+            .execute(&mut index, &Config::default(), args.assume_yes)
+            .is_err());
 
-        assert_eq!(index, expected);
+        let note = index.get("Note").unwrap();
+        assert_eq!(note.path(), &path);
+        assert!(note.has_tag("kept", false));
     }
 
     #[test]
-    fn remove_a_note() {
+    fn assume_yes_deletes_without_prompt() {
         let mut index = Index::new();
         let file = NamedTempFile::new().unwrap();
 
-        let name = "Test Remove";
+        let name = "Test Delete";
         let path = file.path().to_path_buf();
 
         add_note(&mut index, name, &path, &None).unwrap();
 
-        remove_note(&mut index, name).unwrap();
+        let args = crate::scrivener::args::Args::from_iter(&["scrv", "delete", name, "--yes"]);
+        args.cmd
+            .execute(&mut index, &Config::default(), args.assume_yes)
+            .unwrap();
 
         assert_eq!(index, Index::new());
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_returns_exit_code() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/sh\nexit 3\n").unwrap();
+
+        let mut index = Index::new();
+        add_note(&mut index, "script", &script, &None).unwrap();
+
+        let error = run_note(&index, "missing", &[]).unwrap_err();
+        assert_eq!(error.to_string(), errors::does_not_exist("missing"));
+        let error = run_note(&index, "script", &[]).unwrap_err();
+        assert!(error.to_string().contains("is not executable"));
+
+        // A failing note's exit code is returned instead of exiting.
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let run = crate::scrivener::args::Args::from_iter(&["scrv", "run", "script"]);
+        let code = run.cmd.execute(&mut index, &Config::default(), false);
+        assert_eq!(code.unwrap(), 3);
     }
 }
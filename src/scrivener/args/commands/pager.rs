@@ -0,0 +1,65 @@
+//! Showing long output one screen at a time.
+
+use failure::{Error, ResultExt};
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::errors;
+
+/// Returns the command for the user's pager: the value of $PAGER, or
+/// less if it is not set.
+pub(super) fn default_pager() -> String {
+    env::var("PAGER").unwrap_or_else(|_| String::from("less"))
+}
+
+/// Runs `pager` through the shell with `text` as its input, and waits
+/// for it to exit.
+///
+/// # Errors
+///
+/// - The pager cannot be started or exits unsuccessfully.
+pub(super) fn page(pager: &str, text: &[u8]) -> Result<(), Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(pager)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|_| errors::could_not(&format!("run pager `{}`", pager)))?;
+
+    // A pager that is quit early stops reading, which is not an error.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text);
+    }
+
+    let status = child
+        .wait()
+        .with_context(|_| errors::could_not(&format!("run pager `{}`", pager)))?;
+    failure::ensure!(
+        status.success(),
+        "Pager `{}` failed with {}.",
+        pager,
+        status
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn pages_text() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("paged.txt");
+
+        page(&format!("cat > {}", out.display()), b"long note\n").unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "long note\n");
+
+        assert!(page("exit 2", b"").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Progress bars for commands that go through many files.
+//!
+//! Bars are only drawn on a terminal, only when scrivener is built with
+//! the `progress` feature, and never under `--quiet` or JSON output.
+//! Otherwise, `Progress` does nothing.
+
+/// A progress bar on stderr showing how many items have been done out
+/// of the total.
+#[derive(Debug)]
+pub(super) struct Progress {
+    #[cfg(feature = "progress")]
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    /// Starts a progress bar labelled with `message`, which is drawn
+    /// only if stderr is a terminal and `hidden` is false.
+    ///
+    /// Callers pass `hidden` when output is quiet or printed as JSON, so
+    /// that scripts never see a bar.
+    pub(super) fn on_stderr(message: &str, hidden: bool) -> Progress {
+        Progress::new(message, !hidden && atty::is(atty::Stream::Stderr))
+    }
+
+    /// Starts a progress bar labelled with `message`, which is drawn
+    /// only if `show` is true.
+    #[cfg(feature = "progress")]
+    pub(super) fn new(message: &str, show: bool) -> Progress {
+        let bar = if show {
+            let bar = indicatif::ProgressBar::new(0);
+            if let Ok(style) =
+                indicatif::ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len}")
+            {
+                bar.set_style(style.progress_chars("=> "));
+            }
+            bar.set_message(message.to_string());
+            Some(bar)
+        } else {
+            None
+        };
+
+        Progress { bar }
+    }
+
+    /// Starts a progress bar, which is never drawn without the
+    /// `progress` feature.
+    #[cfg(not(feature = "progress"))]
+    pub(super) fn new(_message: &str, _show: bool) -> Progress {
+        Progress {}
+    }
+
+    /// Returns `true` if the bar is drawn.
+    #[cfg(test)]
+    fn is_shown(&self) -> bool {
+        #[cfg(feature = "progress")]
+        return self.bar.is_some();
+
+        #[cfg(not(feature = "progress"))]
+        false
+    }
+
+    /// Shows that `done` of `total` items have been done.
+    pub(super) fn update(&self, done: usize, total: usize) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.set_length(total as u64);
+            bar.set_position(done as u64);
+        }
+
+        #[cfg(not(feature = "progress"))]
+        let _ = (done, total);
+    }
+
+    /// Removes the bar, so that it does not get in the way of the
+    /// command's output.
+    pub(super) fn finish(self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_when_not_a_terminal() {
+        let progress = Progress::new("Scanning", false);
+        assert!(!progress.is_shown());
+        progress.update(1, 2);
+        progress.finish();
+
+        // Nothing is drawn when stderr is redirected to a file or pipe.
+        if !atty::is(atty::Stream::Stderr) {
+            assert!(!Progress::on_stderr("Scanning", false).is_shown());
+        }
+    }
+
+    #[test]
+    fn hidden_when_quiet() {
+        assert!(!Progress::on_stderr("Scanning", true).is_shown());
+    }
+}
@@ -0,0 +1,228 @@
+//! Interactive prompts.
+//!
+//! With the `fuzzy-select` feature, `pick` shows a list that narrows as
+//! the user types. Otherwise, it falls back to a numbered list that is
+//! narrowed one line at a time.
+
+use failure::{Error, ResultExt};
+use std::io::{self, BufRead, Write};
+
+/// Asks the user a yes or no question and returns their answer.
+///
+/// Returns `true` without asking if `assume_yes` is set, so that
+/// scripts can skip every confirmation with a single flag.
+pub(super) fn confirm(question: &str, assume_yes: bool) -> Result<bool, Error> {
+    if assume_yes {
+        return Ok(true);
+    }
+
+    let stdin = io::stdin();
+    confirm_from(question, &mut stdin.lock(), &mut io::stdout())
+}
+
+/// Asks the user to choose one of `options` from a numbered list.
+///
+/// Returns the index of the chosen option, or `None` if the user did
+/// not enter a valid number.
+pub(super) fn choose(question: &str, options: &[&str]) -> Result<Option<usize>, Error> {
+    let stdin = io::stdin();
+    choose_from(question, options, &mut stdin.lock(), &mut io::stdout())
+}
+
+/// Asks the user to pick one of `options` from a list on the terminal
+/// that narrows as they type.
+///
+/// Returns the index of the picked option, or `None` if the user
+/// pressed Escape.
+#[cfg(feature = "fuzzy-select")]
+pub(super) fn pick(question: &str, options: &[&str]) -> Result<Option<usize>, Error> {
+    let picked = dialoguer::FuzzySelect::new()
+        .with_prompt(question)
+        .items(options)
+        .default(0)
+        .interact_opt()
+        .with_context(|_| "could not read answer")?;
+
+    Ok(picked)
+}
+
+/// Asks the user to pick one of `options`, narrowing the list by
+/// typing part of an option until one is left or choosing by number.
+///
+/// Returns the index of the picked option, or `None` if the user
+/// entered nothing or an invalid number.
+#[cfg(not(feature = "fuzzy-select"))]
+pub(super) fn pick(question: &str, options: &[&str]) -> Result<Option<usize>, Error> {
+    let stdin = io::stdin();
+    pick_from(question, options, &mut stdin.lock(), &mut io::stdout())
+}
+
+/// Returns `true` if the characters of `query` appear in `option` in
+/// order, ignoring case, so `mtg` matches `Meeting`.
+#[cfg(not(feature = "fuzzy-select"))]
+fn fuzzy_match(option: &str, query: &str) -> bool {
+    let mut option = option.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| option.any(|o| o == c))
+}
+
+/// Asks the user to pick an option using the given input and output.
+#[cfg(not(feature = "fuzzy-select"))]
+fn pick_from<R: BufRead, W: Write>(
+    question: &str,
+    options: &[&str],
+    input: &mut R,
+    output: &mut W,
+) -> Result<Option<usize>, Error> {
+    let mut shown: Vec<usize> = (0..options.len()).collect();
+
+    loop {
+        for (number, &option) in shown.iter().enumerate() {
+            writeln!(output, "{:>3}) {}", number + 1, options[option])
+                .with_context(|_| "could not write prompt")?;
+        }
+        write!(output, "{} (number, or text to narrow) ", question)
+            .with_context(|_| "could not write prompt")?;
+        output.flush().with_context(|_| "could not write prompt")?;
+
+        let mut answer = String::new();
+        input
+            .read_line(&mut answer)
+            .with_context(|_| "could not read answer")?;
+        let answer = answer.trim();
+
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        if let Ok(number) = answer.parse::<usize>() {
+            return Ok(if number >= 1 && number <= shown.len() {
+                Some(shown[number - 1])
+            } else {
+                None
+            });
+        }
+
+        let narrowed: Vec<usize> = shown
+            .iter()
+            .copied()
+            .filter(|&option| fuzzy_match(options[option], answer))
+            .collect();
+
+        match narrowed.len() {
+            0 => writeln!(output, "Nothing matches `{}`.", answer)
+                .with_context(|_| "could not write prompt")?,
+            1 => return Ok(Some(narrowed[0])),
+            _ => shown = narrowed,
+        }
+    }
+}
+
+/// Asks the user to choose an option using the given input and output.
+fn choose_from<R: BufRead, W: Write>(
+    question: &str,
+    options: &[&str],
+    input: &mut R,
+    output: &mut W,
+) -> Result<Option<usize>, Error> {
+    for (number, option) in options.iter().enumerate() {
+        writeln!(output, "{:>3}) {}", number + 1, option)
+            .with_context(|_| "could not write prompt")?;
+    }
+    write!(output, "{} ", question).with_context(|_| "could not write prompt")?;
+    output.flush().with_context(|_| "could not write prompt")?;
+
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .with_context(|_| "could not read answer")?;
+
+    Ok(match answer.trim().parse::<usize>() {
+        Ok(number) if number >= 1 && number <= options.len() => Some(number - 1),
+        _ => None,
+    })
+}
+
+/// Asks a yes or no question using the given input and output.
+///
+/// Only answers starting with `y` or `Y` count as yes.
+fn confirm_from<R: BufRead, W: Write>(
+    question: &str,
+    input: &mut R,
+    output: &mut W,
+) -> Result<bool, Error> {
+    write!(output, "{} [y/N] ", question).with_context(|_| "could not write prompt")?;
+    output.flush().with_context(|_| "could not write prompt")?;
+
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .with_context(|_| "could not read answer")?;
+
+    Ok(answer.trim_start().starts_with(['y', 'Y']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(text: &str) -> bool {
+        confirm_from("Continue?", &mut text.as_bytes(), &mut Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn confirm_answers() {
+        assert!(answer("y\n"));
+        assert!(answer("Yes\n"));
+        assert!(!answer("n\n"));
+        assert!(!answer("\n"));
+        assert!(!answer(""));
+    }
+
+    #[test]
+    fn choose_answers() {
+        let options = ["first", "second"];
+        let choose = |text: &str| {
+            choose_from("Which?", &options, &mut text.as_bytes(), &mut Vec::new()).unwrap()
+        };
+
+        assert_eq!(choose("1\n"), Some(0));
+        assert_eq!(choose(" 2 \n"), Some(1));
+        assert_eq!(choose("3\n"), None);
+        assert_eq!(choose("0\n"), None);
+        assert_eq!(choose("second\n"), None);
+    }
+
+    #[cfg(not(feature = "fuzzy-select"))]
+    #[test]
+    fn fuzzy_matches() {
+        assert!(fuzzy_match("Meeting notes", "mtg"));
+        assert!(fuzzy_match("Meeting notes", "NOTES"));
+        assert!(!fuzzy_match("Meeting notes", "gm"));
+        assert!(fuzzy_match("anything", ""));
+    }
+
+    #[cfg(not(feature = "fuzzy-select"))]
+    #[test]
+    fn pick_answers() {
+        let options = ["Journal", "Meeting 1", "Meeting 2", "Recipes"];
+        let pick = |text: &str| {
+            pick_from("Which?", &options, &mut text.as_bytes(), &mut Vec::new()).unwrap()
+        };
+
+        assert_eq!(pick("4\n"), Some(3));
+        assert_eq!(pick("rcp\n"), Some(3));
+        assert_eq!(pick("meet\n2\n"), Some(2));
+        assert_eq!(pick("meet\nxyz\n1\n"), Some(1));
+        assert_eq!(pick("meet\n3\n"), None);
+        assert_eq!(pick("\n"), None);
+        assert_eq!(pick(""), None);
+    }
+
+    #[test]
+    fn assume_yes_skips_prompt() {
+        assert!(confirm("Continue?", true).unwrap());
+    }
+}
@@ -0,0 +1,398 @@
+//! A small language of boolean queries for `search`.
+//!
+//! A query is made of `name:TEXT`, `tag:TAG`, and `path:TEXT` terms
+//! combined with `AND`, `OR`, `NOT`, and parentheses, as in
+//! `tag:work AND (name:report OR NOT tag:done)`. Terms next to each
+//! other without an operator must both match. A term without a field
+//! matches names, and values with spaces can be put in double quotes.
+//!
+//! Text with no field or operator word in it is not parsed at all, so
+//! names with parentheses or quotes can be searched for as they are.
+
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::highlight;
+use crate::scrivener::notes::Note;
+
+/// A condition that a `Note` can match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Query {
+    /// Plain text without fields or operators, which matches names and
+    /// tags that contain it, ignoring case
+    Text(String),
+
+    /// Matches names that contain the text, ignoring case
+    Name(String),
+
+    /// Matches notes with the tag, ignoring case
+    Tag(String),
+
+    /// Matches paths that contain the text, ignoring case
+    Path(String),
+
+    /// Matches notes that do not match the query
+    Not(Box<Query>),
+
+    /// Matches notes that match both queries
+    And(Box<Query>, Box<Query>),
+
+    /// Matches notes that match either query
+    Or(Box<Query>, Box<Query>),
+}
+
+/// A piece of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+
+    /// A term, which becomes a `Query` on its own
+    Term(Query),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::And => write!(f, "`AND`"),
+            Token::Or => write!(f, "`OR`"),
+            Token::Not => write!(f, "`NOT`"),
+            Token::Term(_) => write!(f, "a term"),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    /// Parses a query. Text without any fields or operators is kept
+    /// whole as `Query::Text`. See `is_expression`.
+    ///
+    /// # Errors
+    ///
+    /// - The query is empty.
+    /// - A quote or parenthesis is not closed, or a parenthesis is not
+    ///   opened.
+    /// - An operator is missing something to combine.
+    /// - A field is given without a value.
+    fn from_str(text: &str) -> Result<Query, Error> {
+        if !is_expression(text) {
+            let text = text.trim();
+            failure::ensure!(!text.is_empty(), "The query is empty.");
+            return Ok(Query::Text(text.to_string()));
+        }
+
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let query = parser.or()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(query),
+            Some(Token::Close) => failure::bail!("The query has a `)` without a `(`."),
+            Some(token) => failure::bail!("The query has an unexpected {}.", token),
+        }
+    }
+}
+
+impl Query {
+    /// Returns `true` if `note` matches the query.
+    pub(super) fn matches(&self, note: &Note) -> bool {
+        match self {
+            Query::Text(text) => {
+                highlight::match_span(note.name(), text).is_some()
+                    || note
+                        .tags()
+                        .iter()
+                        .flatten()
+                        .any(|tag| highlight::match_span(tag, text).is_some())
+            }
+            Query::Name(text) => highlight::match_span(note.name(), text).is_some(),
+            Query::Tag(tag) => note.has_tag(tag, false),
+            Query::Path(text) => note
+                .path()
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&text.to_lowercase()),
+            Query::Not(query) => !query.matches(note),
+            Query::And(a, b) => a.matches(note) && b.matches(note),
+            Query::Or(a, b) => a.matches(note) || b.matches(note),
+        }
+    }
+
+    /// Returns the terms that a match is looked for in, leaving out
+    /// those under a `NOT`, so that they can be highlighted.
+    pub(super) fn wanted(&self) -> Vec<&Query> {
+        match self {
+            Query::Not(_) => Vec::new(),
+            Query::And(a, b) | Query::Or(a, b) => {
+                let mut terms = a.wanted();
+                terms.extend(b.wanted());
+                terms
+            }
+            term => vec![term],
+        }
+    }
+}
+
+/// The fields that a term can start with, followed by a colon
+const FIELDS: [&str; 3] = ["name", "tag", "path"];
+
+/// Returns `true` if `text` has a word, between spaces or parentheses,
+/// that is an operator or starts with a field, so that it should be
+/// parsed instead of searched for as it is.
+fn is_expression(text: &str) -> bool {
+    text.split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .any(|word| {
+            ["AND", "OR", "NOT"].contains(&word)
+                || word
+                    .find(':')
+                    .is_some_and(|colon| FIELDS.contains(&&word[..colon]))
+        })
+}
+
+/// Splits `text` into tokens.
+///
+/// # Errors
+///
+/// - A double quote is not closed.
+/// - A field is given without a value.
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if quoted => word.push(c),
+            '(' | ')' => {
+                words.extend(Some(std::mem::take(&mut word)).filter(|word| !word.is_empty()));
+                words.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                words.extend(Some(std::mem::take(&mut word)).filter(|word| !word.is_empty()));
+            }
+            c => word.push(c),
+        }
+    }
+    failure::ensure!(!quoted, "The query has a `\"` that is not closed.");
+    words.extend(Some(word).filter(|word| !word.is_empty()));
+
+    words.iter().map(|word| token(word)).collect()
+}
+
+/// Returns the token that `word` stands for.
+///
+/// # Errors
+///
+/// - `word` is a field without a value.
+fn token(word: &str) -> Result<Token, Error> {
+    let term = |field: &str, value: &str| -> Result<Query, Error> {
+        let value = value.replace('"', "");
+        failure::ensure!(!value.is_empty(), "`{}:` needs a value.", field);
+
+        Ok(match field {
+            "name" => Query::Name(value),
+            "tag" => Query::Tag(value),
+            "path" => Query::Path(value),
+            _ => Query::Text(value),
+        })
+    };
+
+    Ok(match word {
+        "(" => Token::Open,
+        ")" => Token::Close,
+        "AND" => Token::And,
+        "OR" => Token::Or,
+        "NOT" => Token::Not,
+        _ => match word.find(':') {
+            Some(colon) if FIELDS.contains(&&word[..colon]) => {
+                Token::Term(term(&word[..colon], &word[colon + 1..])?)
+            }
+            _ => Token::Term(term("", word)?),
+        },
+    })
+}
+
+/// Parses tokens into a `Query`, from loosest to tightest binding:
+/// `OR`, then `AND`, then `NOT`.
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    /// Parses queries joined by `OR`.
+    fn or(&mut self) -> Result<Query, Error> {
+        let mut query = self.and()?;
+        while self.eat(&Token::Or) {
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    /// Parses queries joined by `AND` or written next to each other.
+    fn and(&mut self) -> Result<Query, Error> {
+        let mut query = self.not()?;
+        loop {
+            let explicit = self.eat(&Token::And);
+            match self.tokens.get(self.next) {
+                Some(Token::Or) | Some(Token::Close) | None if !explicit => return Ok(query),
+                _ => query = Query::And(Box::new(query), Box::new(self.not()?)),
+            }
+        }
+    }
+
+    /// Parses a query with any number of `NOT`s before it.
+    fn not(&mut self) -> Result<Query, Error> {
+        if self.eat(&Token::Not) {
+            return Ok(Query::Not(Box::new(self.not()?)));
+        }
+
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+
+        match token {
+            Some(Token::Term(Query::Text(text))) => Ok(Query::Name(text)),
+            Some(Token::Term(term)) => Ok(term),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                failure::ensure!(
+                    self.eat(&Token::Close),
+                    "The query has a `(` that is not closed."
+                );
+                Ok(query)
+            }
+            Some(token) => failure::bail!("The query has {} where a term should be.", token),
+            None => failure::bail!("The query ends before a term."),
+        }
+    }
+
+    /// Moves past the next token if it is `token`, and returns whether
+    /// it was.
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.next) == Some(token) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::scrivener::notes::Index;
+
+    fn parse(text: &str) -> Query {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parsing() {
+        let name = |text: &str| Box::new(Query::Name(text.to_string()));
+        let tag = |text: &str| Box::new(Query::Tag(text.to_string()));
+
+        assert_eq!(
+            parse(" meeting notes "),
+            Query::Text("meeting notes".into())
+        );
+        for literal in &[
+            "Meeting (draft)",
+            "a(",
+            "Bob\"s",
+            "()",
+            "notes: today",
+            "Or else",
+        ] {
+            assert_eq!(parse(literal), Query::Text(literal.to_string()));
+        }
+        assert_eq!(
+            parse("tag:work AND name:report"),
+            Query::And(tag("work"), name("report"))
+        );
+        assert_eq!(
+            parse("tag:a OR tag:b AND NOT tag:c"),
+            Query::Or(
+                tag("a"),
+                Box::new(Query::And(tag("b"), Box::new(Query::Not(tag("c")))))
+            )
+        );
+        assert_eq!(
+            parse("(tag:a OR tag:b) report"),
+            Query::And(Box::new(Query::Or(tag("a"), tag("b"))), name("report"))
+        );
+        assert_eq!(
+            parse("tag:\"two words\" OR path:notes/"),
+            Query::Or(tag("two words"), Box::new(Query::Path("notes/".into())))
+        );
+    }
+
+    #[test]
+    fn malformed_queries() {
+        for query in &[
+            "",
+            "tag:",
+            "tag:a AND",
+            "OR tag:a",
+            "(tag:a",
+            "tag:a)",
+            "NOT",
+            "tag:\"open",
+            "NOT (a",
+        ] {
+            assert!(
+                query.parse::<Query>().is_err(),
+                "{} should not parse",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn evaluation() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags = |tags: &[&str]| Some(tags.iter().map(|tag| tag.to_string()).collect());
+
+        let mut index = Index::new();
+        index
+            .add("Q1 report", &path, &tags(&["work", "urgent"]))
+            .unwrap();
+        index.add("Groceries", &path, &tags(&["today"])).unwrap();
+        index.add("Old report", &path, &tags(&["work"])).unwrap();
+
+        let found = |query: &str| -> Vec<&str> {
+            let query = parse(query);
+            index
+                .notes()
+                .iter()
+                .filter(|note| query.matches(note))
+                .map(|note| note.name())
+                .collect()
+        };
+
+        assert_eq!(
+            found("tag:work AND name:report"),
+            ["Old report", "Q1 report"]
+        );
+        assert_eq!(found("tag:urgent OR tag:today"), ["Groceries", "Q1 report"]);
+        assert_eq!(found("tag:WORK NOT tag:urgent"), ["Old report"]);
+        assert_eq!(found("NOT (tag:work OR tag:today)"), Vec::<&str>::new());
+        assert_eq!(found("report"), ["Old report", "Q1 report"]);
+        assert_eq!(found("urgent"), ["Q1 report"]);
+        assert_eq!(found("name:urgent"), Vec::<&str>::new());
+        assert_eq!(found("path:tmp AND tag:today"), ["Groceries"]);
+    }
+}
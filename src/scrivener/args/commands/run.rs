@@ -0,0 +1,82 @@
+//! Running notes as programs.
+
+use failure::{Error, ResultExt};
+use std::path::Path;
+use std::process::Command;
+
+use super::errors;
+
+/// Returns `true` if the file at `path` can be run.
+///
+/// On Unix, this means any of its executable bits are set. Elsewhere,
+/// any file that exists can be run through the shell.
+#[cfg(unix)]
+pub(super) fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub(super) fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs the file at `path` with `args`, waits for it to exit, and
+/// returns its exit code, or 1 if it was stopped by a signal.
+///
+/// On Windows, the file is started through `cmd`, so that scripts are
+/// run by their associated program.
+///
+/// # Errors
+///
+/// - The file cannot be started.
+pub(super) fn run(path: &Path, args: &[String]) -> Result<i32, Error> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(path);
+        command
+    } else {
+        Command::new(path)
+    };
+
+    let status = command
+        .args(args)
+        .status()
+        .with_context(|_| errors::could_not(&format!("run {}", path.display())))?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+// The test sets executable bits, which only exist on Unix.
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn runs_with_arguments() {
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("script.sh");
+        let out = dir.path().join("out.txt");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\necho \"$@\" > {}\nexit 3\n", out.display()),
+        )
+        .unwrap();
+        assert!(!is_executable(&script));
+
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(is_executable(&script));
+        assert!(!is_executable(dir.path()));
+
+        let args = ["one".to_string(), "--two".to_string()];
+        assert_eq!(run(&script, &args).unwrap(), 3);
+        assert_eq!(fs::read_to_string(&out).unwrap(), "one --two\n");
+    }
+}
@@ -0,0 +1,80 @@
+//! Showing timestamps as dates or as how long ago they were.
+
+use chrono::{DateTime, Local};
+use std::time::{Duration, SystemTime};
+
+/// How long ago a time can be and still be shown relative to now.
+const RELATIVE_LIMIT: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Returns `time` as a local date and time, or as how long before `now`
+/// it was if `relative` is true, such as `3 hours ago`.
+///
+/// Relative times under a minute ago, or in the future, are `just now`,
+/// and those over 30 days ago are shown as a date.
+pub(super) fn format_timestamp(time: SystemTime, now: SystemTime, relative: bool) -> String {
+    let local = DateTime::<Local>::from(time);
+    if !relative {
+        return local.format("%Y-%m-%d %H:%M").to_string();
+    }
+
+    let ago = now.duration_since(time).unwrap_or_default();
+    if ago >= RELATIVE_LIMIT {
+        return local.format("%Y-%m-%d").to_string();
+    }
+
+    let plural = |count: u64, unit: &str| {
+        format!(
+            "{} {}{} ago",
+            count,
+            unit,
+            if count == 1 { "" } else { "s" }
+        )
+    };
+
+    match ago.as_secs() {
+        secs if secs < 60 => "just now".to_string(),
+        secs if secs < 60 * 60 => plural(secs / 60, "minute"),
+        secs if secs < 24 * 60 * 60 => plural(secs / (60 * 60), "hour"),
+        secs => plural(secs / (24 * 60 * 60), "day"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    #[test]
+    fn relative_buckets() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let ago = |secs| format_timestamp(now - Duration::from_secs(secs), now, true);
+
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(59), "just now");
+        assert_eq!(
+            format_timestamp(now + Duration::from_secs(5), now, true),
+            "just now"
+        );
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(45 * 60), "45 minutes ago");
+        assert_eq!(ago(60 * 60), "1 hour ago");
+        assert_eq!(ago(23 * 60 * 60 + 59 * 60), "23 hours ago");
+        assert_eq!(ago(24 * 60 * 60), "1 day ago");
+        assert_eq!(ago(29 * 24 * 60 * 60), "29 days ago");
+
+        let old = now - RELATIVE_LIMIT;
+        let date = DateTime::<Local>::from(old).format("%Y-%m-%d").to_string();
+        assert_eq!(format_timestamp(old, now, true), date);
+    }
+
+    #[test]
+    fn absolute_times() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let expected = DateTime::<Local>::from(now)
+            .format("%Y-%m-%d %H:%M")
+            .to_string();
+
+        assert_eq!(format_timestamp(now, now, false), expected);
+    }
+}
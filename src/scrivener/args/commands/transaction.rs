@@ -0,0 +1,296 @@
+//! Undoing the changes of a command that fails partway through.
+
+use failure::{Error, ResultExt};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::scrivener::notes::Index;
+
+/// A change to the filesystem that can be undone.
+#[derive(Debug)]
+enum Action {
+    /// An empty directory was created
+    CreatedDir(PathBuf),
+
+    /// A file was moved from the first path to the second
+    Moved(PathBuf, PathBuf),
+
+    /// A file with these contents was removed
+    Removed(PathBuf, Vec<u8>),
+}
+
+/// The state of the `Index` before a command, along with the changes
+/// the command made to files, so that they can all be undone.
+#[derive(Debug)]
+pub(super) struct Transaction {
+    snapshot: Index,
+    actions: Vec<Action>,
+}
+
+impl Transaction {
+    /// Starts recording changes, remembering `index` as it is now.
+    pub(super) fn begin(index: &Index) -> Transaction {
+        Transaction {
+            snapshot: index.clone(),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Creates `dir` and any missing parents, recording each one that
+    /// was created.
+    ///
+    /// # Errors
+    ///
+    /// - A directory cannot be created.
+    pub(super) fn create_dir_all(&mut self, dir: &Path) -> Result<(), Error> {
+        let missing: Vec<&Path> = dir
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+            .collect();
+
+        for dir in missing.into_iter().rev() {
+            fs::create_dir(dir).with_context(|_| format!("Could not create {}.", dir.display()))?;
+            self.actions.push(Action::CreatedDir(dir.to_path_buf()));
+        }
+
+        Ok(())
+    }
+
+    /// Moves the file at `from` to `to` and records the move. See
+    /// `move_file`.
+    ///
+    /// # Errors
+    ///
+    /// - The file cannot be moved.
+    pub(super) fn rename(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        move_file(from, to)?;
+        self.actions
+            .push(Action::Moved(from.to_path_buf(), to.to_path_buf()));
+        Ok(())
+    }
+
+    /// Removes the file at `path`, keeping its contents so that it can
+    /// be written back.
+    ///
+    /// # Errors
+    ///
+    /// - The file cannot be read or removed.
+    pub(super) fn remove_file(&mut self, path: &Path) -> Result<(), Error> {
+        let contents = fs::read(path)?;
+        fs::remove_file(path)?;
+        self.actions
+            .push(Action::Removed(path.to_path_buf(), contents));
+        Ok(())
+    }
+
+    /// Checks that `index` is still consistent with how it was when the
+    /// transaction began. See `Index::consistency_problems`.
+    ///
+    /// # Errors
+    ///
+    /// - `index` became inconsistent. Every problem is listed.
+    pub(super) fn verify(&self, index: &Index) -> Result<(), Error> {
+        let problems = index.consistency_problems(&self.snapshot);
+
+        failure::ensure!(
+            problems.is_empty(),
+            "The change was undone because it would leave the index inconsistent: {}.",
+            problems.join("; ")
+        );
+
+        Ok(())
+    }
+
+    /// Undoes the recorded changes, newest first, and puts `index` back
+    /// the way it was when the transaction began.
+    ///
+    /// A change that cannot be undone is reported with a warning, and
+    /// the rest are still undone.
+    pub(super) fn rollback(self, index: &mut Index) {
+        for action in self.actions.into_iter().rev() {
+            let (undone, path) = match &action {
+                Action::CreatedDir(dir) => (fs::remove_dir(dir), dir),
+                Action::Moved(from, to) => (move_file(to, from), from),
+                Action::Removed(path, contents) => (fs::write(path, contents), path),
+            };
+
+            if let Err(error) = undone {
+                eprintln!("Warning: could not restore {}: {}", path.display(), error);
+            }
+        }
+
+        *index = self.snapshot;
+    }
+}
+
+/// Moves the file at `from` to `to`, copying it and removing the
+/// original if it cannot be renamed, such as across filesystems.
+///
+/// # Errors
+///
+/// - The file can be neither renamed nor copied. The error from
+///   renaming is returned.
+/// - The original cannot be removed after copying. The copy is removed.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    let error = match fs::rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(error) => error,
+    };
+
+    if fs::copy(from, to).is_err() {
+        return Err(error);
+    }
+
+    fs::remove_file(from).inspect_err(|_| {
+        let _ = fs::remove_file(to);
+    })
+}
+
+/// Runs `body` with a new `Transaction`, and rolls back everything it
+/// recorded and every change to `index` if it fails.
+///
+/// # Errors
+///
+/// - `body` fails. Its error is returned after rolling back.
+pub(super) fn run<T, F>(index: &mut Index, body: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut Index, &mut Transaction) -> Result<T, Error>,
+{
+    let mut transaction = Transaction::begin(index);
+
+    let result = body(index, &mut transaction);
+    if result.is_err() {
+        transaction.rollback(index);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn failures_roll_back() {
+        let dir = TempDir::new().unwrap();
+        let moved = dir.path().join("moved.txt");
+        let removed = dir.path().join("removed.txt");
+        fs::write(&moved, "moved").unwrap();
+        fs::write(&removed, "removed").unwrap();
+
+        let mut index = Index::new();
+        index.add("moved", &moved, &None).unwrap();
+        let before = index.clone();
+
+        let target = dir.path().join("a/b/moved.txt");
+        let result: Result<(), Error> = run(&mut index, |index, transaction| {
+            transaction.create_dir_all(target.parent().unwrap())?;
+            transaction.rename(&moved, &target)?;
+            index.set_path("moved", &target)?;
+            transaction.remove_file(&removed)?;
+            failure::bail!("Failed partway.")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(index, before);
+        assert_eq!(
+            index.get("moved").unwrap().path(),
+            before.get("moved").unwrap().path()
+        );
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "moved");
+        assert_eq!(fs::read_to_string(&removed).unwrap(), "removed");
+        assert!(!dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn inconsistent_changes_roll_back() {
+        let dir = TempDir::new().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        let moved = dir.path().join("moved.txt");
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let mut index = Index::new();
+        index.add("a", &a, &None).unwrap();
+        index.add("b", &b, &None).unwrap();
+
+        run(&mut index, |index, transaction| {
+            transaction.rename(&a, &moved)?;
+            index.set_path("a", &moved)?;
+            transaction.verify(index)
+        })
+        .unwrap();
+        assert!(moved.exists());
+
+        // Pointing `a` at `b`'s file is a bug that the check catches.
+        let moved_back = dir.path().join("back.txt");
+        let error = run(&mut index, |index, transaction| {
+            transaction.rename(&moved, &moved_back)?;
+            index.set_path("a", &b)?;
+            transaction.verify(index)
+        })
+        .unwrap_err();
+
+        assert!(error.to_string().contains("is shared by notes a, b"));
+        assert!(moved.exists());
+        assert!(!moved_back.exists());
+        assert_eq!(
+            index.get("a").unwrap().path(),
+            &moved.canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn shared_files_move_together() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("shared.txt");
+        let to = dir.path().join("moved.txt");
+        fs::write(&from, "").unwrap();
+
+        let mut index = Index::new();
+        index.add("a", &from, &None).unwrap();
+        index.add("b", &from, &None).unwrap();
+
+        // Moving the file for only one of its notes strands the other.
+        let error = run(&mut index, |index, transaction| {
+            transaction.rename(&from, &to)?;
+            index.set_path("a", &to)?;
+            transaction.verify(index)
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("note `b` was left at"));
+        assert!(from.exists());
+
+        let shared = from.canonicalize().unwrap();
+        run(&mut index, |index, transaction| {
+            transaction.rename(&from, &to)?;
+            index.move_path(&shared, &to)?;
+            transaction.verify(index)
+        })
+        .unwrap();
+        let to = to.canonicalize().unwrap();
+        assert_eq!(index.get("b").unwrap().path(), &to);
+    }
+
+    #[test]
+    fn successes_are_kept() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("note.txt");
+        let to = dir.path().join("sub/note.txt");
+        fs::write(&from, "").unwrap();
+
+        let mut index = Index::new();
+        let result = run(&mut index, |_, transaction| {
+            transaction.create_dir_all(to.parent().unwrap())?;
+            transaction.rename(&from, &to)
+        });
+
+        assert!(result.is_ok());
+        assert!(to.exists());
+        assert!(!from.exists());
+    }
+}
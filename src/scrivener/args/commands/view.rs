@@ -0,0 +1,164 @@
+//! Serializable views of notes with fields computed from their files.
+
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::relative_to;
+use crate::scrivener::config::{CountMode, PathStyle};
+use crate::scrivener::notes::Note;
+
+/// A `Note`'s name, path, and tags, for `list --json`.
+///
+/// Every field is always present so that scripts can rely on them.
+#[derive(Serialize, Debug, PartialEq)]
+pub(super) struct NoteSummary<'a> {
+    name: &'a str,
+
+    /// The path as stored, written as a string even if it is not valid
+    /// Unicode
+    path: String,
+
+    /// The tags, which are empty if the note has none
+    tags: &'a [String],
+}
+
+impl<'a> NoteSummary<'a> {
+    /// Creates a summary of `note`.
+    pub(super) fn new(note: &'a Note) -> NoteSummary<'a> {
+        NoteSummary {
+            name: note.name(),
+            path: note.path().to_string_lossy().into_owned(),
+            tags: note.tags().as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// A `Note`'s stored fields along with facts about its file, for
+/// `list --json-full`.
+#[derive(Serialize, Debug, PartialEq)]
+pub(super) struct NoteView<'a> {
+    name: &'a str,
+    path: &'a Path,
+    tags: &'a [String],
+
+    /// When the note was last modified, in seconds since the Unix epoch
+    modified: Option<u64>,
+
+    /// When the note was added to the index, in seconds since the Unix
+    /// epoch
+    created: Option<u64>,
+
+    /// The position of the note in the order notes were added
+    order: Option<u64>,
+
+    /// The path relative to the current directory
+    relative_path: String,
+
+    /// Whether the note's file exists
+    exists: bool,
+
+    /// The size of the note's file in bytes, if it can be read
+    size: Option<u64>,
+
+    /// The number of words in the note's file, if it can be read
+    words: Option<usize>,
+}
+
+impl<'a> NoteView<'a> {
+    /// Creates a view of `note`, with its path written relative to
+    /// `base` in `style`, and its words or graphemes counted depending
+    /// on `mode`.
+    pub(super) fn new(
+        note: &'a Note,
+        base: &Path,
+        style: PathStyle,
+        mode: CountMode,
+    ) -> NoteView<'a> {
+        NoteView {
+            name: note.name(),
+            path: note.path(),
+            tags: note.tags().as_deref().unwrap_or_default(),
+            modified: unix_secs(note.modified()),
+            created: unix_secs(note.created()),
+            order: note.order(),
+            relative_path: relative_to(note.path(), base, style),
+            exists: note.path().is_file(),
+            size: note.size(),
+            words: note.word_count(mode).ok(),
+        }
+    }
+}
+
+/// Returns `time` in seconds since the Unix epoch.
+fn unix_secs(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::scrivener::notes::Index;
+
+    #[test]
+    fn summary_fields() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        fs::write(&path, "").unwrap();
+
+        let mut index = Index::new();
+        index.add("Note", &path, &None).unwrap();
+        let note = index.get("Note").unwrap();
+
+        let summary = serde_json::to_value(NoteSummary::new(note)).unwrap();
+        assert_eq!(summary["name"], "Note");
+        assert_eq!(summary["path"], note.path().to_str().unwrap());
+        assert_eq!(summary["tags"], serde_json::json!([]));
+        assert_eq!(summary.as_object().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn computed_fields() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        fs::write(&path, "three little words").unwrap();
+
+        let mut index = Index::new();
+        index
+            .add("Note", &path, &Some(vec!["tag".to_string()]))
+            .unwrap();
+        let base = dir.path().canonicalize().unwrap();
+
+        let view = serde_json::to_value(NoteView::new(
+            index.get("Note").unwrap(),
+            &base,
+            PathStyle::Dotted,
+            CountMode::Words,
+        ))
+        .unwrap();
+        assert_eq!(view["name"], "Note");
+        assert_eq!(view["tags"], serde_json::json!(["tag"]));
+        assert_eq!(view["relative_path"], "./note.txt");
+        assert_eq!(view["exists"], true);
+        assert_eq!(view["size"], 18);
+        assert_eq!(view["words"], 3);
+        assert_eq!(view["order"], 1);
+        assert_eq!(view["created"], view["modified"]);
+
+        fs::remove_file(&path).unwrap();
+        let view = NoteView::new(
+            index.get("Note").unwrap(),
+            &base,
+            PathStyle::Bare,
+            CountMode::Words,
+        );
+        assert!(!view.exists);
+        assert_eq!(view.size, None);
+        assert_eq!(view.words, None);
+        assert_eq!(view.relative_path, "note.txt");
+    }
+}
@@ -1,30 +1,298 @@
 //! Argument parsing logic
 
 use failure::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 mod commands;
+use crate::scrivener::config::{self, Config, IndexFormat};
 use crate::scrivener::notes::Index;
 use commands::Command;
 
 /// A struct that contains the arguments passed by the user.
 #[derive(Debug, StructOpt)]
 pub struct Args {
+    /// Answer yes to every confirmation prompt
+    ///
+    /// May be given before or after the subcommand
+    #[structopt(short = "y", long = "yes", global = true)]
+    assume_yes: bool,
+
+    /// Show times as how long ago they were, such as `3 hours ago`
+    ///
+    /// Defaults to the relative_time setting
+    #[structopt(long, global = true, conflicts_with = "absolute-time")]
+    relative_time: bool,
+
+    /// Show times as dates
+    #[structopt(long, global = true)]
+    absolute_time: bool,
+
+    /// Leave out the messages printed when a note is changed
+    /// successfully
+    ///
+    /// Errors are still printed. Defaults to the quiet setting
+    #[structopt(short, long, global = true)]
+    quiet: bool,
+
     #[structopt(subcommand)]
     cmd: Command,
 }
 
 impl Args {
-    /// Executes logic based on the command that the user entered.
-    pub fn execute(&self, program_name: &str) -> Result<(), Error> {
-        let mut index = Index::load(program_name)?;
+    /// Executes logic based on the command that the user entered, and
+    /// returns the code that the program should exit with. See
+    /// `Command::execute`.
+    pub fn execute(&self, program_name: &str) -> Result<i32, Error> {
+        if let Command::Completions { shell, install } = &self.cmd {
+            return commands::completions(*shell, *install).map(|()| 0);
+        }
+
+        let config_path = Config::path(program_name)?;
+
+        // Settings can be changed without touching the index.
+        if let Some((key, value)) = self.cmd.config_setting() {
+            return commands::configure(&config_path, key, value, self.quiet).map(|()| 0);
+        }
+
+        let mut config = Config::load_path(&config_path)?;
+        let index_path = Index::path(program_name, config.index_format)?;
+
+        // Converting moves the index, so it is also handled here.
+        if let Command::ConvertIndex { format } = &self.cmd {
+            return commands::convert_index(
+                &config,
+                &config_path,
+                &index_path,
+                &Index::path(program_name, *format)?,
+                *format,
+            )
+            .map(|()| 0);
+        }
+
+        if let Some(format) = format_override(std::env::var_os(INDEX_FORMAT_VAR))? {
+            config.index_format = format;
+        }
+        let index_path = Index::path(program_name, config.index_format)?;
+
+        if let Some(other) = index_in_other_format(&index_path) {
+            failure::bail!(
+                "The index is stored at {}, not {}. Run `convert-index {}` to convert it.",
+                other.display(),
+                index_path.display(),
+                config.index_format.extension()
+            );
+        }
+
+        self.execute_with(&config, &index_path)
+    }
+
+    /// Executes the command using the `Index` stored at `index_path`.
+    ///
+    /// If `index_path` cannot be written to, commands that would
+    /// modify the `Index` fail before doing anything, so that they
+    /// cannot leave behind files that the `Index` does not know about.
+    /// Other commands still run, but the `Index` is not stored.
+    ///
+    /// The `Index` is only stored if the command changed it.
+    fn execute_with(&self, config: &Config, index_path: &Path) -> Result<i32, Error> {
+        let config = &self.overridden(config);
+        let writable = config::is_writable(index_path);
+
+        failure::ensure!(
+            writable || !self.cmd.modifies_index(),
+            "Cannot save changes because {} is read-only.",
+            index_path.display()
+        );
 
-        self.cmd.execute(&mut index)?;
+        // Names are printed as they are read, so the whole index is
+        // never held in memory.
+        if let Command::Names { filter_tags } = &self.cmd {
+            let stdout = std::io::stdout();
+            return commands::stream_names(index_path, filter_tags, &mut stdout.lock()).map(|()| 0);
+        }
 
-        index.store(program_name)?;
+        let mut index = Index::load_path(index_path)?;
 
-        Ok(())
+        let code = self.cmd.execute(&mut index, config, self.assume_yes)?;
+
+        if writable && index.is_dirty() {
+            index.store_path(index_path, config)?;
+        }
+
+        Ok(code)
+    }
+
+    /// Returns `config` with the settings given as options changed.
+    fn overridden(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+
+        if self.relative_time {
+            config.relative_time = true;
+        } else if self.absolute_time {
+            config.relative_time = false;
+        }
+
+        if self.quiet {
+            config.quiet = true;
+        }
+
+        config
     }
 }
 
-//TODO: Add tests
+/// The environment variable that sets the index format, ahead of the
+/// `index_format` setting.
+const INDEX_FORMAT_VAR: &str = "SCRIVENER_INDEX_FORMAT";
+
+/// Returns the index format named by `from_env`, the value of
+/// $SCRIVENER_INDEX_FORMAT, if it is set and not empty.
+///
+/// # Errors
+///
+/// - `from_env` is not valid Unicode or not an index format.
+fn format_override(from_env: Option<OsString>) -> Result<Option<IndexFormat>, Error> {
+    match from_env.filter(|format| !format.is_empty()) {
+        Some(format) => match format.to_str() {
+            Some(format) => Ok(Some(format.parse()?)),
+            None => failure::bail!("${} is not valid Unicode.", INDEX_FORMAT_VAR),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the path of an index stored in another format next to
+/// `index_path`, if `index_path` itself does not exist.
+///
+/// Loading `index_path` anyway would start an empty index and orphan
+/// the notes in the other file.
+fn index_in_other_format(index_path: &Path) -> Option<PathBuf> {
+    if index_path.exists() {
+        return None;
+    }
+
+    [IndexFormat::Toml, IndexFormat::Json]
+        .iter()
+        .map(|format| index_path.with_extension(format.extension()))
+        .find(|path| path != index_path && path.exists())
+}
+
+//TODO: Improve tests
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn time_options_override_config() {
+        let relative = Config {
+            relative_time: true,
+            ..Config::default()
+        };
+        let args = |args: &[&str]| Args::from_iter([&["scrv"], args, &["stats"]].concat());
+
+        assert!(
+            args(&["--relative-time"])
+                .overridden(&Config::default())
+                .relative_time
+        );
+        assert!(
+            !args(&["--absolute-time"])
+                .overridden(&relative)
+                .relative_time
+        );
+        assert!(args(&[]).overridden(&relative).relative_time);
+        assert!(
+            Args::from_iter_safe(&["scrv", "stats", "--relative-time", "--absolute-time"]).is_err()
+        );
+    }
+
+    #[test]
+    fn quiet_overrides_config() {
+        let quiet = |args: &[&str]| {
+            Args::from_iter(&[&["scrv"], args].concat())
+                .overridden(&Config::default())
+                .quiet
+        };
+
+        assert!(quiet(&["-q", "stats"]));
+        assert!(quiet(&["stats", "--quiet"]));
+        assert!(!quiet(&["stats"]));
+    }
+
+    #[test]
+    fn list_does_not_store() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let index_path = dir.path().join("scrivener.toml");
+        let note_path = dir.path().join("note.txt");
+        std::fs::write(&note_path, "").unwrap();
+
+        let add = Args::from_iter(&["scrv", "add", "note", note_path.to_str().unwrap()]);
+        add.execute_with(&Config::default(), &index_path).unwrap();
+
+        let old = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&index_path, old).unwrap();
+
+        let list = Args::from_iter(&["scrv", "list"]);
+        list.execute_with(&Config::default(), &index_path).unwrap();
+
+        let metadata = std::fs::metadata(&index_path).unwrap();
+        assert_eq!(
+            filetime::FileTime::from_last_modification_time(&metadata),
+            old
+        );
+    }
+
+    #[test]
+    fn read_only_index_blocks_changes() {
+        let config_file = NamedTempFile::new().unwrap();
+        let note_file = NamedTempFile::new().unwrap();
+        let output = note_file.path().with_extension("md");
+
+        // A path inside a regular file can never be written to.
+        let index_path = config_file.path().join("scrivener.toml");
+        let note_path = note_file.path().to_str().unwrap();
+
+        let add = Args::from_iter(&["scrv", "add", "note", note_path]);
+        let error = add
+            .execute_with(&Config::default(), &index_path)
+            .unwrap_err();
+        assert!(error.to_string().contains("read-only"));
+
+        let export = Args::from_iter(&["scrv", "export-index", output.to_str().unwrap()]);
+        export
+            .execute_with(&Config::default(), &index_path)
+            .unwrap();
+        assert!(output.exists());
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn index_format_overrides() {
+        assert_eq!(format_override(None).unwrap(), None);
+        assert_eq!(format_override(Some("".into())).unwrap(), None);
+        assert_eq!(
+            format_override(Some("JSON".into())).unwrap(),
+            Some(IndexFormat::Json)
+        );
+        assert!(format_override(Some("yaml".into())).is_err());
+    }
+
+    #[test]
+    fn index_in_other_format_is_found() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let toml_path = dir.path().join("scrivener.toml");
+        let json_path = dir.path().join("scrivener.json");
+
+        assert_eq!(index_in_other_format(&json_path), None);
+
+        std::fs::write(&toml_path, "").unwrap();
+        assert_eq!(index_in_other_format(&json_path), Some(toml_path.clone()));
+        assert_eq!(index_in_other_format(&toml_path), None);
+
+        std::fs::write(&json_path, "").unwrap();
+        assert_eq!(index_in_other_format(&json_path), None);
+    }
+}
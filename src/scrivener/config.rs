@@ -0,0 +1,714 @@
+//! User settings
+
+use directories::ProjectDirs;
+use failure::{Error, ResultExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The default `name_regex` setting
+pub const DEFAULT_NAME_REGEX: &str = r"^[^/\\-][^/\\]{0,127}$";
+
+/// The default `success_message` setting
+pub const DEFAULT_SUCCESS_MESSAGE: &str = "Note `{name}` at {path} {action} successfully.";
+
+/// The default `ignore` setting: backups and temporary files
+pub const DEFAULT_IGNORE: [&str; 5] = ["*~", "*.bak", "*.orig", "*.swp", "*.tmp"];
+
+/// Settings that change how scrivener behaves.
+///
+/// Stored in scrivener-config.toml, next to scrivener.toml. Any setting
+/// missing from the file takes its default value.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Write each tag on its own line when storing the index
+    pub multiline_tags: bool,
+
+    /// Remove every extension, not just the last one, when deriving a
+    /// note's name from its file name
+    pub strip_all_extensions: bool,
+
+    /// The file format that the index is stored in
+    pub index_format: IndexFormat,
+
+    /// How the names of new notes are rewritten
+    pub name_case: NameCase,
+
+    /// A regular expression that the names of new notes must match
+    ///
+    /// The default allows up to 128 characters without slashes or
+    /// backslashes that do not start with a dash.
+    pub name_regex: String,
+
+    /// The directory that notes are kept in, or empty if there is none
+    pub notes_root: PathBuf,
+
+    /// Whether `new` and `add` refuse files outside `notes_root`
+    pub strict_root: bool,
+
+    /// The directory that `new` creates files in when no path is given,
+    /// or empty to use the current directory
+    ///
+    /// A relative directory is under `notes_root`.
+    pub default_dir: PathBuf,
+
+    /// How paths relative to the current directory are written
+    pub path_style: PathStyle,
+
+    /// Show times as how long ago they were, such as `3 hours ago`,
+    /// instead of as dates
+    pub relative_time: bool,
+
+    /// The command that notes are edited with, or empty to use $VISUAL,
+    /// then $EDITOR, then vi
+    pub editor: String,
+
+    /// Patterns of files that `scan` leaves out, written as in
+    /// `.gitignore`
+    ///
+    /// Hidden files and files matched by `.gitignore` or
+    /// `.scrivenerignore` are always left out.
+    pub ignore: Vec<String>,
+
+    /// The line endings that scrivener writes note files with
+    ///
+    /// Defaults to `preserve` rather than `lf`, so that editing a note
+    /// never rewrites every line of a file that already uses `\r\n`.
+    /// New notes are written with whatever their template or editor
+    /// used, which is `\n` unless something else was chosen.
+    pub line_endings: LineEndings,
+
+    /// Whether scrivener starts the note files it writes with a UTF-8
+    /// byte order mark
+    ///
+    /// Defaults to `preserve` rather than `never`, so that a byte order
+    /// mark another tool relies on is not dropped by editing. New notes
+    /// only get one if their template or editor wrote one.
+    pub bom: Bom,
+
+    /// What `stats`, `info`, and `list --json-full` count in note
+    /// files: words or graphemes
+    pub count_mode: CountMode,
+
+    /// The directory that `edit --backup` copies note files into, under
+    /// each file's own directory path, or empty to keep each copy next
+    /// to its file
+    pub backup_dir: PathBuf,
+
+    /// How many copies of each note file `edit --backup` keeps, newest
+    /// first
+    pub backup_count: usize,
+
+    /// The message printed after `new` or `add` adds a note, or empty
+    /// to print nothing
+    ///
+    /// `{name}`, `{action}`, `{path}`, and `{rel_path}` are replaced by
+    /// the note's name, `created` or `added`, the note's path, and its
+    /// path relative to the current directory.
+    pub success_message: String,
+
+    /// Leave out the messages printed after notes are added, edited,
+    /// or removed successfully
+    pub quiet: bool,
+
+    /// Template files to start new notes from, by tag
+    ///
+    /// Tables must come after plain values in TOML, so this and
+    /// `tag_dirs` stay the last settings.
+    pub tag_templates: BTreeMap<String, PathBuf>,
+
+    /// Directories that notes with each tag belong in
+    ///
+    /// Relative directories are under `notes_root`.
+    pub tag_dirs: BTreeMap<String, PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            multiline_tags: true,
+            strip_all_extensions: false,
+            index_format: IndexFormat::Toml,
+            name_case: NameCase::Preserve,
+            name_regex: DEFAULT_NAME_REGEX.to_string(),
+            notes_root: PathBuf::new(),
+            strict_root: false,
+            default_dir: PathBuf::new(),
+            path_style: PathStyle::Dotted,
+            relative_time: false,
+            editor: String::new(),
+            ignore: DEFAULT_IGNORE.iter().map(|glob| glob.to_string()).collect(),
+            line_endings: LineEndings::Preserve,
+            bom: Bom::Preserve,
+            count_mode: CountMode::Words,
+            backup_dir: PathBuf::new(),
+            backup_count: 1,
+            success_message: DEFAULT_SUCCESS_MESSAGE.to_string(),
+            quiet: false,
+            tag_templates: BTreeMap::new(),
+            tag_dirs: BTreeMap::new(),
+        }
+    }
+}
+
+/// A policy for rewriting the names of new notes.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCase {
+    /// Keep names exactly as given: `My Note`
+    Preserve,
+
+    /// Make every letter lowercase: `my note`
+    Lower,
+
+    /// Lowercase words separated by hyphens: `my-note`
+    Kebab,
+
+    /// Lowercase words separated by underscores: `my_note`
+    Snake,
+}
+
+/// How paths relative to the current directory are written.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    /// Paths below the current directory start with `./`: `./notes/a.txt`
+    #[default]
+    Dotted,
+
+    /// Paths below the current directory start with their first
+    /// directory: `notes/a.txt`
+    Bare,
+}
+
+impl FromStr for PathStyle {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<PathStyle, Error> {
+        match text {
+            "dotted" => Ok(PathStyle::Dotted),
+            "bare" => Ok(PathStyle::Bare),
+            _ => failure::bail!("`{}` is not a path style. Use dotted or bare.", text),
+        }
+    }
+}
+
+/// The characters that end each line of a note file.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// Keep whatever line endings the file already has
+    #[default]
+    Preserve,
+
+    /// A line feed, as on Linux and macOS: `\n`
+    Lf,
+
+    /// A carriage return and a line feed, as on Windows: `\r\n`
+    Crlf,
+}
+
+/// Whether a note file starts with a UTF-8 byte order mark.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Bom {
+    /// Keep the byte order mark if the file has one
+    #[default]
+    Preserve,
+
+    /// Always start the file with a byte order mark
+    Always,
+
+    /// Never start the file with a byte order mark
+    Never,
+}
+
+/// What is counted in the text of a note file.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    /// Words as defined by Unicode word boundaries, where each CJK
+    /// ideograph counts as a word
+    #[default]
+    Words,
+
+    /// User-perceived characters, ignoring whitespace
+    Graphemes,
+}
+
+impl CountMode {
+    /// Returns what is counted, to label counts with.
+    pub fn unit(self) -> &'static str {
+        match self {
+            CountMode::Words => "Words",
+            CountMode::Graphemes => "Graphemes",
+        }
+    }
+}
+
+/// A file format that the index can be stored in.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexFormat {
+    /// Human-friendly and easy to edit by hand
+    Toml,
+
+    /// Faster to read and write for very large indexes
+    Json,
+}
+
+impl IndexFormat {
+    /// Returns the file extension used for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            IndexFormat::Toml => "toml",
+            IndexFormat::Json => "json",
+        }
+    }
+
+    /// Returns the format matching the extension of `path`, if any.
+    pub fn from_path(path: &Path) -> Option<IndexFormat> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse().ok())
+    }
+
+    /// Returns the format that `text`, read from `path`, is written in.
+    ///
+    /// The extension of `path` is used if it names a format. Otherwise,
+    /// text that starts with `{` is taken to be JSON.
+    pub fn detect(path: &Path, text: &str) -> IndexFormat {
+        IndexFormat::from_path(path).unwrap_or_else(|| {
+            if text.trim_start().starts_with('{') {
+                IndexFormat::Json
+            } else {
+                IndexFormat::Toml
+            }
+        })
+    }
+}
+
+impl FromStr for IndexFormat {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<IndexFormat, Error> {
+        match text.to_lowercase().as_str() {
+            "toml" => Ok(IndexFormat::Toml),
+            "json" => Ok(IndexFormat::Json),
+            _ => failure::bail!("`{}` is not an index format. Use toml or json.", text),
+        }
+    }
+}
+
+impl Config {
+    /// Returns the directory that notes are kept in, if one is set.
+    pub fn notes_root(&self) -> Option<&Path> {
+        if self.notes_root.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.notes_root)
+        }
+    }
+
+    /// Returns the directory that new notes are created in, if one is
+    /// set.
+    pub fn default_dir(&self) -> Option<PathBuf> {
+        if self.default_dir.as_os_str().is_empty() {
+            return None;
+        }
+
+        match self.notes_root() {
+            Some(root) if self.default_dir.is_relative() => Some(root.join(&self.default_dir)),
+            _ => Some(self.default_dir.clone()),
+        }
+    }
+
+    /// Returns the command that notes are edited with, if one is set.
+    pub fn editor(&self) -> Option<&str> {
+        if self.editor.is_empty() {
+            None
+        } else {
+            Some(&self.editor)
+        }
+    }
+
+    /// Returns the path of the template for a new note with `tags`.
+    ///
+    /// `template` is used if given. Otherwise, this is the template of
+    /// the first tag in `tags` that has one.
+    pub fn template_for(
+        &self,
+        template: &Option<PathBuf>,
+        tags: &Option<Vec<String>>,
+    ) -> Option<PathBuf> {
+        template.clone().or_else(|| {
+            tags.iter()
+                .flatten()
+                .find_map(|tag| self.tag_templates.get(tag).cloned())
+        })
+    }
+
+    /// Returns the directory that a note with `tags` belongs in, which
+    /// is that of the first tag in `tags` that has one.
+    pub fn dir_for(&self, tags: &Option<Vec<String>>) -> Option<PathBuf> {
+        let dir = tags
+            .iter()
+            .flatten()
+            .find_map(|tag| self.tag_dirs.get(tag))?;
+
+        match self.notes_root() {
+            Some(root) if dir.is_relative() => Some(root.join(dir)),
+            _ => Some(dir.clone()),
+        }
+    }
+
+    /// Creates an instance of `Config` using data stored in
+    /// scrivener-config.toml, or the defaults if it does not exist.
+    pub fn load(program_name: &str) -> Result<Config, Error> {
+        Config::load_path(&Config::path(program_name)?)
+    }
+
+    /// Creates an instance of `Config` using data stored at `path`, or
+    /// the defaults if it does not exist.
+    pub fn load_path(path: &Path) -> Result<Config, Error> {
+        load_toml(path)
+    }
+
+    /// Returns the path of the file that settings are stored in.
+    pub fn path(program_name: &str) -> Result<PathBuf, Error> {
+        Ok(config_dir(program_name)?.join(format!("{}-config.toml", program_name)))
+    }
+
+    /// Writes the settings to the file at `path`, creating its
+    /// directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// - `path` cannot be written to.
+    pub fn store_path(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|_| format!("could not create {}", dir.display()))?;
+        }
+
+        fs::write(path, toml::to_string(self)?)
+            .with_context(|_| format!("could not write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Returns the name and value of every setting, sorted by name.
+    /// See `get` for how values are written.
+    pub fn settings(&self) -> Vec<(String, String)> {
+        self.table()
+            .into_iter()
+            .map(|(key, value)| (key, plain(&value)))
+            .collect()
+    }
+
+    /// Returns the value of the setting named `key`.
+    ///
+    /// Text is returned as it is, without the quotes it has in the
+    /// settings file. Dashes in `key` may be used in place of
+    /// underscores.
+    ///
+    /// # Errors
+    ///
+    /// - There is no setting named `key`.
+    pub fn get(&self, key: &str) -> Result<String, Error> {
+        let key = &setting_name(key);
+        match self.table().get(key) {
+            Some(value) => Ok(plain(value)),
+            None => failure::bail!("`{}` is not a setting.", key),
+        }
+    }
+
+    /// Changes the setting named `key` to `value`.
+    ///
+    /// `value` is parsed as the same type as the current value. Dashes
+    /// in `key` may be used in place of underscores.
+    ///
+    /// # Errors
+    ///
+    /// - There is no setting named `key`.
+    /// - `value` is not valid for the setting.
+    /// - `key` is `index_format`, which only `convert-index` may change
+    ///   because the index has to be converted along with it.
+    /// - `key` is `name_regex` and `value` is not a valid pattern.
+    /// - `key` is `default_dir` and `value` is a relative path, but
+    ///   there is no `notes_root` for it to be relative to.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let key = &setting_name(key);
+        failure::ensure!(
+            key != "index_format",
+            "Run `convert-index <format>` to change `index_format`, so that the index is converted too."
+        );
+        if key == "name_regex" {
+            regex::Regex::new(value)
+                .with_context(|_| format!("The name pattern `{}` is not valid.", value))?;
+        }
+        failure::ensure!(
+            key != "default_dir"
+                || value.is_empty()
+                || Path::new(value).is_absolute()
+                || self.notes_root().is_some(),
+            "`default_dir` must be an absolute path unless `notes_root` is set."
+        );
+        let mut table = self.table();
+
+        let new_value = match table.get(key) {
+            Some(toml::Value::Boolean(_)) => value.parse().map(toml::Value::Boolean).ok(),
+            Some(toml::Value::Integer(_)) => value.parse().map(toml::Value::Integer).ok(),
+            Some(toml::Value::String(_)) => Some(toml::Value::String(value.to_string())),
+            Some(_) => None,
+            None => failure::bail!("`{}` is not a setting.", key),
+        };
+
+        match new_value {
+            Some(new_value) => table.insert(key.to_string(), new_value),
+            None => failure::bail!("`{}` is not a valid value for `{}`.", value, key),
+        };
+
+        *self = toml::Value::Table(table).try_into()?;
+        Ok(())
+    }
+
+    /// Returns the settings as a TOML table.
+    fn table(&self) -> toml::value::Table {
+        match toml::Value::try_from(self) {
+            Ok(toml::Value::Table(table)) => table,
+            _ => unreachable!("Config always serializes to a table"),
+        }
+    }
+}
+
+/// Returns `value` as it is shown to the user: text without quotes,
+/// and anything else as it is written in TOML.
+fn plain(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the name of the setting that `key` refers to, which may be
+/// written with dashes instead of underscores.
+fn setting_name(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+/// Reads a value from the TOML file at `path`, or returns the default
+/// value if the file does not exist.
+///
+/// Nothing is written, so this also works when the configuration
+/// directory is read-only.
+pub(crate) fn load_toml<T: DeserializeOwned + Default>(path: &Path) -> Result<T, Error> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let text =
+        fs::read_to_string(path).with_context(|_| format!("could not read {}", path.display()))?;
+    let value =
+        toml::from_str(&text).with_context(|_| format!("could not parse {}", path.display()))?;
+    Ok(value)
+}
+
+/// Returns `true` if the file at `path` can be written to, or could be
+/// created if it does not exist.
+///
+/// For a missing file, this checks that a file can be created in its
+/// closest existing ancestor, by creating and removing a temporary one.
+/// Permission bits alone do not show this, since they ignore who owns
+/// the directory.
+pub fn is_writable(path: &Path) -> bool {
+    if path.exists() {
+        return OpenOptions::new().write(true).open(path).is_ok();
+    }
+
+    match path.ancestors().skip(1).find(|dir| dir.exists()) {
+        Some(dir) => dir.is_dir() && tempfile::NamedTempFile::new_in(dir).is_ok(),
+        None => false,
+    }
+}
+
+/// Returns the directory that scrivener stores its files in.
+///
+/// Linux: ~/.config/scrivener/
+pub fn config_dir(program_name: &str) -> Result<PathBuf, Error> {
+    match ProjectDirs::from("rs", "", program_name) {
+        Some(dirs) => Ok(dirs.config_dir().to_path_buf()),
+        None => failure::bail!("Could not find a configuration directory."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{NamedTempFile, TempDir};
+
+    use super::*;
+    use crate::scrivener::test_utils::tags;
+
+    #[test]
+    fn writable_paths() {
+        let dir = TempDir::new().unwrap();
+        let file = NamedTempFile::new().unwrap();
+
+        assert!(is_writable(&dir.path().join("scrivener.toml")));
+        assert!(is_writable(&dir.path().join("missing/scrivener.toml")));
+        assert!(is_writable(file.path()));
+
+        // A file cannot contain other files, even for root.
+        assert!(!is_writable(&file.path().join("scrivener.toml")));
+    }
+
+    #[test]
+    fn set_and_get() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("scrivener-config.toml");
+
+        let mut config = Config::default();
+        assert_eq!(config.get("multiline_tags").unwrap(), "true");
+        assert_eq!(config.notes_root(), None);
+
+        config.set("notes_root", "/notes").unwrap();
+        assert_eq!(config.notes_root(), Some(Path::new("/notes")));
+
+        config.set("multiline_tags", "false").unwrap();
+        config.set("count-mode", "graphemes").unwrap();
+        assert!(config.set("count_mode", "letters").is_err());
+        config.store_path(&path).unwrap();
+
+        let config = Config::load_path(&path).unwrap();
+        assert!(!config.multiline_tags);
+        assert_eq!(config.get("multiline_tags").unwrap(), "false");
+        assert_eq!(config.count_mode, CountMode::Graphemes);
+    }
+
+    #[test]
+    fn templates_by_tag() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("scrivener-config.toml");
+        fs::write(
+            &path,
+            "multiline_tags = false\n\n[tag_templates]\nmeeting = '/meeting.md'\n",
+        )
+        .unwrap();
+
+        let config = Config::load_path(&path).unwrap();
+        let explicit = Some(PathBuf::from("/explicit.md"));
+
+        assert_eq!(
+            config.template_for(&None, &tags(&["work", "meeting"])),
+            Some(PathBuf::from("/meeting.md"))
+        );
+        assert_eq!(
+            config.template_for(&explicit, &tags(&["meeting"])),
+            explicit
+        );
+        assert_eq!(config.template_for(&None, &tags(&["work"])), None);
+        assert_eq!(config.template_for(&None, &None), None);
+
+        // Storing keeps the table after the plain settings.
+        config.store_path(&path).unwrap();
+        assert_eq!(Config::load_path(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn dirs_by_tag() {
+        let mut config = Config::default();
+        config
+            .tag_dirs
+            .insert("work".to_string(), PathBuf::from("/work"));
+        config
+            .tag_dirs
+            .insert("home".to_string(), PathBuf::from("home"));
+
+        assert_eq!(
+            config.dir_for(&tags(&["misc", "work", "home"])),
+            Some(PathBuf::from("/work"))
+        );
+        assert_eq!(
+            config.dir_for(&tags(&["home"])),
+            Some(PathBuf::from("home"))
+        );
+        assert_eq!(config.dir_for(&tags(&["misc"])), None);
+
+        config.notes_root = PathBuf::from("/notes");
+        assert_eq!(
+            config.dir_for(&tags(&["home"])),
+            Some(PathBuf::from("/notes/home"))
+        );
+        assert_eq!(
+            config.dir_for(&tags(&["work"])),
+            Some(PathBuf::from("/work"))
+        );
+    }
+
+    #[test]
+    fn editor_and_default_dir() {
+        let mut config = Config::default();
+        assert_eq!(config.editor(), None);
+        assert_eq!(config.default_dir(), None);
+
+        config.set("editor", "nvim").unwrap();
+        assert_eq!(config.editor(), Some("nvim"));
+        assert_eq!(config.get("editor").unwrap(), "nvim");
+
+        // A relative directory needs a notes root to be relative to.
+        assert!(config.set("default-dir", "inbox").is_err());
+        assert_eq!(config.default_dir(), None);
+
+        config.notes_root = PathBuf::from("/notes");
+        config.set("default-dir", "inbox").unwrap();
+        assert_eq!(config.get("default-dir").unwrap(), "inbox");
+        assert_eq!(config.default_dir(), Some(PathBuf::from("/notes/inbox")));
+        config.set("default_dir", "/elsewhere").unwrap();
+        assert_eq!(config.default_dir(), Some(PathBuf::from("/elsewhere")));
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let mut config = Config::default();
+
+        assert!(config.get("nonexistent").is_err());
+        assert!(config.set("nonexistent", "true").is_err());
+        assert!(config.set("multiline_tags", "maybe").is_err());
+        assert!(config.set("name-regex", "(").is_err());
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn index_formats() {
+        assert_eq!(
+            IndexFormat::from_path(Path::new("a/scrivener.json")),
+            Some(IndexFormat::Json)
+        );
+        assert_eq!(IndexFormat::from_path(Path::new("scrivener")), None);
+        assert_eq!(
+            IndexFormat::detect(Path::new("index"), "  {\"notes\": []}"),
+            IndexFormat::Json
+        );
+        assert_eq!(
+            IndexFormat::detect(Path::new("index"), "[[notes]]"),
+            IndexFormat::Toml
+        );
+
+        let mut config = Config::default();
+        let error = config.set("index-format", "json").unwrap_err();
+        assert!(error.to_string().contains("convert-index"));
+        assert_eq!(config.index_format, IndexFormat::Toml);
+    }
+
+    #[test]
+    fn missing_config_is_default() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("scrivener-config.toml");
+
+        assert_eq!(Config::load_path(&path).unwrap(), Config::default());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,131 @@
+//! Reading and writing note files that may be compressed with gzip.
+//!
+//! Compressed files are recognized by a `.gz` extension or by the bytes
+//! every gzip file starts with. Without the `gzip` feature, reading a
+//! compressed file fails instead of returning compressed bytes.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// The bytes every gzip file starts with.
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns `true` if the file at `path` is compressed with gzip, or
+/// would be if it was written with `write`.
+pub(crate) fn is_compressed(path: &Path) -> bool {
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        return true;
+    }
+
+    let mut start = [0; 2];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok()
+        && start == MAGIC
+}
+
+/// Reads the file at `path`, decompressing it if it is compressed.
+///
+/// # Errors
+///
+/// - The file cannot be read or decompressed.
+pub(crate) fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+
+    if bytes.starts_with(&MAGIC) || is_compressed(path) {
+        decompress(&bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Reads the file at `path` as `read` does, as UTF-8 text.
+///
+/// # Errors
+///
+/// - The file cannot be read or decompressed.
+/// - The file is not valid UTF-8.
+pub(crate) fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Writes `bytes` to the file at `path`, compressing them if the file
+/// is compressed.
+///
+/// # Errors
+///
+/// - The file cannot be written.
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if is_compressed(path) {
+        fs::write(path, compress(bytes)?)
+    } else {
+        fs::write(path, bytes)
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(bytes).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(feature = "gzip")]
+fn compress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+#[cfg(not(feature = "gzip"))]
+fn decompress(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "gzip"))]
+fn compress(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+/// The error for compressed files when scrivener is built without the
+/// `gzip` feature.
+#[cfg(not(feature = "gzip"))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the file is compressed with gzip, which this build of scrivener does not support",
+    )
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new().unwrap();
+        let compressed = dir.path().join("note.md.gz");
+        let plain = dir.path().join("note.md");
+
+        write(&compressed, b"archived words").unwrap();
+        write(&plain, b"plain words").unwrap();
+        assert!(fs::read(&compressed).unwrap().starts_with(&MAGIC));
+        assert_eq!(fs::read(&plain).unwrap(), b"plain words");
+
+        assert_eq!(read_to_string(&compressed).unwrap(), "archived words");
+        assert_eq!(read_to_string(&plain).unwrap(), "plain words");
+
+        // Compressed files are found by their contents too.
+        let renamed = dir.path().join("archive");
+        fs::rename(&compressed, &renamed).unwrap();
+        assert!(is_compressed(&renamed));
+        assert_eq!(read_to_string(&renamed).unwrap(), "archived words");
+        assert!(!is_compressed(&plain));
+    }
+}
@@ -1,2 +1,6 @@
 pub mod args;
+pub mod config;
+pub(crate) mod gzip;
 pub mod notes;
+#[cfg(test)]
+pub(crate) mod test_utils;
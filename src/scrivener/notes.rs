@@ -1,14 +1,35 @@
 //! Note and Index
 
 use failure::{Error, ResultExt};
+use ignore::gitignore::GitignoreBuilder;
+use ignore::WalkBuilder;
+use regex::Regex;
+use serde::de::DeserializeSeed;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::scrivener::config::{self, Config, CountMode, IndexFormat, NameCase};
+use crate::scrivener::gzip;
+
+/// Appended to the path of a note's file to get the path of its
+/// metadata sidecar.
+const SIDECAR_SUFFIX: &str = ".scrivener.json";
+
+/// The name of the files of `.gitignore`-style patterns that scans of a
+/// directory also honor.
+pub const IGNORE_FILE_NAME: &str = ".scrivenerignore";
 
 /// Data that points to and uniquely identifies a plaintext file
-#[derive(Deserialize, Serialize, Default, Debug, Eq)]
+#[derive(Deserialize, Serialize, Default, Debug, Clone, Eq)]
 pub struct Note {
     /// A unique identifier that is used to refer to the note
     name: String,
@@ -20,6 +41,37 @@ pub struct Note {
     ///
     /// TODO: make tags searchable
     tags: Option<Vec<String>>,
+
+    /// When the note was last modified
+    #[serde(default, with = "timestamp", skip_serializing_if = "Option::is_none")]
+    modified: Option<SystemTime>,
+
+    /// When the note was added to the index
+    #[serde(default, with = "timestamp", skip_serializing_if = "Option::is_none")]
+    created: Option<SystemTime>,
+
+    /// The position of the note in the order notes were added to the
+    /// index, starting at 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<u64>,
+
+    /// The number of words in the file when it was last counted
+    #[serde(default, skip_serializing_if = "is_unset")]
+    words: Cell<Option<usize>>,
+
+    /// The modification time of the file, in microseconds since the
+    /// Unix epoch, when its words were last counted
+    #[serde(default, skip_serializing_if = "is_unset")]
+    counted: Cell<Option<u64>>,
+
+    /// What was counted in the cached count
+    #[serde(default, skip_serializing_if = "counts_words")]
+    counted_as: Cell<CountMode>,
+
+    /// Whether the cached word count has changed since the note was
+    /// loaded
+    #[serde(skip)]
+    cache_updated: Cell<bool>,
 }
 
 impl PartialEq for Note {
@@ -51,13 +103,18 @@ impl Note {
     /// - The file at `path` does not exist.
     /// - `path` points to a directory.
     pub fn new(name: &str, path: &PathBuf, tags: &Option<Vec<String>>) -> Result<Note, Error> {
-        let path = fs::canonicalize(&path)
+        let path = fs::canonicalize(path)
             .with_context(|_| format!("Could not read file `{:?}`.", path))?;
 
+        let now = SystemTime::now();
+
         Ok(Note {
             name: name.to_string(),
             path,
             tags: tags.clone(),
+            modified: Some(now),
+            created: Some(now),
+            ..Note::default()
         })
     }
 
@@ -78,185 +135,3139 @@ impl Note {
         &self.tags
     }
 
+    /// Returns `true` if the `Note` has a tag equal to `query`, or
+    /// containing it if `partial` is true, ignoring case.
+    pub fn has_tag(&self, query: &str, partial: bool) -> bool {
+        let query = query.to_lowercase();
+
+        self.tags.iter().flatten().any(|tag| {
+            let tag = tag.to_lowercase();
+            if partial {
+                tag.contains(&query)
+            } else {
+                tag == query
+            }
+        })
+    }
+
+    /// Returns when the `Note` was last modified.
+    ///
+    /// Returns None for notes added before modification times were
+    /// recorded.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Returns when the `Note` was added to the index.
+    ///
+    /// Returns None for notes added before creation times were recorded.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
+
+    /// Returns `true` if the `Note`'s file was modified after the time
+    /// recorded in the `Note`, comparing whole microseconds since that
+    /// is how the time is stored.
+    ///
+    /// A `Note` with no recorded time counts as changed if its file
+    /// exists, and one whose file is missing never does.
+    pub fn changed_on_disk(&self) -> bool {
+        match (file_modified(&self.path), self.modified) {
+            (Some(actual), Some(recorded)) => {
+                timestamp::micros(actual) > timestamp::micros(recorded)
+            }
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Returns `true` if `since` is `None` or the `Note` was modified
+    /// after it.
+    ///
+    /// A `Note` without a recorded modification time was never modified
+    /// after any time.
+    pub fn modified_since(&self, since: Option<SystemTime>) -> bool {
+        match since {
+            Some(since) => self.modified.is_some_and(|modified| modified > since),
+            None => true,
+        }
+    }
+
+    /// Returns the size of the `Note`'s file in bytes, or `None` if it
+    /// is missing or cannot be read.
+    pub fn size(&self) -> Option<u64> {
+        fs::metadata(&self.path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+    }
+
+    /// Returns the position of the `Note` in the order notes were added
+    /// to the `Index`.
+    ///
+    /// Returns None for notes added before the order was recorded.
+    pub fn order(&self) -> Option<u64> {
+        self.order
+    }
+
+    /// Returns the names of the notes that the `Note`'s file links to
+    /// with `[[name]]` or `[[name|label]]`, in the order they first
+    /// appear.
+    ///
+    /// # Errors
+    ///
+    /// - The file cannot be read.
+    pub fn links(&self) -> Result<Vec<String>, Error> {
+        let text = gzip::read_to_string(&self.path)
+            .with_context(|_| format!("Could not read file `{:?}`.", self.path))?;
+        Ok(wiki_links(&text))
+    }
+
+    /// Returns the number of words or graphemes in the `Note`'s file,
+    /// depending on `mode`.
+    ///
+    /// The count is cached in the `Note` and only recomputed if the
+    /// file's modification time or `mode` changed since it was last
+    /// counted.
+    ///
+    /// # Errors
+    ///
+    /// - The file cannot be read.
+    pub fn word_count(&self, mode: CountMode) -> Result<usize, Error> {
+        let mtime = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|_| format!("Could not read file `{:?}`.", self.path))?;
+        let mtime = timestamp::micros(mtime);
+
+        if let (Some(words), Some(counted)) = (self.words.get(), self.counted.get()) {
+            if mtime == counted && self.counted_as.get() == mode {
+                return Ok(words);
+            }
+        }
+
+        let text = gzip::read_to_string(&self.path)
+            .with_context(|_| format!("Could not read file `{:?}`.", self.path))?;
+        let words = count(&text, mode);
+
+        self.words.set(Some(words));
+        self.counted.set(Some(mtime));
+        self.counted_as.set(mode);
+        self.cache_updated.set(true);
+
+        Ok(words)
+    }
+
+    /// Returns the path of the `Note`'s metadata sidecar, which is its
+    /// file's path followed by `.scrivener.json`.
+    pub fn sidecar_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(SIDECAR_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// Writes the `Note` as JSON to its sidecar and returns the
+    /// sidecar's path.
+    ///
+    /// # Errors
+    ///
+    /// - The sidecar cannot be written.
+    pub fn write_sidecar(&self) -> Result<PathBuf, Error> {
+        let path = self.sidecar_path();
+        let text = serde_json::to_string_pretty(self)
+            .with_context(|_| format!("could not serialize note `{}`", self.name))?;
+
+        fs::write(&path, text).with_context(|_| format!("could not write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Returns `true` if the cached word count has changed since the
+    /// `Note` was loaded, meaning the `Index` should be stored again.
+    pub fn cache_updated(&self) -> bool {
+        self.cache_updated.get()
+    }
+
+    /// Returns whether `self` and `other` store the same fields, ignoring
+    /// cached word counts. Times are compared to the microsecond, the
+    /// precision they are stored with.
+    fn same_record(&self, other: &Note) -> bool {
+        let micros = |time: Option<SystemTime>| time.map(timestamp::micros);
+
+        self.name == other.name
+            && self.path == other.path
+            && self.tags == other.tags
+            && micros(self.modified) == micros(other.modified)
+            && micros(self.created) == micros(other.created)
+            && self.order == other.order
+    }
+
+    /// Checks the invariants every `Note` in an `Index` should uphold.
+    ///
+    /// # Errors
+    ///
+    /// Returns every problem found, rather than just the first.
+    pub fn validate(&self) -> Result<(), Vec<NoteProblem>> {
+        let mut problems = Vec::new();
+
+        if self.name.trim().is_empty() {
+            problems.push(NoteProblem::EmptyName);
+        }
+
+        if !self.path.is_absolute() {
+            problems.push(NoteProblem::RelativePath);
+        } else if !self.path.is_file() {
+            problems.push(NoteProblem::MissingFile);
+        } else if fs::canonicalize(&self.path).is_ok_and(|path| path != self.path) {
+            problems.push(NoteProblem::NonCanonicalPath);
+        }
+
+        if let Some(tags) = &self.tags {
+            if tags.is_empty() {
+                problems.push(NoteProblem::EmptyTagList);
+            }
+
+            if tags.iter().any(|tag| tag.trim().is_empty()) {
+                problems.push(NoteProblem::EmptyTag);
+            }
+
+            let mut seen = BTreeSet::new();
+            for tag in tags {
+                if !seen.insert(tag) {
+                    problems.push(NoteProblem::DuplicateTag(tag.clone()));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
     /// A helper function to create an instance of `Note` intended to
     /// help search functions search using only the `name`.
     fn dummy(name: &str) -> Note {
         Note {
             name: name.to_string(),
-            path: PathBuf::new(),
-            tags: None,
+            ..Note::default()
+        }
+    }
+}
+
+/// A broken invariant reported by `Note::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoteProblem {
+    /// The name is empty or only whitespace
+    EmptyName,
+
+    /// The path is not absolute
+    RelativePath,
+
+    /// The path does not point to an existing file
+    MissingFile,
+
+    /// The path is absolute but not canonical, such as when it goes
+    /// through a symlink or contains `..`
+    NonCanonicalPath,
+
+    /// The tags are `Some` but contain no tags
+    EmptyTagList,
+
+    /// A tag is empty or only whitespace
+    EmptyTag,
+
+    /// A tag appears more than once
+    DuplicateTag(String),
+}
+
+impl fmt::Display for NoteProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoteProblem::EmptyName => write!(f, "the name is empty"),
+            NoteProblem::RelativePath => write!(f, "the path is not absolute"),
+            NoteProblem::MissingFile => write!(f, "the file does not exist"),
+            NoteProblem::NonCanonicalPath => write!(f, "the path is not canonical"),
+            NoteProblem::EmptyTagList => write!(f, "the tag list is empty"),
+            NoteProblem::EmptyTag => write!(f, "a tag is empty"),
+            NoteProblem::DuplicateTag(tag) => write!(f, "the tag `{}` appears more than once", tag),
+        }
+    }
+}
+
+/// Changes to make to a `Note`'s metadata, as read by `apply-meta`.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MetaUpdate {
+    /// Tags to replace the `Note`'s tags with, where an empty list
+    /// removes them all
+    pub tags: Option<Vec<String>>,
+
+    /// Tags to add to the `Note` if it does not have them yet
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+}
+
+/// What happened to a `Note` when one of its tags was renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagRename {
+    /// The old tag was replaced by the new one
+    Renamed,
+
+    /// The `Note` already had the new tag, so the old one was removed
+    Merged,
+}
+
+impl fmt::Display for TagRename {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagRename::Renamed => write!(f, "renamed"),
+            TagRename::Merged => write!(f, "merged"),
         }
     }
 }
 
+/// Statistics about the `Note`s in an `Index`.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct IndexStats {
+    /// The number of notes
+    pub total: usize,
+
+    /// The number of notes with at least one tag
+    pub tagged: usize,
+
+    /// The number of notes without tags
+    pub untagged: usize,
+
+    /// The number of notes with each tag
+    pub tag_counts: BTreeMap<String, usize>,
+
+    /// The number of words in all readable note files
+    pub total_words: usize,
+
+    /// The number of notes whose files do not exist
+    pub missing_files: usize,
+}
+
 /// An index of `Note`s in alphabetical order by name.
-#[derive(Deserialize, Serialize, Default, Debug)]
+///
+/// `Note`s cache some values in `Cell`s, but they are ordered only by
+/// their names, which never change while in the set.
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct Index {
     notes: BTreeSet<Note>,
+
+    /// Whether `notes` has changed since the `Index` was loaded
+    #[serde(skip)]
+    dirty: bool,
 }
 
 impl Index {
-    /// Creates an instance of `Note` and adds it to `self`.
+    /// Creates an instance of `Note`, adds it to `self`, and returns a
+    /// reference to it.
+    ///
+    /// # Errors
+    ///
+    /// - A `Note` named `name` already exists.
+    /// - The `Note` cannot be created. See `Note::new`.
     pub fn add(
         &mut self,
         name: &str,
         path: &PathBuf,
         tags: &Option<Vec<String>>,
-    ) -> Result<(), Error> {
-        self.notes.insert(Note::new(name, path, tags)?);
-        Ok(())
+    ) -> Result<&Note, Error> {
+        failure::ensure!(
+            !self.contains(name),
+            "A note named `{}` already exists.",
+            name
+        );
+
+        let mut note = Note::new(name, path, tags)?;
+        note.order = Some(self.next_order());
+        self.notes.insert(note);
+        self.dirty = true;
+        Ok(self.get(name).unwrap())
     }
 
-    /// Removes a `Note` from `self`.
-    pub fn remove(&mut self, name: &str) -> bool {
-        self.notes.remove(&Note::dummy(name))
+    /// Returns the order to give the next `Note` added to `self`.
+    fn next_order(&self) -> u64 {
+        self.notes.iter().filter_map(Note::order).max().unwrap_or(0) + 1
     }
 
-    /// Returns `true` if an Index contains a note with the given `name`
-    /// and false otherwise.
-    pub fn contains(&self, name: &str) -> bool {
-        self.notes.contains(&Note::dummy(name))
+    /// Removes a `Note` from `self` and returns it, or `None` if there
+    /// is no `Note` with the given `name`.
+    pub fn remove(&mut self, name: &str) -> Option<Note> {
+        let removed = self.notes.take(&Note::dummy(name));
+        self.dirty |= removed.is_some();
+        removed
     }
 
-    /// Creates an instance of `Index` using data stored in the config
-    /// file, scrivener.toml.
-    pub fn load(filename: &str) -> Result<Index, Error> {
-        let index =
-            confy::load(filename).with_context(|_| format!("could not read {}.toml", filename))?;
-        Ok(index)
+    /// Puts back a `Note` that `remove` returned, keeping its path, tags
+    /// and order as they were.
+    pub fn restore(&mut self, note: Note) {
+        self.notes.insert(note);
+        self.dirty = true;
     }
 
-    /// Updates scrivener.toml using an instance of `Index`
-    pub fn store(&self, filename: &str) -> Result<(), Error> {
-        confy::store(filename, self)
-            .with_context(|_| format!("could not write to {}.toml", filename))?;
-        Ok(())
+    /// Changes the name of the `Note` named `old` to `new` and returns a
+    /// reference to it. The `Note`'s file is left as it is.
+    ///
+    /// # Errors
+    ///
+    /// - There is no `Note` named `old`.
+    /// - A `Note` named `new` already exists.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<&Note, Error> {
+        failure::ensure!(self.contains(old), "Note `{}` does not exist.", old);
+        failure::ensure!(
+            !self.contains(new),
+            "A note named `{}` already exists.",
+            new
+        );
+
+        // Notes are ordered by name, so the note is taken out of the set
+        // while its name changes.
+        let mut note = self.notes.take(&Note::dummy(old)).unwrap();
+        note.name = new.to_string();
+        self.notes.insert(note);
+        self.dirty = true;
+
+        Ok(self.get(new).unwrap())
     }
 
-    /// Returns a reference to a Note with a given `name`
-    pub fn get(&self, name: &str) -> Option<&Note> {
-        self.notes.get(&Note::dummy(name))
+    /// Sets the modification time of the `Note` with the given `name`
+    /// to now and returns a reference to it.
+    ///
+    /// Returns `None` if there is no such `Note`.
+    pub fn touch(&mut self, name: &str) -> Option<&Note> {
+        let mut note = self.notes.take(&Note::dummy(name))?;
+        note.modified = Some(SystemTime::now());
+        self.notes.insert(note);
+        self.dirty = true;
+        self.get(name)
     }
 
-    /// Returns a reference to an `Index`'s notes
-    pub fn notes(&self) -> &BTreeSet<Note> {
-        &self.notes
+    /// Points the `Note` with the given `name` at the file at `path`
+    /// and returns a reference to it.
+    ///
+    /// Returns `None` if there is no such `Note`.
+    ///
+    /// # Errors
+    ///
+    /// - The file at `path` does not exist.
+    pub fn set_path(&mut self, name: &str, path: &Path) -> Result<Option<&Note>, Error> {
+        let path = fs::canonicalize(path)
+            .with_context(|_| format!("Could not read file `{:?}`.", path))?;
+
+        let mut note = match self.notes.take(&Note::dummy(name)) {
+            Some(note) => note,
+            None => return Ok(None),
+        };
+        note.path = path;
+        self.notes.insert(note);
+        self.dirty = true;
+
+        Ok(self.get(name))
     }
 
-    /// Creates an empty instance of Index.
-    #[allow(dead_code)]
-    pub(super) fn new() -> Index {
-        Index {
-            notes: BTreeSet::new(),
-        }
+    /// Points every `Note` whose file is at `from` at the file at `to`,
+    /// such as after the file was moved, and returns their names in
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// - The file at `to` does not exist.
+    pub fn move_path(&mut self, from: &Path, to: &Path) -> Result<Vec<String>, Error> {
+        let to =
+            fs::canonicalize(to).with_context(|_| format!("Could not read file `{:?}`.", to))?;
+        let mut moved = Vec::new();
+
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .map(|mut note| {
+                if note.path == from {
+                    note.path = to.clone();
+                    moved.push(note.name.clone());
+                }
+                note
+            })
+            .collect();
+
+        self.dirty |= !moved.is_empty();
+        Ok(moved)
     }
-}
 
-impl PartialEq for Index {
-    fn eq(&self, other: &Self) -> bool {
-        self.notes == other.notes
+    /// Changes the path of every `Note` whose file was under `from` to
+    /// the same path under `to`, and returns the names of the `Note`s
+    /// that were changed and of those left alone because there is no
+    /// file at the new path.
+    ///
+    /// `from` is made canonical first, like the stored paths, so it may
+    /// be relative. If it no longer exists, its directory is made
+    /// canonical instead, or it is only made absolute.
+    pub fn relocate(&mut self, from: &Path, to: &Path) -> (Vec<String>, Vec<String>) {
+        let (mut fixed, mut unresolved) = (Vec::new(), Vec::new());
+        let from = canonicalize_missing(from).unwrap_or_else(|_| {
+            std::env::current_dir()
+                .map(|dir| dir.join(from))
+                .unwrap_or_else(|_| from.to_path_buf())
+        });
+
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .map(|mut note| {
+                if let Ok(rest) = note.path.strip_prefix(&from) {
+                    match fs::canonicalize(to.join(rest)) {
+                        Ok(path) => {
+                            note.path = path;
+                            fixed.push(note.name.clone());
+                        }
+                        Err(_) => unresolved.push(note.name.clone()),
+                    }
+                }
+                note
+            })
+            .collect();
+
+        self.dirty |= !fixed.is_empty();
+        (fixed, unresolved)
     }
-}
 
-//TODO: Improve tests
-#[cfg(test)]
-mod tests {
-    use tempfile::NamedTempFile;
+    /// Sets the modification time of the `Note` with the given `name` to
+    /// that of its file, recounts its words or graphemes depending on
+    /// `mode`, and returns a reference to it.
+    ///
+    /// Returns `None` if there is no such `Note`.
+    pub fn refresh(&mut self, name: &str, mode: CountMode) -> Option<&Note> {
+        let mut note = self.notes.take(&Note::dummy(name))?;
+        note.modified = file_modified(&note.path).or(note.modified);
+        // A file that cannot be read keeps its old count.
+        let _ = note.word_count(mode);
+        self.notes.insert(note);
+        self.dirty = true;
+        self.get(name)
+    }
 
-    use super::*;
+    /// Adds each of `tags` that the `Note` named `name` does not have
+    /// yet, and returns a reference to the `Note`.
+    ///
+    /// Tags are trimmed, empty ones are skipped, and, as in
+    /// `clean_tags`, tags are compared without regard to case.
+    ///
+    /// Returns `None` if there is no such `Note`.
+    pub fn add_tags(&mut self, name: &str, tags: &[String]) -> Option<&Note> {
+        let mut note = self.notes.take(&Note::dummy(name))?;
 
-    // Currently broken when testing for Windows on Linux.
-    #[test]
-    fn create_note() {
-        let file = NamedTempFile::new().unwrap();
+        for tag in tags
+            .iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+        {
+            let note_tags = note.tags.get_or_insert_with(Vec::new);
+            let lower = tag.to_lowercase();
+            if !note_tags.iter().any(|old| old.to_lowercase() == lower) {
+                note_tags.push(tag.to_string());
+                self.dirty = true;
+            }
+        }
 
-        let name = "test";
-        let path = file.path().to_path_buf();
+        self.notes.insert(note);
+        self.get(name)
+    }
 
-        let note = Note::new(name, &path, &None).unwrap();
+    /// Removes each of `tags` from the `Note` named `name`, and returns
+    /// a reference to the `Note`. A `Note` left without tags has none
+    /// at all, as if it was never tagged.
+    ///
+    /// Tags are trimmed and compared without regard to case.
+    ///
+    /// Returns `None` if there is no such `Note`.
+    pub fn remove_tags(&mut self, name: &str, tags: &[String]) -> Option<&Note> {
+        let mut note = self.notes.take(&Note::dummy(name))?;
 
-        let expected = Note {
-            name: name.to_string(),
-            path,
-            tags: None,
-        };
+        if let Some(note_tags) = &mut note.tags {
+            let removed: BTreeSet<String> =
+                tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+            let before = note_tags.len();
+            note_tags.retain(|tag| !removed.contains(&tag.to_lowercase()));
+            self.dirty |= note_tags.len() != before;
 
-        assert_eq!(note, expected);
-        assert_eq!(note.path, expected.path);
-        assert_eq!(note.tags, expected.tags);
+            if note_tags.is_empty() {
+                note.tags = None;
+            }
+        }
+
+        self.notes.insert(note);
+        self.get(name)
     }
 
-    // Currently broken when testing for Windows on Linux.
-    #[test]
-    fn create_note_with_tags() {
-        let file = NamedTempFile::new().unwrap();
+    /// Applies every update in `updates` to the `Note` with the same
+    /// name, and returns the names of the `Note`s that changed.
+    ///
+    /// # Errors
+    ///
+    /// - Any of the names is not in the `Index`, in which case nothing
+    ///   is changed.
+    pub fn apply_meta(
+        &mut self,
+        updates: &BTreeMap<String, MetaUpdate>,
+    ) -> Result<Vec<String>, Error> {
+        let missing: Vec<&str> = updates
+            .keys()
+            .filter(|name| !self.contains(name))
+            .map(String::as_str)
+            .collect();
+        failure::ensure!(
+            missing.is_empty(),
+            "No notes are named {}.",
+            missing.join(", ")
+        );
 
-        let name = "test";
-        let path = file.path().to_path_buf();
-        let tags = Some(vec![
-            "one".to_string(),
-            "two".to_string(),
-            "three".to_string(),
-        ]);
+        let mut changed = Vec::new();
+        for (name, update) in updates {
+            let mut note = self.notes.take(&Note::dummy(name)).unwrap();
+            let before = note.tags.clone();
 
-        let note = Note::new(name, &path, &tags).unwrap();
+            if let Some(tags) = &update.tags {
+                note.tags = Some(tags.clone()).filter(|tags| !tags.is_empty());
+            }
+            for tag in &update.add_tags {
+                let note_tags = note.tags.get_or_insert_with(Vec::new);
+                if !note_tags.contains(tag) {
+                    note_tags.push(tag.clone());
+                }
+            }
 
-        let expected = Note {
-            name: name.to_string(),
-            path,
-            tags,
-        };
+            if note.tags != before {
+                changed.push(name.clone());
+                self.dirty = true;
+            }
+            self.notes.insert(note);
+        }
 
-        assert_eq!(note, expected);
-        assert_eq!(note.path, expected.path);
-        assert_eq!(note.tags, expected.tags);
+        Ok(changed)
     }
 
-    #[test]
-    fn add_note_to_index() {
-        let file = NamedTempFile::new().unwrap();
+    /// Renames the tag `old` to `new` on every `Note` that has it, and
+    /// returns the names of those `Note`s with what happened to each.
+    ///
+    /// A `Note` that already has `new` just loses `old`, so it is not
+    /// tagged `new` twice. Tags are compared exactly, and nothing changes
+    /// if `old` and `new` are the same.
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> Vec<(String, TagRename)> {
+        let mut outcomes = Vec::new();
+        if old == new {
+            return outcomes;
+        }
 
-        let name = "Test Add";
-        let path = file.path().to_path_buf();
-        let tags = Some(vec![String::from("one"), String::from("two")]);
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .map(|mut note| {
+                if let Some(tags) = note.tags.as_mut() {
+                    if let Some(position) = tags.iter().position(|tag| tag == old) {
+                        let outcome = if tags.iter().any(|tag| tag == new) {
+                            tags.remove(position);
+                            TagRename::Merged
+                        } else {
+                            tags[position] = new.to_string();
+                            TagRename::Renamed
+                        };
+                        outcomes.push((note.name.clone(), outcome));
+                    }
+                }
+                note
+            })
+            .collect();
 
-        let mut index = Index {
-            notes: BTreeSet::new(),
-        };
+        self.dirty |= !outcomes.is_empty();
+        outcomes
+    }
 
-        index.add(name, &path, &tags).unwrap();
+    /// Removes empty and duplicate tags from every `Note`, comparing tags
+    /// without regard to case and keeping the first of each, and
+    /// returns the number of `Note`s that changed.
+    ///
+    /// A `Note` left with no tags has its tags set to `None`.
+    pub fn clean_tags(&mut self) -> usize {
+        let mut cleaned = 0;
 
-        let mut expected = Index::new();
-        expected.add(name, &path, &tags).unwrap();
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .map(|mut note| {
+                if let Some(tags) = note.tags.take() {
+                    let mut seen = BTreeSet::new();
+                    let kept: Vec<String> = tags
+                        .iter()
+                        .filter(|tag| !tag.trim().is_empty() && seen.insert(tag.to_lowercase()))
+                        .cloned()
+                        .collect();
 
-        assert_eq!(index, expected);
+                    if kept != tags || kept.is_empty() {
+                        cleaned += 1;
+                    }
+                    if !kept.is_empty() {
+                        note.tags = Some(kept);
+                    }
+                }
+                note
+            })
+            .collect();
+
+        self.dirty |= cleaned > 0;
+        cleaned
     }
 
-    #[test]
-    fn remove_note_from_index() {
-        let file = NamedTempFile::new().unwrap();
+    /// Removes the `Note` named `other`, adds any of its tags that are
+    /// missing to the `Note` named `keep`, and returns the removed
+    /// `Note`.
+    ///
+    /// Returns `None`, changing nothing, if either `Note` does not
+    /// exist or they are the same `Note`.
+    pub fn absorb(&mut self, keep: &str, other: &str) -> Option<Note> {
+        if keep == other || !self.contains(keep) {
+            return None;
+        }
 
-        let name = "Test Remove";
-        let path = file.path().to_path_buf();
+        let other = self.notes.take(&Note::dummy(other))?;
+        let mut note = self.notes.take(&Note::dummy(keep)).unwrap();
 
-        let mut index = Index::new();
-        index.add(name, &path, &None).unwrap();
+        for tag in other.tags.iter().flatten() {
+            let tags = note.tags.get_or_insert_with(Vec::new);
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        self.notes.insert(note);
+        self.dirty = true;
+        Some(other)
+    }
 
-        assert!(index.remove(name));
+    /// Removes every `Note` whose file is at `path` from `self` and
+    /// returns them.
+    pub fn remove_path(&mut self, path: &Path) -> Vec<Note> {
+        let (removed, kept): (BTreeSet<Note>, _) = std::mem::take(&mut self.notes)
+            .into_iter()
+            .partition(|note| note.path == path);
+        self.notes = kept;
 
-        assert_eq!(index, Index::new());
+        self.dirty |= !removed.is_empty();
+        removed.into_iter().collect()
     }
 
-    #[test]
-    fn index_contains_note() {
-        let file = NamedTempFile::new().unwrap();
+    /// Removes every `Note` whose file no longer exists from `self` and
+    /// returns their names in order.
+    pub fn prune(&mut self) -> Vec<String> {
+        let (removed, kept): (BTreeSet<Note>, _) = std::mem::take(&mut self.notes)
+            .into_iter()
+            .partition(|note| !note.path.exists());
+        self.notes = kept;
 
-        let name = "Test Contains";
-        let path = file.path().to_path_buf();
+        self.dirty |= !removed.is_empty();
+        removed.into_iter().map(|note| note.name).collect()
+    }
 
-        let mut index = Index::new();
-        index.add(name, &path, &None).unwrap();
+    /// Returns every way that `self` became inconsistent since it was
+    /// `before`, such as after a command moved or renamed notes.
+    ///
+    /// `Note`s that are new or changed must pass `Note::validate`,
+    /// except that their files may be missing, so the paths of files
+    /// that exist must be canonical. A `Note` may not be left pointing at
+    /// a missing file that another `Note` moved away from, and no file
+    /// may be shared by more `Note`s than before, unless they all shared
+    /// one file before. Problems that were already there are not
+    /// reported.
+    pub fn consistency_problems(&self, before: &Index) -> Vec<String> {
+        let mut problems = Vec::new();
 
-        assert!(index.contains(name));
+        for note in &self.notes {
+            let unchanged = before
+                .get(&note.name)
+                .is_some_and(|old| old.same_record(note));
+            if unchanged {
+                if !note.path.exists() {
+                    let moved_off = before.notes.iter().find(|old| {
+                        old.name != note.name
+                            && old.path == note.path
+                            && self.get(&old.name).is_some_and(|new| new.path != old.path)
+                    });
+                    if let Some(other) = moved_off {
+                        problems.push(format!(
+                            "note `{}` was left at {} when note `{}` moved off it",
+                            note.name,
+                            note.path.display(),
+                            other.name
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            if let Err(found) = note.validate() {
+                for problem in found {
+                    if problem != NoteProblem::MissingFile {
+                        problems.push(format!("note `{}`: {}", note.name, problem));
+                    }
+                }
+            }
+        }
+
+        let sharers = |index: &Index| {
+            let mut sharers: HashMap<PathBuf, Vec<String>> = HashMap::new();
+            for note in &index.notes {
+                sharers
+                    .entry(note.path.clone())
+                    .or_default()
+                    .push(note.name.clone());
+            }
+            sharers
+        };
+        // Notes that shared a file before may move to a new one together.
+        let old_sharers = sharers(before);
+        let shared_before = |names: &[String]| {
+            let old_path = |name: &String| before.get(name).map(|old| &old.path);
+            names
+                .iter()
+                .all(|name| old_path(name).is_some() && old_path(name) == old_path(&names[0]))
+        };
+        let mut shared: Vec<(PathBuf, Vec<String>)> = sharers(self)
+            .into_iter()
+            .filter(|(path, names)| {
+                names.len() > 1
+                    && names.len() > old_sharers.get(path).map_or(0, Vec::len)
+                    && !shared_before(names)
+            })
+            .collect();
+        shared.sort();
+        for (path, names) in shared {
+            problems.push(format!(
+                "{} is shared by notes {}",
+                path.display(),
+                names.join(", ")
+            ));
+        }
+
+        problems
+    }
+
+    /// Returns `true` if the `Index` has changed since it was loaded,
+    /// including any cached word counts, and should be stored again.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty || self.notes.iter().any(Note::cache_updated)
+    }
+
+    /// Returns `true` if an Index contains a note with the given `name`
+    /// and false otherwise.
+    pub fn contains(&self, name: &str) -> bool {
+        self.notes.contains(&Note::dummy(name))
+    }
+
+    /// Creates an instance of `Index` using data stored in the config
+    /// file, scrivener.toml or scrivener.json depending on the `Config`.
+    pub fn load(filename: &str, config: &Config) -> Result<Index, Error> {
+        Index::load_path(&Index::path(filename, config.index_format)?)
+    }
+
+    /// Creates an instance of `Index` using data stored at `path`, or
+    /// an empty `Index` if it does not exist.
+    ///
+    /// The format is taken from the extension of `path`, or guessed
+    /// from the contents if the extension is not recognized.
+    pub fn load_path(path: &Path) -> Result<Index, Error> {
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+
+        let text = fs::read_to_string(path)
+            .with_context(|_| format!("could not read {}", path.display()))?;
+
+        let index = match IndexFormat::detect(path, &text) {
+            IndexFormat::Toml => toml::from_str(&text).map_err(Error::from),
+            IndexFormat::Json => serde_json::from_str(&text).map_err(Error::from),
+        };
+
+        Ok(index.with_context(|_| format!("could not parse {}", path.display()))?)
+    }
+
+    /// Calls `visit` with each `Note` stored at `path`, in alphabetical
+    /// order by name, without building an `Index`. Nothing is visited
+    /// if `path` does not exist.
+    ///
+    /// This is for read-only commands that look at each `Note` once,
+    /// such as `names`, so that very large indexes are never held in
+    /// memory all at once. JSON indexes are read a little at a time.
+    /// TOML indexes are read whole, but their `Note`s are still not
+    /// kept.
+    ///
+    /// # Errors
+    ///
+    /// - The file cannot be read or parsed.
+    /// - `visit` returns an error, which stops reading.
+    pub fn stream_path<F>(path: &Path, visit: F) -> Result<(), Error>
+    where
+        F: FnMut(Note) -> Result<(), Error>,
+    {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let read_error = || format!("could not read {}", path.display());
+        let file = File::open(path).with_context(|_| read_error())?;
+        let mut reader = BufReader::new(file);
+        let start =
+            String::from_utf8_lossy(reader.fill_buf().with_context(|_| read_error())?).into_owned();
+
+        let mut stopped = None;
+        let seed = stream::Notes {
+            visit,
+            stopped: &mut stopped,
+        };
+        let streamed = match IndexFormat::detect(path, &start) {
+            IndexFormat::Toml => {
+                let mut text = String::new();
+                reader
+                    .read_to_string(&mut text)
+                    .with_context(|_| read_error())?;
+                seed.deserialize(&mut toml::Deserializer::new(&text))
+                    .map_err(Error::from)
+            }
+            IndexFormat::Json => seed
+                .deserialize(&mut serde_json::Deserializer::from_reader(reader))
+                .map_err(Error::from),
+        };
+
+        // An error from `visit` is returned as it is, rather than as a
+        // problem with the file.
+        if let Some(error) = stopped {
+            return Err(error);
+        }
+
+        Ok(streamed.with_context(|_| format!("could not parse {}", path.display()))?)
+    }
+
+    /// Updates scrivener.toml or scrivener.json using an instance of
+    /// `Index`
+    ///
+    /// Tag lists are written one tag per line unless disabled in the
+    /// `Config`.
+    pub fn store(&self, filename: &str, config: &Config) -> Result<(), Error> {
+        self.store_path(&Index::path(filename, config.index_format)?, config)
+    }
+
+    /// Writes an `Index` to `path`, creating its directory if needed.
+    ///
+    /// The format is taken from the extension of `path`, or from the
+    /// `Config` if the extension is not recognized.
+    pub fn store_path(&self, path: &Path, config: &Config) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|_| format!("could not create {}", dir.display()))?;
+        }
+
+        let text = match IndexFormat::from_path(path).unwrap_or(config.index_format) {
+            IndexFormat::Toml => self.to_toml(config.multiline_tags)?,
+            IndexFormat::Json => self.to_json()?,
+        };
+
+        fs::write(path, text).with_context(|_| format!("could not write to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Returns the path of scrivener.toml, or scrivener.json if
+    /// `format` is JSON.
+    pub fn path(filename: &str, format: IndexFormat) -> Result<PathBuf, Error> {
+        Ok(config::config_dir(filename)?.join(format!("{}.{}", filename, format.extension())))
+    }
+
+    /// Serializes an `Index` to TOML.
+    ///
+    /// If `multiline_tags` is true, each tag is written on its own
+    /// line, which keeps long tag lists readable and diff-friendly.
+    /// Otherwise, each tag list is written on a single line.
+    pub fn to_toml(&self, multiline_tags: bool) -> Result<String, Error> {
+        let mut text = String::new();
+        let mut serializer = toml::Serializer::pretty(&mut text);
+        serializer.pretty_array(multiline_tags);
+
+        self.serialize(&mut serializer)
+            .with_context(|_| "could not serialize notes")?;
+        Ok(text)
+    }
+
+    /// Serializes an `Index` to indented JSON.
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self).with_context(|_| "could not serialize notes")?)
+    }
+
+    /// Returns a reference to a Note with a given `name`
+    pub fn get(&self, name: &str) -> Option<&Note> {
+        self.notes.get(&Note::dummy(name))
+    }
+
+    /// Returns a reference to an `Index`'s notes
+    pub fn notes(&self) -> &BTreeSet<Note> {
+        &self.notes
+    }
+
+    /// Returns the names of the `Index`'s notes in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.notes.iter().map(|note| note.name())
+    }
+
+    /// Returns every `Note` for which `pred` returns `true`, in
+    /// alphabetical order by name.
+    ///
+    /// This is the general-purpose query that the more specific search
+    /// methods are built on, so predicates can be freely combined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use scrivener::notes::Index;
+    /// # use tempfile::NamedTempFile;
+    /// # let file = NamedTempFile::new().unwrap();
+    /// # let path = file.path().to_path_buf();
+    /// let mut index = Index::default();
+    /// index.add("groceries", &path, &Some(vec!["todo".to_string()]))?;
+    /// index.add("chores", &path, &Some(vec!["todo".to_string()]))?;
+    /// index.add("journal", &path, &None)?;
+    ///
+    /// // Notes tagged `todo` whose name contains an `r`.
+    /// let found = index.find_by(|note| {
+    ///     note.name().contains('r')
+    ///         && note
+    ///             .tags()
+    ///             .as_ref()
+    ///             .map_or(false, |tags| tags.iter().any(|tag| tag == "todo"))
+    /// });
+    ///
+    /// let names: Vec<&str> = found.iter().map(|note| note.name()).collect();
+    /// assert_eq!(names, ["chores", "groceries"]);
+    /// # Ok::<(), failure::Error>(())
+    /// ```
+    pub fn find_by<F: Fn(&Note) -> bool>(&self, pred: F) -> Vec<&Note> {
+        self.notes.iter().filter(|note| pred(note)).collect()
+    }
+
+    /// Creates an `Index` from the plaintext files in `dir`, naming
+    /// each `Note` after its file using `derive_name`.
+    ///
+    /// If `recursive` is true, subdirectories are scanned as well.
+    /// Files matched by `ignore` are left out. When two files would get
+    /// the same name, a number is added to the later one, as in
+    /// `todo-2`.
+    ///
+    /// # Errors
+    ///
+    /// - `dir` or one of its subdirectories cannot be read.
+    /// - A pattern or file in `ignore` is not valid.
+    pub fn from_directory(
+        dir: &Path,
+        recursive: bool,
+        strip_all: bool,
+        ignore: &ScanIgnore,
+    ) -> Result<Index, Error> {
+        Index::from_directory_with(dir, recursive, strip_all, ignore, |_, _| {})
+    }
+
+    /// Does the same as `from_directory`, calling `progress` with the
+    /// number of files added so far and the total after each one.
+    pub fn from_directory_with<F: FnMut(usize, usize)>(
+        dir: &Path,
+        recursive: bool,
+        strip_all: bool,
+        ignore: &ScanIgnore,
+        mut progress: F,
+    ) -> Result<Index, Error> {
+        let mut index = Index::default();
+
+        let paths = plaintext_files(dir, recursive, ignore)?;
+        for (done, path) in paths.iter().enumerate() {
+            let name = index.unique_name(&derive_name(path, strip_all)?);
+            index.add(&name, path, &None)?;
+            progress(done + 1, paths.len());
+        }
+
+        Ok(index)
+    }
+
+    /// Creates an `Index` from the metadata sidecars in `dir` and its
+    /// subdirectories.
+    ///
+    /// Each `Note` points to the file next to its sidecar, even if the
+    /// pair was moved after the sidecar was written. `Note`s whose
+    /// names are taken are renamed as in `from_directory`.
+    ///
+    /// # Errors
+    ///
+    /// - `dir` or a sidecar in it cannot be read.
+    /// - A sidecar does not describe a `Note`.
+    pub fn from_sidecars(dir: &Path) -> Result<Index, Error> {
+        let mut index = Index::default();
+
+        for sidecar in files_in(dir, true)?
+            .into_iter()
+            .filter(|path| is_sidecar(path))
+        {
+            let text = fs::read_to_string(&sidecar)
+                .with_context(|_| format!("could not read {}", sidecar.display()))?;
+            let mut note: Note = serde_json::from_str(&text)
+                .with_context(|_| format!("could not parse {}", sidecar.display()))?;
+
+            let path = sidecar.to_string_lossy();
+            let path = PathBuf::from(&path[..path.len() - SIDECAR_SUFFIX.len()]);
+            note.path = fs::canonicalize(&path).unwrap_or(path);
+            note.name = index.unique_name(&note.name);
+
+            index.notes.insert(note);
+            index.dirty = true;
+        }
+
+        Ok(index)
+    }
+
+    /// Adds the `Note`s from `other` to `self`, returning the names of
+    /// the `Note`s that were added.
+    ///
+    /// `Note`s whose files are already in `self` are skipped, and
+    /// `Note`s whose names are taken are renamed as in
+    /// `from_directory`.
+    pub fn merge(&mut self, other: Index) -> Vec<String> {
+        let mut added = Vec::new();
+
+        for mut note in other.notes {
+            if self.notes.iter().any(|existing| existing.path == note.path) {
+                continue;
+            }
+
+            note.name = self.unique_name(&note.name);
+            note.order = Some(self.next_order());
+            added.push(note.name.clone());
+            self.notes.insert(note);
+            self.dirty = true;
+        }
+
+        added
+    }
+
+    /// Returns `name` if no `Note` in `self` has it, and otherwise
+    /// `name` followed by the first number that makes it unique.
+    pub fn unique_name(&self, name: &str) -> String {
+        if !self.contains(name) {
+            return name.to_string();
+        }
+
+        (2..)
+            .map(|number| format!("{}-{}", name, number))
+            .find(|candidate| !self.contains(candidate))
+            .unwrap()
+    }
+
+    /// Returns every `Note` whose file links to the `Note` named `name`.
+    /// See `Note::links`.
+    ///
+    /// `Note`s whose files cannot be read are left out.
+    pub fn incoming_links(&self, name: &str) -> Vec<&Note> {
+        self.find_by(|note| {
+            note.links()
+                .is_ok_and(|links| links.iter().any(|link| link == name))
+        })
+    }
+
+    /// Computes statistics about the `Note`s in `self`, counting words
+    /// or graphemes depending on `mode`.
+    ///
+    /// Files that exist but cannot be read do not count towards
+    /// `total_words`.
+    pub fn stats(&self, mode: CountMode) -> IndexStats {
+        self.stats_with(mode, |_, _| {})
+    }
+
+    /// Does the same as `stats`, calling `progress` with the number of
+    /// `Note`s counted so far and the total after each one.
+    pub fn stats_with<F: FnMut(usize, usize)>(
+        &self,
+        mode: CountMode,
+        mut progress: F,
+    ) -> IndexStats {
+        let mut stats = IndexStats::default();
+
+        for (done, note) in self.notes.iter().enumerate() {
+            stats.total += 1;
+
+            match &note.tags {
+                Some(tags) if !tags.is_empty() => {
+                    stats.tagged += 1;
+                    for tag in tags {
+                        *stats.tag_counts.entry(tag.clone()).or_insert(0) += 1;
+                    }
+                }
+                _ => stats.untagged += 1,
+            }
+
+            if note.path.exists() {
+                stats.total_words += note.word_count(mode).unwrap_or(0);
+            } else {
+                stats.missing_files += 1;
+            }
+
+            progress(done + 1, self.notes.len());
+        }
+
+        stats
+    }
+
+    /// Groups the `Note`s in `self` by tag.
+    ///
+    /// A `Note` with several tags appears in each of their groups.
+    /// Untagged `Note`s are left out.
+    pub fn by_tag(&self) -> BTreeMap<&str, Vec<&Note>> {
+        let mut groups: BTreeMap<&str, Vec<&Note>> = BTreeMap::new();
+
+        for note in &self.notes {
+            for tag in note.tags.iter().flatten() {
+                let group = groups.entry(tag.as_str()).or_default();
+                if !group.contains(&note) {
+                    group.push(note);
+                }
+            }
+        }
+
+        groups
+    }
+
+    /// Returns groups of `Note`s whose files have identical contents.
+    ///
+    /// Each group has at least two `Note`s, in alphabetical order by
+    /// name, and the groups are ordered by their first `Note`. `Note`s
+    /// whose files cannot be read are skipped.
+    pub fn content_duplicates(&self) -> Vec<Vec<&Note>> {
+        let mut by_hash: BTreeMap<Vec<u8>, Vec<&Note>> = BTreeMap::new();
+
+        for note in &self.notes {
+            if let Ok(contents) = fs::read(&note.path) {
+                let hash = Sha256::digest(&contents).to_vec();
+                by_hash.entry(hash).or_default().push(note);
+            }
+        }
+
+        let mut groups: Vec<Vec<&Note>> = by_hash
+            .into_values()
+            .filter(|notes| notes.len() > 1)
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    /// Returns a new `Index` containing copies of the `Note`s for which
+    /// `pred` returns `true`.
+    pub fn subset<F: Fn(&Note) -> bool>(&self, pred: F) -> Index {
+        Index {
+            notes: self.find_by(pred).into_iter().cloned().collect(),
+            dirty: false,
+        }
+    }
+
+    /// Adds every `Note` in `delta` to `self`, replacing any `Note`
+    /// with the same name, and returns the names of the `Note`s that
+    /// were replaced.
+    pub fn apply(&mut self, delta: Index) -> Vec<String> {
+        let mut replaced = Vec::new();
+
+        for mut note in delta.notes {
+            // Replaced notes keep their place, and new ones go last.
+            note.order = match self.get(&note.name) {
+                Some(old) => old.order,
+                None => Some(self.next_order()),
+            };
+
+            if let Some(old) = self.notes.replace(note) {
+                replaced.push(old.name);
+            }
+            self.dirty = true;
+        }
+
+        replaced
+    }
+
+    /// Returns every `Note` whose name contains `query`, ignoring case.
+    pub fn search_by_name(&self, query: &str) -> Vec<&Note> {
+        let query = query.to_lowercase();
+        self.find_by(|note| note.name.to_lowercase().contains(&query))
+    }
+
+    /// Returns every `Note` that has a tag equal to `tag`, ignoring
+    /// case.
+    pub fn search_by_tag(&self, tag: &str) -> Vec<&Note> {
+        self.find_by(|note| note.has_tag(tag, false))
+    }
+
+    /// Creates an empty instance of Index.
+    #[allow(dead_code)]
+    pub(super) fn new() -> Index {
+        Index {
+            notes: BTreeSet::new(),
+            dirty: false,
+        }
+    }
+}
+
+/// Counts the words in `text` using Unicode word boundaries.
+///
+/// Punctuation is not counted, and text without spaces, such as
+/// Chinese or Japanese, is still split into words.
+pub fn count_words(text: &str) -> usize {
+    count(text, CountMode::Words)
+}
+
+/// Counts the words or graphemes in `text`, depending on `mode`.
+pub fn count(text: &str, mode: CountMode) -> usize {
+    match mode {
+        CountMode::Words => text.unicode_words().count(),
+        CountMode::Graphemes => text
+            .graphemes(true)
+            .filter(|grapheme| !grapheme.trim().is_empty())
+            .count(),
+    }
+}
+
+/// Used to leave out what a cached count counted when it is the
+/// default.
+fn counts_words(cell: &Cell<CountMode>) -> bool {
+    cell.get() == CountMode::Words
+}
+
+/// Used to avoid storing cached values that have not been computed.
+fn is_unset<T: Copy>(cell: &Cell<Option<T>>) -> bool {
+    cell.get().is_none()
+}
+
+/// Files to leave out when scanning a directory for notes.
+///
+/// Hidden files are always left out, as is anything matched by a
+/// `.gitignore` or `.scrivenerignore` file in the scanned directory or
+/// its parents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanIgnore {
+    /// More patterns to leave out, written as in `.gitignore`
+    pub globs: Vec<String>,
+
+    /// More files of `.gitignore`-style patterns
+    pub files: Vec<PathBuf>,
+}
+
+/// Returns the paths of the plaintext files in `dir`, sorted so that
+/// scanning the same directory twice gives the same result.
+///
+/// Metadata sidecars and files matched by `ignore` are left out. If
+/// `recursive` is true, subdirectories are scanned as well.
+fn plaintext_files(
+    dir: &Path,
+    recursive: bool,
+    ignore: &ScanIgnore,
+) -> Result<Vec<PathBuf>, Error> {
+    let read_error = || format!("Could not read directory {}.", dir.display());
+    fs::read_dir(dir).with_context(|_| read_error())?;
+
+    let mut globs = GitignoreBuilder::new(dir);
+    for glob in &ignore.globs {
+        globs
+            .add_line(None, glob)
+            .with_context(|_| format!("`{}` is not a valid ignore pattern.", glob))?;
+    }
+    let globs = globs.build()?;
+
+    let mut walker = WalkBuilder::new(dir);
+    walker
+        .max_depth(if recursive { None } else { Some(1) })
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+            !globs.matched(entry.path(), is_dir).is_ignore()
+        });
+    for file in &ignore.files {
+        if let Some(error) = walker.add_ignore(file) {
+            return Err(error)
+                .with_context(|_| format!("Could not read ignore file {}.", file.display()))?;
+        }
+    }
+
+    let mut files = Vec::new();
+    for entry in walker.build() {
+        let path = entry.with_context(|_| read_error())?.into_path();
+        if !path.is_dir() && !is_sidecar(&path) && is_plaintext(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the paths of the files in `dir`, sorted by path.
+///
+/// If `recursive` is true, subdirectories are searched as well.
+fn files_in(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, Error> {
+    let read_error = |_: &_| format!("Could not read directory {}.", dir.display());
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(read_error)? {
+        paths.push(entry.with_context(read_error)?.path());
+    }
+    paths.sort();
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            if recursive {
+                files.extend(files_in(&path, recursive)?);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns when the file at `path` was last modified, or `None` if it
+/// cannot be read.
+fn file_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns `true` if the file at `path` is a metadata sidecar written
+/// by `Note::write_sidecar`.
+fn is_sidecar(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(SIDECAR_SUFFIX))
+}
+
+/// Returns `true` if the start of the file at `path` is valid UTF-8
+/// without any null bytes, which rules out most binary files.
+fn is_plaintext(path: &Path) -> bool {
+    let mut start = Vec::new();
+    let read = fs::File::open(path).and_then(|file| file.take(8192).read_to_end(&mut start));
+
+    if read.is_err() || start.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(&start) {
+        Ok(_) => true,
+        // The last character may have been cut off.
+        Err(error) => error.error_len().is_none(),
+    }
+}
+
+/// Derives a `Note` name from the file name of `path`.
+///
+/// If `strip_all` is false, only the final extension is removed, so
+/// `notes.tar.gz` becomes `notes.tar`. If it is true, every extension
+/// is removed, so `notes.tar.gz` becomes `notes`. A leading dot, as in
+/// `.plan`, is never treated as an extension.
+///
+/// # Errors
+///
+/// - `path` has no file name or its file name is not valid UTF-8.
+pub fn derive_name(path: &Path, strip_all: bool) -> Result<String, Error> {
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(file_name) => file_name,
+        None => failure::bail!("Could not derive a note name from {}.", path.display()),
+    };
+
+    // Skip a leading dot so that hidden files keep their whole name.
+    let search_from = if file_name.starts_with('.') { 1 } else { 0 };
+    let dot = if strip_all {
+        file_name[search_from..].find('.')
+    } else {
+        file_name[search_from..].rfind('.')
+    };
+
+    match dot {
+        Some(dot) => Ok(file_name[..search_from + dot].to_string()),
+        None => Ok(file_name.to_string()),
+    }
+}
+
+/// Returns the names of the directories between `root` and the file at
+/// `path`, outermost first, so `root/work/acme/note.txt` gives `work`
+/// and `acme`.
+///
+/// If `depth` is given, only that many directories below `root` are
+/// used. Returns no tags if `path` is not under `root`.
+pub fn tags_from_path(path: &Path, root: &Path, depth: Option<usize>) -> Vec<String> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let (path, root) = (canonical(path), canonical(root));
+
+    let dirs = match path.parent().and_then(|dir| dir.strip_prefix(&root).ok()) {
+        Some(dirs) => dirs,
+        None => return Vec::new(),
+    };
+
+    dirs.iter()
+        .take(depth.unwrap_or(usize::MAX))
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Returns the name of the directory that the file at `path` is in, so
+/// `recipes/soup.txt` gives `recipes`.
+///
+/// Returns `None` if that directory is `root`, since every note there
+/// would get the same tag, or if it has no name, like `/`.
+pub fn parent_tag(path: &Path, root: Option<&Path>) -> Option<String> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let parent = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => canonical(Path::new(".")),
+        Some(parent) => canonical(parent),
+        None => return None,
+    };
+
+    if root.is_some_and(|root| canonical(root) == parent) {
+        return None;
+    }
+
+    parent
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Returns `true` if the file at `path` is in `root` or one of its
+/// subdirectories.
+///
+/// Both paths are made canonical first. If `path` does not exist yet,
+/// its directory is made canonical instead, so new files can be
+/// checked before they are created.
+pub fn path_under_root(path: &Path, root: &Path) -> bool {
+    let root = match fs::canonicalize(root) {
+        Ok(root) => root,
+        Err(_) => return false,
+    };
+
+    canonicalize_missing(path).is_ok_and(|path| path.starts_with(root))
+}
+
+/// Returns `path` made canonical, or, if it does not exist, its
+/// directory made canonical and joined with its file name.
+///
+/// # Errors
+///
+/// - Neither `path` nor its directory can be made canonical.
+fn canonicalize_missing(path: &Path) -> std::io::Result<PathBuf> {
+    fs::canonicalize(path).or_else(|error| match (path.parent(), path.file_name()) {
+        (Some(dir), Some(file_name)) => {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            fs::canonicalize(dir).map(|dir| dir.join(file_name))
+        }
+        _ => Err(error),
+    })
+}
+
+/// Removes the directories that held the file at `path` if they are
+/// now empty, from the innermost outward, and returns the ones removed.
+///
+/// Stops at the first directory that still has anything in it, and
+/// never removes `root` itself or anything outside it.
+///
+/// # Errors
+///
+/// - `root` or one of the directories cannot be read or removed.
+pub fn prune_empty_dirs(path: &Path, root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let root = fs::canonicalize(root)
+        .with_context(|_| format!("Could not read directory {}.", root.display()))?;
+
+    let mut pruned = Vec::new();
+    let mut dir = path.parent().and_then(|dir| fs::canonicalize(dir).ok());
+
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(&root) {
+            break;
+        }
+
+        let read_error = |_: &_| format!("Could not read directory {}.", current.display());
+        if fs::read_dir(&current)
+            .with_context(read_error)?
+            .next()
+            .is_some()
+        {
+            break;
+        }
+
+        fs::remove_dir(&current)
+            .with_context(|_| format!("Could not remove directory {}.", current.display()))?;
+
+        dir = current.parent().map(Path::to_path_buf);
+        pruned.push(current);
+    }
+
+    Ok(pruned)
+}
+
+/// Returns the targets of the `[[name]]` and `[[name|label]]` links in
+/// `text`, trimmed and without duplicates, in the order they first
+/// appear.
+fn wiki_links(text: &str) -> Vec<String> {
+    let regex = Regex::new(r"\[\[([^\[\]|]+)(?:\|[^\[\]]*)?\]\]").unwrap();
+
+    let mut links: Vec<String> = Vec::new();
+    for found in regex.captures_iter(text) {
+        let link = found[1].trim();
+        if !link.is_empty() && !links.iter().any(|known| known == link) {
+            links.push(link.to_string());
+        }
+    }
+
+    links
+}
+
+/// Checks that `name` matches the regular expression `pattern`.
+///
+/// # Errors
+///
+/// - `pattern` is not a valid regular expression.
+/// - `name` does not match `pattern`.
+pub fn validate_name(name: &str, pattern: &str) -> Result<(), Error> {
+    let regex = Regex::new(pattern)
+        .with_context(|_| format!("The name pattern `{}` is not valid.", pattern))?;
+
+    failure::ensure!(
+        regex.is_match(name),
+        "`{}` is not a valid note name. Names must match `{}`.",
+        name,
+        pattern
+    );
+
+    Ok(())
+}
+
+/// Rewrites `name` according to the policy `case`.
+///
+/// For `Kebab` and `Snake`, words are runs of letters and digits, so
+/// `My  Note (draft)` becomes `my-note-draft` or `my_note_draft`.
+pub fn normalize_name(name: &str, case: NameCase) -> String {
+    let separator = match case {
+        NameCase::Preserve => return name.to_string(),
+        NameCase::Lower => return name.to_lowercase(),
+        NameCase::Kebab => "-",
+        NameCase::Snake => "_",
+    };
+
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// (De)serializes an optional `SystemTime` as seconds since the Unix
+/// epoch, which is easier to read in scrivener.toml.
+///
+/// Times are kept to the microsecond, so that changes to a file within
+/// the same second are still noticed. Whole seconds are written as
+/// integers, as older versions did.
+mod timestamp {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const MICROS_PER_SEC: u64 = 1_000_000;
+
+    /// Seconds as stored, which older versions only wrote whole
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Secs {
+        Whole(u64),
+        Fractional(f64),
+    }
+
+    /// Returns `time` in whole microseconds since the Unix epoch, the
+    /// precision it is stored with.
+    pub fn micros(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_micros() as u64)
+    }
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time.map(micros) {
+            Some(micros) if micros % MICROS_PER_SEC == 0 => {
+                serializer.serialize_u64(micros / MICROS_PER_SEC)
+            }
+            Some(micros) => serializer.serialize_f64(micros as f64 / MICROS_PER_SEC as f64),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        let micros = Option::<Secs>::deserialize(deserializer)?.map(|secs| match secs {
+            Secs::Whole(secs) => secs * MICROS_PER_SEC,
+            Secs::Fractional(secs) => (secs * MICROS_PER_SEC as f64).round() as u64,
+        });
+        Ok(micros.map(|micros| UNIX_EPOCH + Duration::from_micros(micros)))
+    }
+}
+
+/// Reads the `Note`s of a stored `Index` one at a time, handing each to
+/// a callback instead of collecting them. See `Index::stream_path`.
+mod stream {
+    use failure::Error;
+    use serde::de::{
+        self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor,
+    };
+    use std::fmt;
+
+    use super::Note;
+
+    /// A whole stored `Index`, of which only the `notes` are read.
+    pub struct Notes<'a, F> {
+        /// Called with each `Note` in turn
+        pub visit: F,
+
+        /// Where the error that `visit` stopped reading with is put
+        pub stopped: &'a mut Option<Error>,
+    }
+
+    impl<'de, 'a, F> DeserializeSeed<'de> for Notes<'a, F>
+    where
+        F: FnMut(Note) -> Result<(), Error>,
+    {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_map(self)
+        }
+    }
+
+    impl<'de, 'a, F> Visitor<'de> for Notes<'a, F>
+    where
+        F: FnMut(Note) -> Result<(), Error>,
+    {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an index of notes")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+            while let Some(key) = map.next_key::<String>()? {
+                if key == "notes" {
+                    map.next_value_seed(NoteList(&mut self))?;
+                } else {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// The list of `Note`s in a stored `Index`.
+    struct NoteList<'b, 'a, F>(&'b mut Notes<'a, F>);
+
+    impl<'de, 'b, 'a, F> DeserializeSeed<'de> for NoteList<'b, 'a, F>
+    where
+        F: FnMut(Note) -> Result<(), Error>,
+    {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_seq(self)
+        }
+    }
+
+    impl<'de, 'b, 'a, F> Visitor<'de> for NoteList<'b, 'a, F>
+    where
+        F: FnMut(Note) -> Result<(), Error>,
+    {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of notes")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            while let Some(note) = seq.next_element::<Note>()? {
+                if let Err(error) = (self.0.visit)(note) {
+                    *self.0.stopped = Some(error);
+                    return Err(de::Error::custom("stopped reading notes"));
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Two `Index`es are equal if they hold `Note`s with the same stored
+/// fields: names, paths, tags, modification and creation times, and
+/// the order they were added in.
+///
+/// Cached word counts and whether anything has changed since loading
+/// are not compared, since they are only kept to save work.
+impl PartialEq for Index {
+    fn eq(&self, other: &Self) -> bool {
+        self.notes.len() == other.notes.len()
+            && self
+                .notes
+                .iter()
+                .zip(&other.notes)
+                .all(|(note, other)| note.same_record(other))
+    }
+}
+
+//TODO: Improve tests
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::scrivener::test_utils::tags;
+
+    // Currently broken when testing for Windows on Linux.
+    #[test]
+    fn create_note() {
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "test";
+        let path = file.path().to_path_buf();
+
+        let note = Note::new(name, &path, &None).unwrap();
+
+        let expected = Note {
+            name: name.to_string(),
+            path,
+            tags: None,
+            modified: note.modified,
+            ..Note::default()
+        };
+
+        assert_eq!(note, expected);
+        assert_eq!(note.path, expected.path);
+        assert_eq!(note.tags, expected.tags);
+    }
+
+    // Currently broken when testing for Windows on Linux.
+    #[test]
+    fn create_note_with_tags() {
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "test";
+        let path = file.path().to_path_buf();
+        let tags = Some(vec![
+            "one".to_string(),
+            "two".to_string(),
+            "three".to_string(),
+        ]);
+
+        let note = Note::new(name, &path, &tags).unwrap();
+
+        let expected = Note {
+            name: name.to_string(),
+            path,
+            tags,
+            modified: note.modified,
+            ..Note::default()
+        };
+
+        assert_eq!(note, expected);
+        assert_eq!(note.path, expected.path);
+        assert_eq!(note.tags, expected.tags);
+    }
+
+    #[test]
+    fn add_note_to_index() {
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Add";
+        let path = file.path().to_path_buf();
+        let tags = Some(vec![String::from("one"), String::from("two")]);
+
+        let mut index = Index {
+            notes: BTreeSet::new(),
+            dirty: false,
+        };
+
+        let added = index.add(name, &path, &tags).unwrap();
+        assert_eq!(added.name(), name);
+        assert_eq!(added.tags(), &tags);
+
+        // The two notes are added at different times, so only the path
+        // is compared.
+        let mut expected = Index::new();
+        let expected = expected.add(name, &path, &tags).unwrap();
+
+        assert_eq!(index.get(name).unwrap().path(), expected.path());
+        assert!(index.add(name, &path, &None).is_err());
+    }
+
+    #[test]
+    fn remove_note_from_index() {
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Remove";
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add(name, &path, &None).unwrap();
+
+        let removed = index.remove(name).unwrap();
+        assert_eq!(removed.name(), name);
+        assert_eq!(removed.path(), &fs::canonicalize(&path).unwrap());
+        assert!(index.remove(name).is_none());
+
+        assert_eq!(index, Index::new());
+    }
+
+    #[test]
+    fn remove_path_returns_notes() {
+        let file = NamedTempFile::new().unwrap();
+        let other = NamedTempFile::new().unwrap();
+
+        let mut index = Index::new();
+        index.add("a", &file.path().to_path_buf(), &None).unwrap();
+        index.add("b", &file.path().to_path_buf(), &None).unwrap();
+        index.add("c", &other.path().to_path_buf(), &None).unwrap();
+
+        let path = fs::canonicalize(file.path()).unwrap();
+        let removed: Vec<String> = index
+            .remove_path(&path)
+            .into_iter()
+            .map(|note| note.name)
+            .collect();
+
+        assert_eq!(removed, ["a", "b"]);
+        assert_eq!(index.notes().len(), 1);
+    }
+
+    #[test]
+    fn move_path_moves_every_sharer() {
+        let file = NamedTempFile::new().unwrap();
+        let other = NamedTempFile::new().unwrap();
+        let to = NamedTempFile::new().unwrap();
+
+        let mut index = Index::new();
+        index.add("a", &file.path().to_path_buf(), &None).unwrap();
+        index.add("b", &file.path().to_path_buf(), &None).unwrap();
+        index.add("c", &other.path().to_path_buf(), &None).unwrap();
+
+        let from = fs::canonicalize(file.path()).unwrap();
+        assert_eq!(index.move_path(&from, to.path()).unwrap(), ["a", "b"]);
+
+        let to = fs::canonicalize(to.path()).unwrap();
+        assert_eq!(index.get("a").unwrap().path(), &to);
+        assert_eq!(index.get("b").unwrap().path(), &to);
+        assert_ne!(index.get("c").unwrap().path(), &to);
+        assert!(index.move_path(&from, &from.join("missing")).is_err());
+    }
+
+    #[test]
+    fn prune_removes_missing_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kept = dir.path().join("kept.txt");
+        fs::write(&kept, "").unwrap();
+
+        let mut index = Index::new();
+        index.add("kept", &kept, &None).unwrap();
+        for name in &["b", "a"] {
+            let path = dir.path().join(name);
+            fs::write(&path, "").unwrap();
+            index.add(name, &path, &None).unwrap();
+            fs::remove_file(&path).unwrap();
+        }
+
+        assert_eq!(index.prune(), ["a", "b"]);
+        assert_eq!(index.find_by(|_| true).len(), 1);
+        assert!(index.get("kept").is_some());
+        assert!(index.prune().is_empty());
+    }
+
+    #[test]
+    fn index_contains_note() {
+        let file = NamedTempFile::new().unwrap();
+
+        let name = "Test Contains";
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add(name, &path, &None).unwrap();
+
+        assert!(index.contains(name));
+    }
+
+    #[test]
+    fn touch_note() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add("old", &path, &None).unwrap();
+        index.add("new", &path, &None).unwrap();
+
+        let long_ago = SystemTime::UNIX_EPOCH;
+        for name in &["old", "new"] {
+            let mut note = index.notes.take(&Note::dummy(name)).unwrap();
+            note.modified = Some(long_ago);
+            index.notes.insert(note);
+        }
+
+        assert_eq!(index.touch("old").unwrap().name(), "old");
+        assert!(index.touch("missing").is_none());
+
+        let old = index.get("old").unwrap().modified().unwrap();
+        let new = index.get("new").unwrap().modified().unwrap();
+        assert!(old > long_ago);
+        assert!(old > new);
+    }
+
+    #[test]
+    fn modified_round_trip() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add("Timed", &path, &None).unwrap();
+
+        let text = index.to_toml(true).unwrap();
+        let loaded: Index = toml::from_str(&text).unwrap();
+
+        let secs = |time: Option<SystemTime>| {
+            time.unwrap()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        let (note, loaded) = (index.get("Timed").unwrap(), loaded.get("Timed").unwrap());
+        assert_eq!(secs(loaded.modified()), secs(note.modified()));
+        assert_eq!(secs(loaded.created()), secs(note.created()));
+
+        let legacy: Index = toml::from_str("[[notes]]\nname = 'a'\npath = '/a'\n").unwrap();
+        assert_eq!(legacy.get("a").unwrap().modified(), None);
+        assert_eq!(legacy.get("a").unwrap().created(), None);
+    }
+
+    #[test]
+    fn exact_and_partial_tags() {
+        let file = NamedTempFile::new().unwrap();
+        let tags = Some(vec!["project/ACME".to_string()]);
+        let note = Note::new("note", &file.path().to_path_buf(), &tags).unwrap();
+
+        assert!(note.has_tag("Project/acme", false));
+        assert!(!note.has_tag("acme", false));
+        assert!(note.has_tag("acme", true));
+        assert!(note.has_tag("project/", true));
+        assert!(!note.has_tag("other", true));
+    }
+
+    #[test]
+    fn dirty_after_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("note.txt");
+        fs::write(&path, "one two").unwrap();
+        let index_path = dir.path().join("scrivener.toml");
+
+        let mut index = Index::new();
+        assert!(!index.is_dirty());
+        index.add("Note", &path, &None).unwrap();
+        assert!(index.is_dirty());
+        index.store_path(&index_path, &Config::default()).unwrap();
+
+        let mut index = Index::load_path(&index_path).unwrap();
+        assert!(!index.is_dirty());
+        assert!(index.remove("Missing").is_none());
+        assert!(index.touch("Missing").is_none());
+        assert!(index
+            .remove_path(&dir.path().join("missing.txt"))
+            .is_empty());
+        assert!(!index.is_dirty());
+
+        // Caching a word count also needs storing.
+        index
+            .get("Note")
+            .unwrap()
+            .word_count(CountMode::Words)
+            .unwrap();
+        assert!(index.is_dirty());
+
+        let mut index = Index::load_path(&index_path).unwrap();
+        assert!(index.touch("Note").is_some());
+        assert!(index.is_dirty());
+    }
+
+    #[test]
+    fn duplicate_contents() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+
+        for (name, contents) in &[("a", "same"), ("b", "other"), ("c", "same"), ("d", "other")] {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            index.add(name, &path, &None).unwrap();
+        }
+
+        let path = dir.path().join("e");
+        fs::write(&path, "unique").unwrap();
+        index.add("e", &path, &None).unwrap();
+
+        let missing = dir.path().join("missing");
+        fs::write(&missing, "same").unwrap();
+        index.add("missing", &missing, &None).unwrap();
+        fs::remove_file(&missing).unwrap();
+
+        let groups: Vec<Vec<&str>> = index
+            .content_duplicates()
+            .iter()
+            .map(|group| names(group))
+            .collect();
+        assert_eq!(groups, [vec!["a", "c"], vec!["b", "d"]]);
+    }
+
+    #[test]
+    fn absorb_merges_tags() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add("keep", &path, &tags(&["a", "b"])).unwrap();
+        index.add("other", &path, &tags(&["b", "c"])).unwrap();
+        index.add("untagged", &path, &None).unwrap();
+
+        assert!(index.absorb("keep", "keep").is_none());
+        assert!(index.absorb("keep", "nonexistent").is_none());
+        assert_eq!(index.absorb("keep", "other").unwrap().name(), "other");
+        assert!(!index.contains("other"));
+        assert_eq!(index.get("keep").unwrap().tags(), &tags(&["a", "b", "c"]));
+
+        assert!(index.absorb("untagged", "keep").is_some());
+        assert_eq!(
+            index.get("untagged").unwrap().tags(),
+            &tags(&["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn sidecar_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let mut index = Index::new();
+
+        for (name, file) in &[("a", "a.txt"), ("b", "sub/b.txt")] {
+            let path = dir.path().join(file);
+            fs::write(&path, "text").unwrap();
+            index
+                .add(name, &path, &Some(vec![name.to_string()]))
+                .unwrap();
+        }
+
+        for note in index.notes() {
+            let sidecar = note.write_sidecar().unwrap();
+            assert!(sidecar.to_str().unwrap().ends_with(".txt.scrivener.json"));
+        }
+
+        // Sidecars are not notes themselves.
+        assert_eq!(
+            plaintext_files(dir.path(), true, &ScanIgnore::default())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let secs = |note: &Note| {
+            let time = note.modified().unwrap();
+            time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        };
+
+        let rebuilt = Index::from_sidecars(dir.path()).unwrap();
+        assert_eq!(rebuilt, index);
+        for (a, b) in rebuilt.notes().iter().zip(index.notes()) {
+            assert_eq!(a.path(), b.path());
+            assert_eq!(a.tags(), b.tags());
+            assert_eq!(secs(a), secs(b));
+        }
+    }
+
+    #[test]
+    fn clean_legacy_tags() {
+        let mut index: Index = toml::from_str(
+            r#"
+            [[notes]]
+            name = "clean"
+            path = "/clean"
+            tags = ["a", "b"]
+
+            [[notes]]
+            name = "duplicates"
+            path = "/duplicates"
+            tags = ["Work", "a", "work", "a"]
+
+            [[notes]]
+            name = "empty"
+            path = "/empty"
+            tags = ["", "  "]
+
+            [[notes]]
+            name = "none"
+            path = "/none"
+            tags = []
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(index.clean_tags(), 3);
+        assert!(index.is_dirty());
+
+        let tags = |name| index.get(name).unwrap().tags().clone();
+        assert_eq!(tags("clean"), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(
+            tags("duplicates"),
+            Some(vec!["Work".to_string(), "a".to_string()])
+        );
+        assert_eq!(tags("empty"), None);
+        assert_eq!(tags("none"), None);
+
+        assert_eq!(index.clean_tags(), 0);
+    }
+
+    #[test]
+    fn tags_from_paths() {
+        let root = Path::new("/notes");
+        let tags = |path: &str, depth| tags_from_path(Path::new(path), root, depth);
+
+        assert!(tags("/notes/note.txt", None).is_empty());
+        assert_eq!(tags("/notes/work/note.txt", None), ["work"]);
+        assert_eq!(
+            tags("/notes/work/acme/2020/note.txt", None),
+            ["work", "acme", "2020"]
+        );
+        assert_eq!(
+            tags("/notes/work/acme/2020/note.txt", Some(2)),
+            ["work", "acme"]
+        );
+        assert!(tags("/notes/work/note.txt", Some(0)).is_empty());
+        assert!(tags("/elsewhere/work/note.txt", None).is_empty());
+    }
+
+    #[test]
+    fn parent_tags() {
+        let root = Path::new("/notes");
+        let tag = |path: &str, root| parent_tag(Path::new(path), root);
+
+        assert_eq!(tag("/notes/recipes/soup.txt", None), Some("recipes".into()));
+        assert_eq!(
+            tag("/notes/recipes/soup.txt", Some(root)),
+            Some("recipes".into())
+        );
+        assert_eq!(tag("/notes/a/b/soup.txt", Some(root)), Some("b".into()));
+        assert_eq!(tag("/notes/soup.txt", None), Some("notes".into()));
+        assert_eq!(tag("/notes/soup.txt", Some(root)), None);
+        assert_eq!(tag("/soup.txt", None), None);
+    }
+
+    #[test]
+    fn paths_under_root() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("notes");
+        fs::create_dir_all(root.join("work")).unwrap();
+        fs::write(root.join("work/note.txt"), "").unwrap();
+        fs::write(dir.path().join("stray.txt"), "").unwrap();
+
+        assert!(path_under_root(&root.join("work/note.txt"), &root));
+        assert!(path_under_root(&root.join("work/new.txt"), &root));
+        assert!(path_under_root(&root.join("work/../work/note.txt"), &root));
+        assert!(!path_under_root(&dir.path().join("stray.txt"), &root));
+        assert!(!path_under_root(&root.join("../stray.txt"), &root));
+        assert!(!path_under_root(&root.join("missing/new.txt"), &root));
+    }
+
+    #[test]
+    fn prune_nested_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("notes");
+        let deep = root.join("work/acme/2020");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(root.join("work/other.txt"), "").unwrap();
+
+        let pruned = prune_empty_dirs(&deep.join("gone.txt"), &root).unwrap();
+        assert_eq!(pruned.len(), 2);
+        assert!(!root.join("work/acme").exists());
+        assert!(root.join("work/other.txt").exists());
+
+        // The root itself is never removed.
+        fs::remove_file(root.join("work/other.txt")).unwrap();
+        prune_empty_dirs(&root.join("work/gone.txt"), &root).unwrap();
+        assert!(!root.join("work").exists());
+        assert!(root.exists());
+
+        // Nor is anything outside it.
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        assert!(prune_empty_dirs(&outside.join("gone.txt"), &root)
+            .unwrap()
+            .is_empty());
+        assert!(outside.exists());
+    }
+
+    #[test]
+    fn links_between_notes() {
+        assert_eq!(
+            wiki_links("See [[Plan]], [[ Ideas | my ideas]] and [[Plan]]. [[]] [not]"),
+            ["Plan", "Ideas"]
+        );
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+        for (name, text) in &[("a", "[[b]] [[c]]"), ("b", "[[a]]"), ("c", "")] {
+            let path = dir.path().join(name);
+            fs::write(&path, text).unwrap();
+            index.add(name, &path, &None).unwrap();
+        }
+
+        assert_eq!(index.get("a").unwrap().links().unwrap(), ["b", "c"]);
+        assert_eq!(names(&index.incoming_links("b")), ["a"]);
+        assert_eq!(names(&index.incoming_links("a")), ["b"]);
+
+        fs::remove_file(dir.path().join("b")).unwrap();
+        assert!(index.get("b").unwrap().links().is_err());
+        assert!(index.incoming_links("a").is_empty());
+    }
+
+    #[test]
+    fn name_validation() {
+        let default = config::DEFAULT_NAME_REGEX;
+        for name in &[
+            "Note",
+            "My Note (draft)",
+            "ünïcödé",
+            "a-b",
+            &"x".repeat(128),
+        ] {
+            assert!(validate_name(name, default).is_ok(), "{}", name);
+        }
+        for name in &["", "a/b", "-flag", "back\\slash", &"x".repeat(129)] {
+            assert!(validate_name(name, default).is_err(), "{}", name);
+        }
+
+        let kebab = "^[a-z0-9]+(-[a-z0-9]+)*$";
+        assert!(validate_name("my-note-2", kebab).is_ok());
+        assert!(validate_name("My Note", kebab).is_err());
+
+        let error = validate_name("Note", "(").unwrap_err();
+        assert!(error.to_string().contains("not valid"));
+    }
+
+    #[test]
+    fn apply_meta_updates() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        for name in &["a", "b", "c"] {
+            index.add(name, &path, &tags(&["old"])).unwrap();
+        }
+        index.dirty = false;
+
+        let updates: BTreeMap<String, MetaUpdate> = serde_json::from_str(
+            r#"{"a": {"tags": ["new"]}, "b": {"tags": []}, "c": {"add_tags": ["old"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(index.apply_meta(&updates).unwrap(), ["a", "b"]);
+        assert_eq!(index.get("a").unwrap().tags(), &tags(&["new"]));
+        assert_eq!(index.get("b").unwrap().tags(), &None);
+        assert_eq!(index.get("c").unwrap().tags(), &tags(&["old"]));
+        assert!(index.is_dirty());
+
+        let updates: BTreeMap<String, MetaUpdate> =
+            serde_json::from_str(r#"{"a": {"tags": []}, "zzz": {"tags": []}}"#).unwrap();
+        let error = index.apply_meta(&updates).unwrap_err();
+        assert!(error.to_string().contains("zzz"));
+        assert_eq!(index.get("a").unwrap().tags(), &tags(&["new"]));
+
+        let unknown = r#"{"a": {"description": "text"}}"#;
+        assert!(serde_json::from_str::<BTreeMap<String, MetaUpdate>>(unknown).is_err());
+    }
+
+    #[test]
+    fn changed_and_refreshed() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index.add("Note", &path, &None).unwrap();
+        let note = index.get("Note").unwrap();
+        assert!(!note.changed_on_disk());
+
+        let later = note.modified().unwrap() + std::time::Duration::from_secs(60);
+        fs::write(&path, "one two three").unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(later)).unwrap();
+        assert!(index.get("Note").unwrap().changed_on_disk());
+
+        index.dirty = false;
+        let note = index.refresh("Note", CountMode::Words).unwrap();
+        assert!(!note.changed_on_disk());
+        assert_eq!(note.words.get(), Some(3));
+        assert!(index.is_dirty());
+        assert!(index.refresh("Missing", CountMode::Words).is_none());
+
+        // A change within the same second is still noticed, even after
+        // the index is stored and loaded again.
+        let whole = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&path, whole).unwrap();
+        index.refresh("Note", CountMode::Words);
+        let mut index: Index = toml::from_str(&index.to_toml(true).unwrap()).unwrap();
+        assert!(!index.get("Note").unwrap().changed_on_disk());
+
+        let partial = filetime::FileTime::from_unix_time(1_000_000, 250_000_000);
+        fs::write(&path, "one two three four").unwrap();
+        filetime::set_file_mtime(&path, partial).unwrap();
+        let note = index.get("Note").unwrap();
+        assert!(note.changed_on_disk());
+        assert_eq!(note.word_count(CountMode::Words).unwrap(), 4);
+
+        index.refresh("Note", CountMode::Words);
+        let index: Index = toml::from_str(&index.to_toml(true).unwrap()).unwrap();
+        assert!(!index.get("Note").unwrap().changed_on_disk());
+    }
+
+    #[test]
+    fn insertion_order() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        for name in &["c", "a", "b"] {
+            index.add(name, &path, &None).unwrap();
+        }
+        let order = |index: &Index, name| index.get(name).unwrap().order();
+        assert_eq!(order(&index, "c"), Some(1));
+        assert_eq!(order(&index, "b"), Some(3));
+
+        index.remove("b");
+        index.add("d", &path, &None).unwrap();
+        assert_eq!(order(&index, "d"), Some(3));
+
+        let mut delta = Index::new();
+        delta.add("e", &path, &None).unwrap();
+        delta.add("c", &path, &None).unwrap();
+        index.apply(delta);
+        assert_eq!(order(&index, "c"), Some(1));
+        assert_eq!(order(&index, "e"), Some(4));
+
+        let loaded: Index = toml::from_str(&index.to_toml(true).unwrap()).unwrap();
+        assert_eq!(order(&loaded, "e"), Some(4));
+    }
+
+    #[test]
+    fn rename_notes() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index
+            .add("b", &path, &Some(vec!["tag".to_string()]))
+            .unwrap();
+        index.add("c", &path, &None).unwrap();
+        index.dirty = false;
+
+        let renamed = index.rename("b", "z").unwrap();
+        assert_eq!(renamed.tags(), &Some(vec!["tag".to_string()]));
+        assert!(index.is_dirty());
+        assert!(!index.contains("b"));
+        assert_eq!(index.names().collect::<Vec<_>>(), ["c", "z"]);
+
+        assert!(index.rename("missing", "y").is_err());
+        assert!(index.rename("c", "z").is_err());
+        assert!(index.contains("c"));
+    }
+
+    #[test]
+    fn rename_tags() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index
+            .add("both", &path, &tags(&["old", "x", "new"]))
+            .unwrap();
+        index.add("old", &path, &tags(&["x", "old"])).unwrap();
+        index.add("other", &path, &tags(&["x"])).unwrap();
+
+        assert_eq!(
+            index.rename_tag("old", "new"),
+            [
+                ("both".to_string(), TagRename::Merged),
+                ("old".to_string(), TagRename::Renamed)
+            ]
+        );
+        assert_eq!(index.get("both").unwrap().tags(), &tags(&["x", "new"]));
+        assert_eq!(index.get("old").unwrap().tags(), &tags(&["x", "new"]));
+        assert_eq!(index.get("other").unwrap().tags(), &tags(&["x"]));
+        assert!(index.rename_tag("old", "new").is_empty());
+
+        assert!(index.rename_tag("new", "new").is_empty());
+        assert_eq!(index.get("both").unwrap().tags(), &tags(&["x", "new"]));
+    }
+
+    #[test]
+    fn add_tags_to_note() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        let mut index = Index::new();
+        index
+            .add("a", &path, &Some(vec!["one".to_string()]))
+            .unwrap();
+        index.store_path(&path, &Config::default()).unwrap();
+        let mut index = Index::load_path(&path).unwrap();
+
+        let new_tags = ["one".to_string(), "two".to_string()];
+        let note = index.add_tags("a", &new_tags).unwrap();
+        assert_eq!(note.tags(), &Some(new_tags.to_vec()));
+        assert!(index.is_dirty());
+        assert!(index.add_tags("missing", &new_tags).is_none());
+
+        index.dirty = false;
+        let repeated = [" One ".to_string(), "TWO".to_string(), " ".to_string()];
+        let note = index.add_tags("a", &repeated).unwrap();
+        assert_eq!(note.tags(), &Some(new_tags.to_vec()));
+        assert!(!index.is_dirty());
+
+        let note = index.add_tags("a", &[" three ".to_string()]).unwrap();
+        assert_eq!(note.tags().as_ref().unwrap().last().unwrap(), "three");
+    }
+
+    #[test]
+    fn remove_tags_from_note() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        let mut index = Index::new();
+        index.add("a", &path, &Some(tags(&["one", "two"]))).unwrap();
+        index.dirty = false;
+
+        let note = index.remove_tags("a", &tags(&["three"])).unwrap();
+        assert_eq!(note.tags(), &Some(tags(&["one", "two"])));
+        assert!(!index.is_dirty());
+
+        let note = index.remove_tags("a", &tags(&[" ONE "])).unwrap();
+        assert_eq!(note.tags(), &Some(tags(&["two"])));
+        assert!(index.is_dirty());
+
+        let note = index.remove_tags("a", &tags(&["two"])).unwrap();
+        assert_eq!(note.tags(), &None);
+        assert!(index.remove_tags("missing", &tags(&["one"])).is_none());
+    }
+
+    #[test]
+    fn name_cases() {
+        let name = "My  Note (Draft_2)";
+
+        assert_eq!(normalize_name(name, NameCase::Preserve), name);
+        assert_eq!(normalize_name(name, NameCase::Lower), "my  note (draft_2)");
+        assert_eq!(normalize_name(name, NameCase::Kebab), "my-note-draft-2");
+        assert_eq!(normalize_name(name, NameCase::Snake), "my_note_draft_2");
+        assert_eq!(
+            normalize_name("Ünïcödé Ñame", NameCase::Kebab),
+            "ünïcödé-ñame"
+        );
+    }
+
+    #[test]
+    fn toml_and_json_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("note.txt");
+        fs::write(&file, "one two").unwrap();
+        let tags = Some(vec!["a".to_string(), "b".to_string()]);
+
+        let mut index = Index::new();
+        index.add("Tagged", &file, &tags).unwrap();
+        index.add("Plain", &file, &None).unwrap();
+        index
+            .get("Plain")
+            .unwrap()
+            .word_count(CountMode::Words)
+            .unwrap();
+
+        let config = Config::default();
+        let toml_path = dir.path().join("scrivener.toml");
+        let json_path = dir.path().join("scrivener.json");
+        index.store_path(&toml_path, &config).unwrap();
+        index.store_path(&json_path, &config).unwrap();
+
+        assert!(fs::read_to_string(&json_path).unwrap().starts_with('{'));
+
+        let from_toml = Index::load_path(&toml_path).unwrap();
+        let from_json = Index::load_path(&json_path).unwrap();
+        assert_eq!(from_toml, from_json);
+
+        for (a, b) in from_toml.notes().iter().zip(from_json.notes()) {
+            assert_eq!(a.path(), b.path());
+            assert_eq!(a.tags(), b.tags());
+            assert_eq!(a.modified(), b.modified());
+            assert_eq!(a.words.get(), b.words.get());
+        }
+
+        // Without a recognized extension, JSON is detected by content.
+        let sniffed = dir.path().join("index");
+        fs::copy(&json_path, &sniffed).unwrap();
+        assert_eq!(Index::load_path(&sniffed).unwrap(), from_json);
+    }
+
+    #[test]
+    fn streamed_names_match_full_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("note.txt");
+        fs::write(&file, "words").unwrap();
+
+        let mut index = Index::new();
+        for i in 0..5000 {
+            let tags = Some(vec![format!("tag{}", i % 7)]);
+            index.add(&format!("Note {}", i), &file, &tags).unwrap();
+        }
+
+        let config = Config::default();
+        let sniffed = dir.path().join("index");
+        for path in &[
+            dir.path().join("scrivener.toml"),
+            dir.path().join("scrivener.json"),
+        ] {
+            index.store_path(path, &config).unwrap();
+            let loaded: Vec<String> = Index::load_path(path)
+                .unwrap()
+                .names()
+                .map(String::from)
+                .collect();
+
+            let mut streamed = Vec::new();
+            Index::stream_path(path, |note| {
+                streamed.push(note.name().to_string());
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(loaded.len(), 5000);
+            assert_eq!(streamed, loaded);
+        }
+
+        // An error from the callback stops reading and is returned as
+        // it is.
+        fs::copy(dir.path().join("scrivener.json"), &sniffed).unwrap();
+        let mut seen = 0;
+        let error = Index::stream_path(&sniffed, |_| {
+            seen += 1;
+            failure::ensure!(seen < 3, "enough");
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "enough");
+        assert_eq!(seen, 3);
+
+        let missing = dir.path().join("missing.json");
+        Index::stream_path(&missing, |_| panic!("nothing to visit")).unwrap();
+    }
+
+    #[test]
+    fn unicode_word_counts() {
+        let text = "Hello, world! 你好世界 naïve cafe\u{301} — done.";
+
+        assert_eq!(text.split_whitespace().count(), 7);
+        assert_eq!(count_words(text), 9);
+        assert_eq!(count(text, CountMode::Graphemes), 31);
+
+        assert_eq!(count_words("日本語のテキスト"), 5);
+        assert_eq!(count_words(""), 0);
+        assert_eq!(count("  \n\t", CountMode::Graphemes), 0);
+    }
+
+    #[test]
+    fn word_count_is_cached() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        fs::write(&path, "one two three").unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+
+        let note = Note::new("Counted", &path, &None).unwrap();
+        assert!(!note.cache_updated());
+        assert_eq!(note.word_count(CountMode::Words).unwrap(), 3);
+        assert!(note.cache_updated());
+
+        // Same modification time, so the cached count is used.
+        fs::write(&path, "one two three four").unwrap();
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        assert_eq!(note.word_count(CountMode::Words).unwrap(), 3);
+
+        // Newer modification time, so the words are counted again.
+        let later = filetime::FileTime::from_unix_time(1_000_060, 0);
+        filetime::set_file_mtime(&path, later).unwrap();
+        assert_eq!(note.word_count(CountMode::Words).unwrap(), 4);
+
+        // A count of something else is not reused.
+        assert_eq!(note.word_count(CountMode::Graphemes).unwrap(), 15);
+        assert_eq!(note.word_count(CountMode::Words).unwrap(), 4);
+    }
+
+    #[test]
+    fn word_count_survives_round_trip() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        fs::write(&path, "one two").unwrap();
+
+        let mut index = Index::new();
+        index.add("Counted", &path, &None).unwrap();
+        index
+            .get("Counted")
+            .unwrap()
+            .word_count(CountMode::Words)
+            .unwrap();
+
+        let loaded: Index = toml::from_str(&index.to_toml(true).unwrap()).unwrap();
+        let note = loaded.get("Counted").unwrap();
+        assert_eq!(note.words.get(), Some(2));
+        assert!(!note.cache_updated());
+    }
+
+    #[test]
+    fn equality_ignores_caches() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        fs::write(&path, "one two").unwrap();
+
+        let mut index = Index::new();
+        index.add("Counted", &path, &None).unwrap();
+        let loaded: Index = toml::from_str(&index.to_toml(true).unwrap()).unwrap();
+
+        index
+            .get("Counted")
+            .unwrap()
+            .word_count(CountMode::Words)
+            .unwrap();
+        assert!(index.is_dirty());
+        assert!(!loaded.is_dirty());
+        assert_eq!(index, loaded);
+
+        let mut other = Index::new();
+        other
+            .add("Counted", &path, &Some(vec!["tag".to_string()]))
+            .unwrap();
+        assert_ne!(index, other);
+
+        // Stored times are compared too.
+        let whole = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&path, whole).unwrap();
+        index.refresh("Counted", CountMode::Words);
+        assert_ne!(index, loaded);
+    }
+
+    #[test]
+    fn many_tags_round_trip() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags: Vec<String> = (0..50).map(|i| format!("tag-{}", i)).collect();
+
+        let mut index = Index::new();
+        index.add("Tagged", &path, &Some(tags.clone())).unwrap();
+
+        let text = index.to_toml(true).unwrap();
+        assert!(text.lines().count() > tags.len());
+        assert!(text.lines().any(|line| line.trim() == "'tag-49',"));
+
+        let loaded: Index = toml::from_str(&text).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.get("Tagged").unwrap().tags(), &Some(tags));
+    }
+
+    #[test]
+    fn single_line_tags() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let tags = Some(vec!["one".to_string(), "two".to_string()]);
+
+        let mut index = Index::new();
+        index.add("Tagged", &path, &tags).unwrap();
+
+        let text = index.to_toml(false).unwrap();
+        assert!(text.contains("tags = ['one', 'two']"));
+        assert_eq!(
+            toml::from_str::<Index>(&text)
+                .unwrap()
+                .get("Tagged")
+                .unwrap()
+                .tags(),
+            &tags
+        );
+    }
+
+    #[test]
+    fn group_by_tag() {
+        let file = NamedTempFile::new().unwrap();
+        let index = query_fixture(&file);
+
+        let groups = index.by_tag();
+        let keys: Vec<&str> = groups.keys().copied().collect();
+
+        assert_eq!(keys, ["Todo", "daily", "work"]);
+        assert_eq!(names(&groups["work"]), ["Work Log"]);
+        assert_eq!(names(&groups["Todo"]), ["Groceries"]);
+    }
+
+    #[test]
+    fn derive_name_final_extension() {
+        let name = |path| derive_name(Path::new(path), false).unwrap();
+
+        assert_eq!(name("/notes/todo.txt"), "todo");
+        assert_eq!(name("/notes/notes.tar.gz"), "notes.tar");
+        assert_eq!(name("/notes/report.md.bak"), "report.md");
+        assert_eq!(name("/notes/README"), "README");
+        assert_eq!(name("/notes/.plan"), ".plan");
+    }
+
+    #[test]
+    fn derive_name_all_extensions() {
+        let name = |path| derive_name(Path::new(path), true).unwrap();
+
+        assert_eq!(name("/notes/todo.txt"), "todo");
+        assert_eq!(name("/notes/notes.tar.gz"), "notes");
+        assert_eq!(name("/notes/report.md.bak"), "report");
+        assert_eq!(name("/notes/README"), "README");
+        assert_eq!(name("/notes/.plan.txt"), ".plan");
+    }
+
+    #[test]
+    fn derive_name_without_file_name() {
+        assert!(derive_name(Path::new("/"), false).is_err());
+        assert!(derive_name(Path::new("/notes/.."), true).is_err());
+    }
+
+    #[test]
+    fn index_stats() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, text).unwrap();
+            path
+        };
+
+        let mut index = Index::new();
+        index
+            .add("a", &file("a.txt", "one two"), &tags(&["x", "y"]))
+            .unwrap();
+        index
+            .add("b", &file("b.txt", "three"), &tags(&["x"]))
+            .unwrap();
+        let gone = file("c.txt", "four five six");
+        index.add("c", &gone, &None).unwrap();
+        fs::remove_file(gone).unwrap();
+
+        let mut tag_counts = BTreeMap::new();
+        tag_counts.insert("x".to_string(), 2);
+        tag_counts.insert("y".to_string(), 1);
+
+        assert_eq!(
+            index.stats(CountMode::Words),
+            IndexStats {
+                total: 3,
+                tagged: 2,
+                untagged: 1,
+                tag_counts,
+                total_words: 3,
+                missing_files: 1,
+            }
+        );
+        assert_eq!(Index::new().stats(CountMode::Words), IndexStats::default());
+    }
+
+    #[test]
+    fn index_from_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::write(root.join("todo.txt"), "buy milk").unwrap();
+        fs::write(root.join("todo.md"), "# todo").unwrap();
+        fs::write(root.join("image.png"), [0x89, b'P', b'N', b'G', 0, 0]).unwrap();
+        fs::write(root.join("sub/ideas.txt"), "").unwrap();
+        fs::write(root.join("sub/deeper/todo.txt"), "nested").unwrap();
+
+        let names = |index: &Index| -> Vec<String> {
+            index
+                .notes()
+                .iter()
+                .map(|note| note.name().to_string())
+                .collect()
+        };
+
+        let flat = Index::from_directory(root, false, false, &ScanIgnore::default()).unwrap();
+        assert_eq!(names(&flat), ["todo", "todo-2"]);
+        assert_eq!(
+            flat.get("todo").unwrap().path(),
+            &root.join("todo.md").canonicalize().unwrap()
+        );
+
+        let deep = Index::from_directory(root, true, false, &ScanIgnore::default()).unwrap();
+        assert_eq!(names(&deep), ["ideas", "todo", "todo-2", "todo-3"]);
+        assert_eq!(
+            deep.get("todo").unwrap().path(),
+            &root.join("sub/deeper/todo.txt").canonicalize().unwrap()
+        );
+
+        assert!(
+            Index::from_directory(&root.join("missing"), false, false, &ScanIgnore::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn scans_skip_ignored_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in &[
+            "note.txt",
+            ".hidden.txt",
+            "draft.bak",
+            "build/out.txt",
+            "sub/kept.txt",
+            "sub/secret.txt",
+            "sub/scratch.tmp",
+        ] {
+            fs::write(root.join(file), "words").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(root.join("sub").join(IGNORE_FILE_NAME), "secret.txt\n").unwrap();
+
+        let extra = tempfile::NamedTempFile::new().unwrap();
+        fs::write(extra.path(), "*.tmp\n").unwrap();
+
+        let ignore = ScanIgnore {
+            globs: vec!["*.bak".to_string()],
+            files: vec![extra.path().to_path_buf()],
+        };
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|path| path.strip_prefix(root).unwrap().display().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            names(plaintext_files(root, true, &ignore).unwrap()),
+            ["note.txt", "sub/kept.txt"]
+        );
+        assert_eq!(
+            names(plaintext_files(root, true, &ScanIgnore::default()).unwrap()),
+            ["draft.bak", "note.txt", "sub/kept.txt", "sub/scratch.tmp"]
+        );
+
+        let bad = ScanIgnore {
+            files: vec![root.join("missing")],
+            ..ScanIgnore::default()
+        };
+        assert!(plaintext_files(root, true, &bad).is_err());
+    }
+
+    #[test]
+    fn progress_is_reported() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "one").unwrap();
+        fs::write(dir.path().join("b.txt"), "two words").unwrap();
+
+        let mut steps = Vec::new();
+        let index = Index::from_directory_with(
+            dir.path(),
+            false,
+            false,
+            &ScanIgnore::default(),
+            |done, total| steps.push((done, total)),
+        )
+        .unwrap();
+        assert_eq!(steps, [(1, 2), (2, 2)]);
+
+        let mut steps = Vec::new();
+        let stats = index.stats_with(CountMode::Words, |done, total| steps.push((done, total)));
+        assert_eq!(steps, [(1, 2), (2, 2)]);
+        assert_eq!(stats.total_words, 3);
+    }
+
+    #[test]
+    fn merge_skips_known_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        fs::write(dir.path().join("b.txt"), "").unwrap();
+
+        let mut index = Index::new();
+        index.add("a", &dir.path().join("a.txt"), &None).unwrap();
+        index.add("b", &dir.path().join("a.txt"), &None).unwrap();
+
+        let scanned =
+            Index::from_directory(dir.path(), false, false, &ScanIgnore::default()).unwrap();
+        assert_eq!(index.merge(scanned), ["b-2"]);
+        assert_eq!(index.notes().len(), 3);
+    }
+
+    #[test]
+    fn valid_note() {
+        let file = NamedTempFile::new().unwrap();
+        let note = Note::new("note", &file.path().to_path_buf(), &Some(vec!["a".into()])).unwrap();
+
+        assert_eq!(note.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_notes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file.txt");
+        fs::write(&file, "").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let file = fs::canonicalize(file).unwrap();
+
+        let problems = |name: &str, path: PathBuf, tags: Option<Vec<&str>>| {
+            let note = Note {
+                name: name.to_string(),
+                path,
+                tags: tags.map(|tags| tags.into_iter().map(String::from).collect()),
+                ..Note::default()
+            };
+            note.validate().unwrap_err()
+        };
+
+        assert_eq!(problems(" ", file.clone(), None), [NoteProblem::EmptyName]);
+        assert_eq!(
+            problems("a", PathBuf::from("file.txt"), None),
+            [NoteProblem::RelativePath]
+        );
+        assert_eq!(
+            problems("a", dir.path().join("missing.txt"), None),
+            [NoteProblem::MissingFile]
+        );
+        assert_eq!(
+            problems("a", file.parent().unwrap().join("sub/../file.txt"), None),
+            [NoteProblem::NonCanonicalPath]
+        );
+        assert_eq!(
+            problems("a", file.clone(), Some(vec![])),
+            [NoteProblem::EmptyTagList]
+        );
+        assert_eq!(
+            problems("", file, Some(vec!["x", "", "x"])),
+            [
+                NoteProblem::EmptyName,
+                NoteProblem::EmptyTag,
+                NoteProblem::DuplicateTag("x".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn modified_since_and_apply() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let at = |secs| Some(UNIX_EPOCH + std::time::Duration::from_secs(secs));
+
+        let mut index = Index::new();
+        for (name, modified) in &[("old", at(100)), ("new", at(300)), ("legacy", None)] {
+            index.add(name, &path, &None).unwrap();
+            let mut note = index.notes.take(&Note::dummy(name)).unwrap();
+            note.modified = *modified;
+            index.notes.insert(note);
+        }
+
+        let since = at(200);
+        assert_eq!(
+            names(&index.find_by(|note| note.modified_since(since))),
+            ["new"]
+        );
+        assert_eq!(index.find_by(|note| note.modified_since(None)).len(), 3);
+
+        let delta = index.subset(|note| note.modified_since(since));
+        assert_eq!(delta.notes().len(), 1);
+
+        let mut other = Index::new();
+        other
+            .add("new", &path, &Some(vec!["stale".to_string()]))
+            .unwrap();
+        other.add("mine", &path, &None).unwrap();
+
+        assert_eq!(other.apply(delta), ["new"]);
+        assert_eq!(other.notes().len(), 2);
+        assert_eq!(other.get("new").unwrap().tags(), &None);
+        assert_eq!(other.get("new").unwrap().modified(), at(300));
+    }
+
+    /// Builds an `Index` with a few tagged notes for query tests.
+    fn query_fixture(file: &NamedTempFile) -> Index {
+        let path = file.path().to_path_buf();
+        let tag = |tags: &[&str]| Some(tags.iter().map(|t| t.to_string()).collect());
+
+        let mut index = Index::new();
+        index
+            .add("Work Log", &path, &tag(&["work", "daily"]))
+            .unwrap();
+        index.add("Groceries", &path, &tag(&["Todo"])).unwrap();
+        index.add("worksheet", &path, &None).unwrap();
+        index
+    }
+
+    fn names<'a>(notes: &[&'a Note]) -> Vec<&'a str> {
+        notes.iter().map(|note| note.name()).collect()
+    }
+
+    #[test]
+    fn find_by_predicate() {
+        let file = NamedTempFile::new().unwrap();
+        let index = query_fixture(&file);
+
+        assert_eq!(
+            names(&index.find_by(|_| true)),
+            ["Groceries", "Work Log", "worksheet"]
+        );
+        assert!(index.find_by(|_| false).is_empty());
+        assert_eq!(
+            names(&index.find_by(|note| note.tags().is_none())),
+            ["worksheet"]
+        );
+    }
+
+    #[test]
+    fn search_by_name_ignores_case() {
+        let file = NamedTempFile::new().unwrap();
+        let index = query_fixture(&file);
+
+        assert_eq!(
+            names(&index.search_by_name("WORK")),
+            ["Work Log", "worksheet"]
+        );
+        assert!(index.search_by_name("missing").is_empty());
+    }
+
+    #[test]
+    fn names_in_order() {
+        let file = NamedTempFile::new().unwrap();
+        let index = query_fixture(&file);
+
+        assert_eq!(
+            index.names().collect::<Vec<_>>(),
+            names(&index.find_by(|_| true))
+        );
+        assert_eq!(Index::new().names().count(), 0);
+    }
+
+    #[test]
+    fn search_by_tag_ignores_case() {
+        let file = NamedTempFile::new().unwrap();
+        let index = query_fixture(&file);
+
+        assert_eq!(names(&index.search_by_tag("todo")), ["Groceries"]);
+        assert_eq!(names(&index.search_by_tag("DAILY")), ["Work Log"]);
+        assert!(index.search_by_tag("work log").is_empty());
     }
 }
@@ -0,0 +1,6 @@
+//! Helpers shared by the unit tests.
+
+/// Returns `tags` as the tags of a `Note`.
+pub(crate) fn tags(tags: &[&str]) -> Option<Vec<String>> {
+    Some(tags.iter().map(|tag| tag.to_string()).collect())
+}
@@ -0,0 +1,96 @@
+//! Runs scrv and checks what it prints.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// Runs scrv with `args`, keeping its settings and index in `home`.
+fn scrv(home: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_scrv"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env_remove("SCRIVENER_INDEX_FORMAT")
+        .env_remove("SCRIVENER_NOTES_DIR")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+#[test]
+fn quiet_silences_success_messages() {
+    let home = TempDir::new().unwrap();
+    let note = home.path().join("note.txt");
+    let gone = home.path().join("gone.txt");
+    fs::write(&note, "").unwrap();
+    fs::write(&gone, "").unwrap();
+
+    let added = scrv(home.path(), &["add", "Note", note.to_str().unwrap()]);
+    assert!(added.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&added.stderr).contains("`Note`"));
+
+    let tagged = scrv(home.path(), &["tag", "Note", "work"]);
+    assert!(tagged.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&tagged.stderr).contains("is tagged work"));
+
+    scrv(home.path(), &["-q", "add", "Gone", gone.to_str().unwrap()]);
+    fs::remove_file(&gone).unwrap();
+
+    let exported = home.path().join("exported.toml");
+    let exported = exported.to_str().unwrap();
+
+    for args in &[
+        &["-q", "untag", "Note", "work"][..],
+        &["--quiet", "prune"],
+        &["config", "-q", "relative_time", "true"],
+        &["-q", "tag", "Note", "today"],
+        &["-q", "rename-tag", "today", "now"],
+        &["-q", "export", exported],
+        &["-q", "import", exported],
+        &["-q", "verify"],
+    ] {
+        let output = scrv(home.path(), args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        assert!(output.stderr.is_empty(), "{:?}", args);
+    }
+
+    let listed = scrv(home.path(), &["-q", "list"]);
+    assert!(String::from_utf8_lossy(&listed.stdout).contains("Note"));
+}
+
+#[test]
+fn status_messages_stay_out_of_stdout() {
+    let home = TempDir::new().unwrap();
+    let note = home.path().join("note.txt");
+    let gone = home.path().join("gone.txt");
+    fs::write(&note, "").unwrap();
+    fs::write(&gone, "").unwrap();
+
+    let listed = scrv(home.path(), &["list"]);
+    assert!(listed.stdout.is_empty());
+    assert!(!listed.stderr.is_empty());
+
+    scrv(home.path(), &["-q", "add", "Note", note.to_str().unwrap()]);
+
+    for args in &[
+        &["rename-tag", "missing", "other"][..],
+        &["duplicates"],
+        &["doctor"],
+        &["search", "nothing"],
+        &["prune"],
+    ] {
+        let output = scrv(home.path(), args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        assert!(!output.stderr.is_empty(), "{:?}", args);
+    }
+
+    scrv(home.path(), &["-q", "add", "Gone", gone.to_str().unwrap()]);
+    fs::remove_file(&gone).unwrap();
+
+    let output = scrv(home.path(), &["prune", "--dry-run"]);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Would prune 1 notes: Gone"));
+}